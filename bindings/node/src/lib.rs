@@ -0,0 +1,89 @@
+//! Node.js绑定
+//!
+//! ```js
+//! const { compress } = require('gif-compressor')
+//! const { data, sizeKb } = await compress(fs.readFileSync('in.gif'), { target: 256 })
+//! ```
+
+use gif_compressor::config::Config;
+use gif_compressor::options::CompressionOptions;
+use gif_compressor::compress_bytes_with_reporter;
+use gif_compressor::reporter::SilentReporter;
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use std::fs;
+use std::sync::Arc;
+
+/// 压缩结果
+#[napi(object)]
+pub struct CompressResult {
+    /// 压缩后的文件内容
+    pub data: Buffer,
+    /// 原始大小(KB)
+    pub original_size_kb: f64,
+    /// 压缩后大小(KB)
+    pub size_kb: f64,
+}
+
+/// 输入数据：内存中的GIF或文件路径
+enum Input {
+    Bytes(Vec<u8>),
+    Path(String),
+}
+
+/// 在libuv线程池中执行压缩，不阻塞事件循环
+pub struct CompressTask {
+    input: Input,
+    options: CompressionOptions,
+}
+
+impl Task for CompressTask {
+    type Output = (f64, Vec<u8>);
+    type JsValue = CompressResult;
+
+    fn compute(&mut self) -> Result<Self::Output> {
+        let data = match &mut self.input {
+            Input::Bytes(data) => std::mem::take(data),
+            Input::Path(path) => fs::read(path).map_err(|e| Error::from_reason(e.to_string()))?,
+        };
+        let original_size_kb = data.len() as f64 / 1024.0;
+        let output = compress_bytes_with_reporter(&data, &self.options, Arc::new(SilentReporter))
+            .map_err(|e| Error::from_reason(e.to_string()))?;
+        Ok((original_size_kb, output))
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> Result<Self::JsValue> {
+        let (original_size_kb, data) = output;
+        Ok(CompressResult {
+            size_kb: data.len() as f64 / 1024.0,
+            original_size_kb,
+            data: data.into(),
+        })
+    }
+}
+
+/// 压缩GIF，`input` 可以是Buffer或文件路径，`options` 的字段与配置文件相同
+#[napi(ts_return_type = "Promise<CompressResult>")]
+pub fn compress(
+    input: Either<Buffer, String>,
+    options: Option<serde_json::Value>,
+) -> Result<AsyncTask<CompressTask>> {
+    let mut compression_options = CompressionOptions::default();
+    if let Some(options) = options {
+        let config: Config = serde_json::from_value(options)
+            .map_err(|e| Error::new(Status::InvalidArg, format!("选项无效: {}", e)))?;
+        config
+            .apply(&mut compression_options)
+            .map_err(|e| Error::new(Status::InvalidArg, e.to_string()))?;
+    }
+
+    let input = match input {
+        Either::A(buffer) => Input::Bytes(buffer.to_vec()),
+        Either::B(path) => Input::Path(path),
+    };
+
+    Ok(AsyncTask::new(CompressTask {
+        input,
+        options: compression_options,
+    }))
+}
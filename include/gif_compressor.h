@@ -0,0 +1,49 @@
+/*
+ * gif_compressor C API
+ *
+ * 链接 libgif_compressor（cargo build --release 生成的 cdylib）。
+ * 选项以JSON字符串传入，字段与配置文件相同，例如：
+ *   {"target": 256, "max-width": 128, "preset": "discord-emoji"}
+ * 传 NULL 时使用默认选项。
+ */
+#ifndef GIF_COMPRESSOR_H
+#define GIF_COMPRESSOR_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define GIF_COMPRESS_OK 0
+#define GIF_COMPRESS_ERR_INVALID_ARGUMENT 1
+#define GIF_COMPRESS_ERR_INVALID_OPTIONS 2
+#define GIF_COMPRESS_ERR_FAILED 3
+#define GIF_COMPRESS_ERR_PANIC 4
+
+/* 压缩文件，返回状态码 */
+int gif_compress(const char *input_path, const char *output_path, const char *options_json);
+
+/* 压缩内存中的GIF数据，成功时 *output 需要用 gif_free_buffer 释放 */
+int gif_compress_buffer(const uint8_t *input, size_t input_len, const char *options_json,
+                        uint8_t **output, size_t *output_len);
+
+/* 释放 gif_compress_buffer 分配的缓冲区 */
+void gif_free_buffer(uint8_t *buffer, size_t len);
+
+/* 当前线程最近一次调用的错误信息，没有错误时返回 NULL */
+const char *gif_last_error(void);
+
+/*
+ * 当前线程最近一次调用的库错误码，没有错误或错误不是由压缩库产生时返回 0。
+ * 错误码是稳定的，例如 10 未找到gifsicle、20 输入文件不存在、
+ * 41 无法满足大小上限、60 已取消，完整列表见 GifError::code。
+ */
+int gif_last_error_code(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* GIF_COMPRESSOR_H */
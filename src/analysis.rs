@@ -0,0 +1,245 @@
+//! GIF分析与压缩结果估计
+//!
+//! 只解码不编码，用于 `--dry-run` 在真正压缩之前粗略估计各组参数能达到的大小。
+
+use crate::{filters, GifError};
+use crate::options::{Backend, CompressionOptions};
+use image::codecs::gif::GifDecoder;
+use image::{AnimationDecoder, ImageDecoder};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+/// 统计颜色时最多采样的像素数，避免大文件占用过多内存和时间
+const MAX_SAMPLED_PIXELS: usize = 4_000_000;
+
+/// 单帧的复杂度
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FrameStats {
+    /// 与上一帧相比发生变化的像素百分比，第一帧为100
+    pub changed_percent: f64,
+    /// 本帧颜色分布的香农熵(bit/像素)
+    pub entropy: f64,
+    /// 本帧采样到的不同颜色数
+    pub unique_colors: usize,
+}
+
+/// GIF的基本信息
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct GifAnalysis {
+    /// 文件大小(KB)
+    pub size_kb: f64,
+    pub width: u32,
+    pub height: u32,
+    pub frame_count: usize,
+    /// 平均帧率
+    pub fps: f64,
+    /// 采样到的不同颜色数
+    pub unique_colors: usize,
+    /// 颜色分布的香农熵(bit/像素)，越高越难压缩
+    pub palette_entropy: f64,
+    /// 所有帧共同的纯色背景，可以用 `--key-background` 转为透明
+    pub background: Option<[u8; 3]>,
+    /// 每帧的复杂度
+    pub frames: Vec<FrameStats>,
+}
+
+/// 一组参数的估计大小
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Estimate {
+    /// 每隔多少帧取1帧
+    pub skip: usize,
+    /// lossy级别，None表示不使用有损压缩
+    pub lossy: Option<u32>,
+    /// 颜色数，None表示保持原始颜色数
+    pub colors: Option<u16>,
+    /// 估计大小(KB)
+    pub size_kb: f64,
+}
+
+/// 分析内存中的GIF
+pub fn analyze(data: &[u8]) -> Result<GifAnalysis, GifError> {
+    let decoder = GifDecoder::new(Cursor::new(data))?;
+    let (width, height) = decoder.dimensions();
+    let frames = decoder.into_frames().collect_frames()?;
+    if frames.is_empty() {
+        return Err(GifError::NoFrames);
+    }
+
+    let mut total_ms = 0.0;
+    for frame in &frames {
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let delay_ms = numer as f64 / denom as f64;
+        // 浏览器会把小于20ms的帧延迟按100ms处理
+        total_ms += if delay_ms < 20.0 { 100.0 } else { delay_ms };
+    }
+
+    // 按固定步长采样像素统计颜色分布
+    let total_pixels = width as usize * height as usize * frames.len();
+    let step = total_pixels.div_ceil(MAX_SAMPLED_PIXELS).max(1);
+    let mut counts: HashMap<[u8; 4], u64> = HashMap::new();
+    let mut frame_stats = Vec::with_capacity(frames.len());
+    let mut previous: Option<&[u8]> = None;
+    for frame in &frames {
+        let mut frame_counts: HashMap<[u8; 4], u64> = HashMap::new();
+        for pixel in frame.buffer().pixels().step_by(step) {
+            // 透明像素不区分颜色
+            let key = if pixel[3] < 128 { [0; 4] } else { pixel.0 };
+            *frame_counts.entry(key).or_insert(0) += 1;
+        }
+        for (&color, &count) in &frame_counts {
+            *counts.entry(color).or_insert(0) += count;
+        }
+
+        let pixels = frame.buffer().as_raw();
+        let changed_percent = match previous {
+            Some(prev) => {
+                let changed = pixels
+                    .chunks_exact(4)
+                    .zip(prev.chunks_exact(4))
+                    .filter(|(a, b)| a != b)
+                    .count();
+                changed as f64 * 100.0 / (width as f64 * height as f64)
+            }
+            None => 100.0,
+        };
+        previous = Some(pixels);
+        frame_stats.push(FrameStats {
+            changed_percent,
+            entropy: entropy(&frame_counts),
+            unique_colors: frame_counts.len(),
+        });
+    }
+
+    Ok(GifAnalysis {
+        size_kb: data.len() as f64 / 1024.0,
+        width,
+        height,
+        frame_count: frames.len(),
+        fps: frames.len() as f64 * 1000.0 / total_ms,
+        unique_colors: counts.len(),
+        palette_entropy: entropy(&counts),
+        background: filters::detect_background(frames.iter().map(|frame| frame.buffer())),
+        frames: frame_stats,
+    })
+}
+
+/// 颜色分布的香农熵(bit/像素)
+fn entropy(counts: &HashMap<[u8; 4], u64>) -> f64 {
+    let total: u64 = counts.values().sum();
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+impl GifAnalysis {
+    /// 除第一帧外平均每帧变化的像素百分比
+    pub fn mean_changed_percent(&self) -> f64 {
+        if self.frames.len() < 2 {
+            return 0.0;
+        }
+        self.frames[1..].iter().map(|f| f.changed_percent).sum::<f64>() / (self.frames.len() - 1) as f64
+    }
+
+    /// 每帧熵的平均值
+    pub fn mean_frame_entropy(&self) -> f64 {
+        self.frames.iter().map(|f| f.entropy).sum::<f64>() / self.frames.len() as f64
+    }
+
+    /// 粗略的可压缩性评分(0-100)，越高越容易压小
+    ///
+    /// 帧间变化越少，gifsicle的帧间透明优化越有效；每帧颜色越简单，LZW编码越紧凑。
+    pub fn compressibility_score(&self) -> f64 {
+        let change = self.mean_changed_percent() / 100.0;
+        let entropy = self.mean_frame_entropy().min(8.0) / 8.0;
+        (100.0 * (1.0 - 0.6 * change - 0.4 * entropy)).clamp(0.0, 100.0)
+    }
+
+    /// 单帧使用的最多颜色数
+    pub fn max_frame_colors(&self) -> usize {
+        self.frames.iter().map(|f| f.unique_colors).max().unwrap_or(0)
+    }
+
+    /// 限制为指定颜色数时估计能节省的比例(0-1)
+    ///
+    /// 每帧本来就用不到这么多颜色时不会有任何节省。
+    pub fn color_savings(&self, colors: u16, options: &CompressionOptions) -> f64 {
+        if colors as usize >= self.max_frame_colors() {
+            return 0.0;
+        }
+        1.0 - self.estimate(1, None, Some(colors), options) / self.estimate(1, None, None, options)
+    }
+
+    /// 按经验模型估计压缩后的大小，只用于判断目标是否现实，误差可能在30%以上
+    pub fn estimate(&self, skip: usize, lossy: Option<u32>, colors: Option<u16>, options: &CompressionOptions) -> f64 {
+        // 抽帧后相邻帧差异变大，大小下降得比帧数慢
+        let frame_factor = (1.0 / skip as f64).powf(0.85);
+
+        // 颜色越杂乱，lossy能省下的越多
+        let entropy_ratio = self.palette_entropy.min(8.0) / 8.0;
+        let lossy_factor = lossy.map_or(1.0, |level| {
+            1.0 - 0.45 * entropy_ratio * (1.0 - (-(level as f64) / 80.0).exp())
+        });
+
+        // 每帧最多256色，熵超过8 bit时按8 bit计算
+        let frame_entropy = self.palette_entropy.min(8.0);
+        let color_bits = colors.map_or(8.0, |c| (c.clamp(2, 256) as f64).log2());
+        let color_factor = if color_bits < frame_entropy {
+            0.5 + 0.5 * color_bits / frame_entropy
+        } else {
+            1.0
+        };
+
+        let scale_w = options.max_width.map_or(1.0, |max| max as f64 / self.width as f64);
+        let scale_h = options.max_height.map_or(1.0, |max| max as f64 / self.height as f64);
+        let scale = scale_w.min(scale_h).min(1.0);
+
+        self.size_kb * frame_factor * lossy_factor * color_factor * scale * scale
+    }
+
+    /// 估计几组常用参数的大小，按估计大小从大到小排列
+    pub fn estimates(&self, options: &CompressionOptions) -> Vec<Estimate> {
+        let max_skip = options.max_drop_skip(self.frame_count);
+        let min_skip = options.min_skip(self.fps).min(max_skip);
+        let skips: Vec<usize> = [1, 2, 3, 4].iter().map(|&skip| skip.clamp(min_skip, max_skip)).collect();
+
+        let mut combos = Vec::new();
+        for (i, &skip) in skips.iter().enumerate() {
+            if i > 0 && skip == skips[i - 1] {
+                continue;
+            }
+            match options.backend {
+                // 纯Rust后端没有lossy，通过减少颜色数压缩
+                Backend::Native => {
+                    for colors in [options.colors, Some(128), Some(64)] {
+                        combos.push((skip, None, colors));
+                    }
+                }
+                Backend::Gifsicle => {
+                    for lossy in [None, Some(60), Some(120), Some(200)] {
+                        combos.push((skip, lossy, options.colors));
+                    }
+                }
+            }
+        }
+
+        let mut estimates: Vec<Estimate> = combos
+            .into_iter()
+            .map(|(skip, lossy, colors)| Estimate {
+                skip,
+                lossy,
+                colors,
+                size_kb: self.estimate(skip, lossy, colors, options),
+            })
+            .collect();
+        estimates.sort_by(|a, b| b.size_kb.total_cmp(&a.size_kb));
+        estimates
+    }
+}
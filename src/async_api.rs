@@ -0,0 +1,92 @@
+//! 基于tokio的异步接口
+//!
+//! 压缩在 `spawn_blocking` 线程中执行，不会阻塞异步工作线程；
+//! 进度事件通过通道实时发送，日志不输出。两个函数都必须在tokio运行时中调用。
+//!
+//! 返回的future在完成前被丢弃时（例如被 `tokio::time::timeout` 超时取消）会触发
+//! `options.cancel`，后台的压缩任务随之停止并清理临时文件。
+
+use crate::cancel::CancellationToken;
+use crate::options::CompressionOptions;
+use crate::progress::ProgressEvent;
+use crate::report::CompressionReport;
+use crate::reporter::{Reporter, SharedReporter};
+use crate::{compress_bytes_with_reporter, compress_with_reporter, GifError};
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task;
+
+/// 把进度事件转发到通道，忽略日志
+struct ChannelReporter(UnboundedSender<ProgressEvent>);
+
+impl Reporter for ChannelReporter {
+    fn log(&self, _message: &str) {}
+
+    fn event(&self, event: &ProgressEvent) {
+        // 调用方不再关心进度时忽略发送失败
+        let _ = self.0.send(event.clone());
+    }
+}
+
+fn channel_reporter() -> (SharedReporter, UnboundedReceiver<ProgressEvent>) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (Arc::new(ChannelReporter(tx)), rx)
+}
+
+/// future被丢弃时取消压缩
+struct CancelOnDrop(Option<CancellationToken>);
+
+impl CancelOnDrop {
+    /// 任务已经完成，不再需要取消
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        if let Some(token) = self.0.take() {
+            token.cancel();
+        }
+    }
+}
+
+/// 异步压缩文件，返回压缩报告的future和进度事件接收端
+pub fn compress_async(
+    input_path: impl Into<PathBuf>,
+    output_path: impl Into<PathBuf>,
+    options: CompressionOptions,
+) -> (impl Future<Output = Result<CompressionReport, GifError>>, UnboundedReceiver<ProgressEvent>) {
+    let (input_path, output_path) = (input_path.into(), output_path.into());
+    let (reporter, rx) = channel_reporter();
+    let guard = CancelOnDrop(Some(options.cancel.clone()));
+    let handle = task::spawn_blocking(move || {
+        compress_with_reporter(input_path, output_path, &options, reporter)
+    });
+
+    let result = async move {
+        let result = handle.await;
+        guard.disarm();
+        result.map_err(|e| GifError::TaskFailed { message: e.to_string() })?
+    };
+    (result, rx)
+}
+
+/// 异步压缩内存中的GIF数据，返回压缩结果的future和进度事件接收端
+pub fn compress_bytes_async(
+    data: Vec<u8>,
+    options: CompressionOptions,
+) -> (impl Future<Output = Result<Vec<u8>, GifError>>, UnboundedReceiver<ProgressEvent>) {
+    let (reporter, rx) = channel_reporter();
+    let guard = CancelOnDrop(Some(options.cancel.clone()));
+    let handle = task::spawn_blocking(move || compress_bytes_with_reporter(&data, &options, reporter));
+
+    let result = async move {
+        let result = handle.await;
+        guard.disarm();
+        result.map_err(|e| GifError::TaskFailed { message: e.to_string() })?
+    };
+    (result, rx)
+}
@@ -0,0 +1,179 @@
+//! 批量输入列表
+//!
+//! `@列表文件` 每行一个输入路径，可以直接使用 `find` 的输出或资源清单。路径后可以用制表符
+//! 分隔只对这一行生效的参数，例如 `icons/spinner.gif<TAB>--target 100 --colors 64`；
+//! 空行和以 `#` 开头的行被忽略。
+//!
+//! 以NUL分隔时（`find -print0` 的输出）每一项都只是路径，可以包含空格、制表符和换行。
+
+use crate::options::OutputFormat;
+use crate::{GifError, WithPath};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// 列表中的一个输入
+#[derive(Debug, Clone)]
+pub struct BatchEntry {
+    pub input: PathBuf,
+    /// 只对这个输入生效的命令行参数
+    pub overrides: Vec<String>,
+    /// 所在的行号，从1开始
+    pub line: usize,
+}
+
+/// 读取列表文件，`-` 表示标准输入
+pub fn read_list<P: AsRef<Path>>(path: P, null_separated: bool) -> Result<Vec<BatchEntry>, GifError> {
+    let path = path.as_ref();
+    let data = if path == Path::new("-") {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        data
+    } else {
+        fs::read(path).with_path(path)?
+    };
+    if null_separated {
+        Ok(parse_null_separated(&data))
+    } else {
+        Ok(parse_list(&data))
+    }
+}
+
+/// 解析列表内容，路径两端的空格会保留
+///
+/// 路径保留原始字节，与以NUL分隔时相同，不是UTF-8的文件名也能找到。
+pub fn parse_list(data: &[u8]) -> Vec<BatchEntry> {
+    data.split(|&byte| byte == b'\n')
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.iter().all(u8::is_ascii_whitespace) || line.starts_with(b"#") {
+                return None;
+            }
+            let (path, overrides) = match line.iter().position(|&byte| byte == b'\t') {
+                Some(tab) => (&line[..tab], &line[tab + 1..]),
+                None => (line, &[][..]),
+            };
+            Some(BatchEntry {
+                input: path_from_bytes(path),
+                overrides: String::from_utf8_lossy(overrides).split_whitespace().map(str::to_string).collect(),
+                line: i + 1,
+            })
+        })
+        .collect()
+}
+
+/// 解析以NUL分隔的路径，`line` 为第几项
+pub fn parse_null_separated(data: &[u8]) -> Vec<BatchEntry> {
+    data.split(|&byte| byte == 0)
+        .filter(|path| !path.is_empty())
+        .enumerate()
+        .map(|(i, path)| BatchEntry { input: path_from_bytes(path), overrides: Vec::new(), line: i + 1 })
+        .collect()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+/// 非Unix平台的路径不是任意字节，按UTF-8解码
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// 输入在输出目录中对应的路径
+///
+/// 沿用输入的文件名并按输出格式替换扩展名；不同目录下的同名文件依次加上 `_2`、`_3` 等后缀，
+/// `used` 记录已经分配的路径。
+pub fn output_path(dir: &Path, input: &Path, format: OutputFormat, used: &mut HashSet<PathBuf>) -> PathBuf {
+    let stem = input.file_stem().unwrap_or(OsStr::new("output"));
+    let extension = match format {
+        OutputFormat::Gif => "gif",
+        OutputFormat::Webm => "webm",
+    };
+    // 按原始字节拼接文件名，不是UTF-8的文件名保持不变
+    let name = |suffix: String| {
+        let mut name = stem.to_os_string();
+        name.push(suffix);
+        dir.join(name)
+    };
+    let mut path = name(format!(".{}", extension));
+    let mut index = 2;
+    while used.contains(&path) {
+        path = name(format!("_{}.{}", index, extension));
+        index += 1;
+    }
+    used.insert(path.clone());
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        let entries = parse_list("# 注释\n\na.gif\n   \n #不是注释.gif\nc.gif\r\n".as_bytes());
+        let inputs: Vec<_> = entries.iter().map(|entry| (entry.input.clone(), entry.line)).collect();
+        assert_eq!(
+            inputs,
+            [(PathBuf::from("a.gif"), 3), (PathBuf::from(" #不是注释.gif"), 5), (PathBuf::from("c.gif"), 6)]
+        );
+    }
+
+    #[test]
+    fn parses_overrides_after_tab() {
+        let entries = parse_list(b" my icon.gif\t--target 100  --colors 64\nplain.gif\t\n");
+        assert_eq!(entries[0].input, PathBuf::from(" my icon.gif"));
+        assert_eq!(entries[0].overrides, ["--target", "100", "--colors", "64"]);
+        assert_eq!(entries[1].input, PathBuf::from("plain.gif"));
+        assert!(entries[1].overrides.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let entries = parse_list(b"caf\xe9.gif\t--colors 64\n");
+        assert_eq!(entries[0].input.as_os_str().as_bytes(), b"caf\xe9.gif");
+        assert_eq!(entries[0].overrides, ["--colors", "64"]);
+        let output = output_path(Path::new("out"), &entries[0].input, OutputFormat::Gif, &mut HashSet::new());
+        assert_eq!(output.as_os_str().as_bytes(), b"out/caf\xe9.gif");
+    }
+
+    #[test]
+    fn parses_null_separated_paths() {
+        let entries = parse_null_separated(b"a b.gif\0\0line\nbreak\tx.gif\0");
+        let inputs: Vec<_> = entries.iter().map(|entry| (entry.input.clone(), entry.line)).collect();
+        assert_eq!(inputs, [(PathBuf::from("a b.gif"), 1), (PathBuf::from("line\nbreak\tx.gif"), 2)]);
+        assert!(entries.iter().all(|entry| entry.overrides.is_empty()));
+    }
+
+    #[test]
+    fn reports_missing_list_with_path() {
+        let path = Path::new("/nonexistent/list.txt");
+        match read_list(path, false) {
+            Err(GifError::File { path: error_path, .. }) => assert_eq!(error_path, path),
+            other => panic!("应当返回文件错误: {:?}", other.map(|entries| entries.len())),
+        }
+    }
+
+    #[test]
+    fn numbers_duplicate_output_names() {
+        let mut used = HashSet::new();
+        let dir = Path::new("out");
+        let paths = [
+            output_path(dir, Path::new("a/x.gif"), OutputFormat::Gif, &mut used),
+            output_path(dir, Path::new("b/x.gif"), OutputFormat::Gif, &mut used),
+            output_path(dir, Path::new("c/x.gif"), OutputFormat::Gif, &mut used),
+            output_path(dir, Path::new("x.gif"), OutputFormat::Webm, &mut used),
+        ];
+        assert_eq!(paths, [dir.join("x.gif"), dir.join("x_2.gif"), dir.join("x_3.gif"), dir.join("x.webm")]);
+    }
+}
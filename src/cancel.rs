@@ -0,0 +1,146 @@
+//! 取消正在进行的压缩
+
+use crate::GifError;
+use std::io::Read;
+use std::process::{Command, Output, Stdio};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// 轮询子进程状态的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// 取消令牌，克隆后共享同一个状态
+///
+/// 通过 `CompressionOptions::cancel` 传入，触发后压缩会尽快停止搜索、
+/// 终止正在运行的子进程、清理临时文件并返回 `GifError::Cancelled`。
+///
+/// [`expire`](Self::expire) 是较温和的结束方式：同样停止搜索并终止子进程，
+/// 但输出目前为止最好的结果，还没有任何结果时返回 `GifError::TimedOut`。
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    expired: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 请求取消
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// 是否已请求取消
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// 时间用完，停止搜索并使用目前为止最好的结果
+    pub fn expire(&self) {
+        self.expired.store(true, Ordering::SeqCst);
+    }
+
+    /// 是否已经时间用完
+    pub fn is_expired(&self) -> bool {
+        self.expired.load(Ordering::SeqCst)
+    }
+
+    /// 已请求取消时返回错误
+    pub(crate) fn check(&self) -> Result<(), GifError> {
+        if self.is_cancelled() {
+            return Err(GifError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// 子进程被令牌终止时的错误，只是时间用完时为 `GifError::TimedOut`
+    pub(crate) fn stop_reason(&self) -> GifError {
+        if self.is_expired() && !self.is_cancelled() {
+            GifError::TimedOut
+        } else {
+            GifError::Cancelled
+        }
+    }
+
+    /// 搜索结束时没有任何结果的错误，时间用完时为 `GifError::TimedOut`
+    pub(crate) fn no_results(&self) -> GifError {
+        if self.is_expired() {
+            GifError::TimedOut
+        } else {
+            GifError::NoValidResults
+        }
+    }
+}
+
+/// 运行外部命令并收集输出，取消时终止子进程
+pub(crate) fn run_command(command: &mut Command, token: &CancellationToken) -> Result<Output, GifError> {
+    run_command_until(command, token, || false)?.ok_or(GifError::Cancelled)
+}
+
+/// 运行外部命令并收集输出，取消或 `stop` 返回true时终止子进程
+///
+/// 用于结果已经不再需要的候选，例如其他线程已经达到目标大小。被 `stop` 终止时返回None，
+/// 与用户取消区分开；时间用完时返回 `GifError::TimedOut`，调用方按一次失败的尝试处理。
+pub(crate) fn run_command_until(
+    command: &mut Command,
+    token: &CancellationToken,
+    stop: impl Fn() -> bool,
+) -> Result<Option<Output>, GifError> {
+    token.check()?;
+    if token.is_expired() {
+        return Err(GifError::TimedOut);
+    }
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // 在后台读取输出，避免管道写满导致子进程阻塞
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if token.is_cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(GifError::Cancelled);
+        }
+        if token.is_expired() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(GifError::TimedOut);
+        }
+        if stop() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+
+    Ok(Some(Output {
+        status,
+        stdout: collect(stdout),
+        stderr: collect(stderr),
+    }))
+}
+
+fn read_in_background(mut pipe: impl Read + Send + 'static) -> JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        let _ = pipe.read_to_end(&mut buffer);
+        buffer
+    })
+}
+
+fn collect(handle: Option<JoinHandle<Vec<u8>>>) -> Vec<u8> {
+    handle.and_then(|h| h.join().ok()).unwrap_or_default()
+}
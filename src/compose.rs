@@ -0,0 +1,206 @@
+//! 叠加两个GIF动画
+//!
+//! 把一个GIF缩放后叠加到另一个GIF上，输出帧的时间轴是两者帧切换时刻的并集，
+//! 画布和总时长以底层GIF为准。也可以按画中画布局把叠加层缩小后贴在底层的角落。
+
+use crate::GifError;
+use crate::native::{self, DecodedFrame};
+use crate::options::CompressionOptions;
+use image::imageops::{self, FilterType};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// 叠加层与底层时长不同时的对齐方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LoopAlign {
+    /// 叠加层循环播放直到底层结束
+    Loop,
+    /// 拉伸或压缩叠加层的时间轴，与底层同时开始和结束
+    Stretch,
+    /// 叠加层只播放一次，之后停在最后一帧
+    Once,
+}
+
+impl FromStr for LoopAlign {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "loop" => Ok(LoopAlign::Loop),
+            "stretch" => Ok(LoopAlign::Stretch),
+            "once" => Ok(LoopAlign::Once),
+            _ => Err(format!("未知的对齐方式: {}", s)),
+        }
+    }
+}
+
+/// 画中画小窗所在的角落
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl FromStr for Corner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "top-left" => Ok(Corner::TopLeft),
+            "top-right" => Ok(Corner::TopRight),
+            "bottom-left" => Ok(Corner::BottomLeft),
+            "bottom-right" => Ok(Corner::BottomRight),
+            _ => Err(format!("未知的角落: {}", s)),
+        }
+    }
+}
+
+/// 画中画布局，小窗的位置和大小按两者的实际尺寸计算
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Pip {
+    pub corner: Corner,
+    /// 小窗最多占底层宽度和高度的比例
+    pub size: f64,
+    /// 小窗与画布边缘的距离(像素)
+    pub margin: u32,
+}
+
+impl Default for Pip {
+    fn default() -> Self {
+        Self { corner: Corner::BottomRight, size: 0.25, margin: 8 }
+    }
+}
+
+impl Pip {
+    /// 计算小窗的左上角坐标和缩放比例
+    fn layout(&self, base: (u32, u32), overlay: (u32, u32)) -> (i64, i64, f64) {
+        let scale = (self.size * base.0 as f64 / overlay.0 as f64)
+            .min(self.size * base.1 as f64 / overlay.1 as f64);
+        let width = ((overlay.0 as f64 * scale).round() as i64).max(1);
+        let height = ((overlay.1 as f64 * scale).round() as i64).max(1);
+        let margin = self.margin as i64;
+
+        let x = match self.corner {
+            Corner::TopLeft | Corner::BottomLeft => margin,
+            Corner::TopRight | Corner::BottomRight => base.0 as i64 - width - margin,
+        };
+        let y = match self.corner {
+            Corner::TopLeft | Corner::TopRight => margin,
+            Corner::BottomLeft | Corner::BottomRight => base.1 as i64 - height - margin,
+        };
+        (x, y, scale)
+    }
+}
+
+/// 叠加层的位置、缩放和时间对齐
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Placement {
+    /// 叠加层左上角相对底层的横坐标，可以为负或超出画布，超出部分被裁掉
+    pub x: i64,
+    /// 叠加层左上角相对底层的纵坐标
+    pub y: i64,
+    /// 叠加层的缩放比例
+    pub scale: f64,
+    pub align: LoopAlign,
+    /// 设置时按画中画布局放置叠加层，忽略 `x`、`y` 和 `scale`
+    pub pip: Option<Pip>,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self { x: 0, y: 0, scale: 1.0, align: LoopAlign::Loop, pip: None }
+    }
+}
+
+/// 浏览器会把小于2的帧延迟按10处理
+fn effective_delay(delay: u16) -> u64 {
+    if delay < 2 { 10 } else { delay as u64 }
+}
+
+/// 每帧的开始时刻(1/100秒)和总时长
+fn timeline(frames: &[DecodedFrame]) -> (Vec<u64>, u64) {
+    let mut starts = Vec::with_capacity(frames.len());
+    let mut time = 0;
+    for frame in frames {
+        starts.push(time);
+        time += effective_delay(frame.delay);
+    }
+    (starts, time)
+}
+
+/// 某一时刻正在显示的帧
+fn frame_at(starts: &[u64], time: u64) -> usize {
+    starts.partition_point(|&start| start <= time).saturating_sub(1)
+}
+
+/// 叠加两个内存中的GIF，返回未压缩的合成结果
+pub fn compose(
+    base: &[u8],
+    overlay: &[u8],
+    placement: &Placement,
+    options: &CompressionOptions,
+) -> Result<Vec<u8>, GifError> {
+    let base = native::decode_frames(base)?;
+    let mut overlay = native::decode_frames(overlay)?;
+
+    let (x, y, scale) = match placement.pip {
+        Some(pip) => pip.layout(base[0].image.dimensions(), overlay[0].image.dimensions()),
+        None => (placement.x, placement.y, placement.scale),
+    };
+
+    if scale > 0.0 && scale != 1.0 {
+        let (width, height) = overlay[0].image.dimensions();
+        let new_width = ((width as f64 * scale).round() as u32).max(1);
+        let new_height = ((height as f64 * scale).round() as u32).max(1);
+        for frame in &mut overlay {
+            frame.image = imageops::resize(&frame.image, new_width, new_height, FilterType::CatmullRom);
+        }
+    }
+
+    let (base_starts, base_duration) = timeline(&base);
+    let (overlay_starts, overlay_duration) = timeline(&overlay);
+
+    // 叠加层时间与底层时间的换算
+    let to_base = |time: u64| match placement.align {
+        LoopAlign::Stretch => time * base_duration / overlay_duration,
+        _ => time,
+    };
+    let to_overlay = |time: u64| match placement.align {
+        LoopAlign::Loop => time % overlay_duration,
+        LoopAlign::Stretch => time * overlay_duration / base_duration,
+        LoopAlign::Once => time.min(overlay_duration - 1),
+    };
+
+    // 两者任意一方切换帧时都输出一帧
+    let mut cuts = base_starts.clone();
+    let repeats = match placement.align {
+        LoopAlign::Loop => base_duration.div_ceil(overlay_duration),
+        _ => 1,
+    };
+    for repeat in 0..repeats {
+        cuts.extend(overlay_starts.iter().map(|&start| to_base(start + repeat * overlay_duration)));
+    }
+    cuts.retain(|&time| time < base_duration);
+    cuts.sort_unstable();
+    cuts.dedup();
+
+    let frames: Vec<DecodedFrame> = cuts
+        .iter()
+        .enumerate()
+        .map(|(i, &time)| {
+            let end = cuts.get(i + 1).copied().unwrap_or(base_duration);
+            let mut image = base[frame_at(&base_starts, time)].image.clone();
+            let layer = &overlay[frame_at(&overlay_starts, to_overlay(time))].image;
+            imageops::overlay(&mut image, layer, x, y);
+            DecodedFrame { image, delay: (end - time).min(u16::MAX as u64) as u16 }
+        })
+        .collect();
+
+    native::encode_exact(&frames, options)
+}
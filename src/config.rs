@@ -0,0 +1,595 @@
+use crate::{GifError, WithPath};
+use crate::options::{Backend, ColorSpace, CompressionOptions, Denoise, DropMode, HexColor, Optimizer, OutputFormat, Percent, Prefer, Profile, Quantizer, Region, SAMPLE_FAC_RANGE};
+use crate::paths;
+use crate::preset;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// 项目目录下的配置文件名
+pub const PROJECT_CONFIG_FILE: &str = "gif_compressor.toml";
+
+/// 环境变量前缀，例如 `GIF_COMPRESSOR_TARGET`
+const ENV_PREFIX: &str = "GIF_COMPRESSOR_";
+
+/// 配置文件内容，所有字段均可省略
+///
+/// 先读取用户配置（Linux下为 `~/.config/gif_compressor/config.toml`），
+/// 再读取当前目录下的 `gif_compressor.toml`，后者覆盖前者；
+/// 最后叠加 `GIF_COMPRESSOR_*` 环境变量，环境变量中的预设也在配置文件之后应用。
+///
+/// `[presets.<名称>]` 表定义用户预设，字段与顶层相同，可以通过
+/// `preset` 字段继承一个内置预设，也可以通过 `profile` 字段指定内容类型。
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub target: Option<f64>,
+    pub min_frames: Option<u32>,
+    pub threads: Option<usize>,
+    pub lossy_levels: Option<Vec<u32>>,
+    pub backend: Option<Backend>,
+    pub temp_dir: Option<PathBuf>,
+    pub preset: Option<String>,
+    pub profile: Option<Profile>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub sharpen: Option<u8>,
+    pub max_fps: Option<f64>,
+    pub colors: Option<u16>,
+    pub strict: Option<bool>,
+    pub format: Option<OutputFormat>,
+    pub loop_count: Option<u16>,
+    pub collapse_static: Option<bool>,
+    pub lossless: Option<bool>,
+    pub no_lossy: Option<bool>,
+    pub keep_all_frames: Option<bool>,
+    pub prefer: Option<Prefer>,
+    pub max_drop: Option<Percent>,
+    pub optimizer: Option<Optimizer>,
+    pub optimizer_budget: Option<u64>,
+    pub autocrop: Option<bool>,
+    pub denoise: Option<Denoise>,
+    pub key_background: Option<bool>,
+    pub transparent_color: Option<HexColor>,
+    pub tolerance: Option<u8>,
+    pub freeze_static: Option<bool>,
+    pub dedupe_frames: Option<bool>,
+    pub protect_region: Option<Region>,
+    pub detect_text: Option<bool>,
+    pub drop_mode: Option<DropMode>,
+    pub quantizer: Option<Quantizer>,
+    pub sample_fac: Option<u32>,
+    pub color_space: Option<ColorSpace>,
+    pub deband: Option<bool>,
+    #[serde(default)]
+    pub presets: BTreeMap<String, Config>,
+    /// 环境变量层，在配置文件之后单独应用
+    #[serde(skip)]
+    env: Option<Box<Config>>,
+}
+
+impl Config {
+    /// 加载用户配置、项目配置和环境变量
+    pub fn load() -> Result<Self, GifError> {
+        let mut config = Config::default();
+
+        if let Some(path) = paths::user_config_file()
+            && let Some(user) = Self::from_file(&path)?
+        {
+            config = config.merge(user);
+        }
+
+        if let Some(project) = Self::from_file(Path::new(PROJECT_CONFIG_FILE))? {
+            config = config.merge(project);
+        }
+
+        config.env = Some(Box::new(Self::from_env()?));
+        Ok(config)
+    }
+
+    /// 读取 `GIF_COMPRESSOR_*` 环境变量
+    fn from_env() -> Result<Self, GifError> {
+        Ok(Config {
+            target: env_value("TARGET")?,
+            min_frames: env_value("MIN_FRAMES")?,
+            threads: env_value("THREADS")?,
+            lossy_levels: env_list("LOSSY_LEVELS")?,
+            backend: env_value("BACKEND")?,
+            temp_dir: env_string("TEMP_DIR").map(PathBuf::from),
+            preset: env_string("PRESET"),
+            profile: env_value("PROFILE")?,
+            max_width: env_value("MAX_WIDTH")?,
+            max_height: env_value("MAX_HEIGHT")?,
+            sharpen: env_value("SHARPEN")?,
+            max_fps: env_value("MAX_FPS")?,
+            colors: env_value("COLORS")?,
+            strict: env_bool("STRICT")?,
+            format: env_value("FORMAT")?,
+            loop_count: env_value("LOOP_COUNT")?,
+            collapse_static: env_bool("COLLAPSE_STATIC")?,
+            lossless: env_bool("LOSSLESS")?,
+            no_lossy: env_bool("NO_LOSSY")?,
+            keep_all_frames: env_bool("KEEP_ALL_FRAMES")?,
+            prefer: env_value("PREFER")?,
+            max_drop: env_value("MAX_DROP")?,
+            optimizer: env_value("OPTIMIZER")?,
+            optimizer_budget: env_value("OPTIMIZER_BUDGET")?,
+            autocrop: env_bool("AUTOCROP")?,
+            denoise: env_value("DENOISE")?,
+            key_background: env_bool("KEY_BACKGROUND")?,
+            transparent_color: env_value("TRANSPARENT_COLOR")?,
+            tolerance: env_value("TOLERANCE")?,
+            freeze_static: env_bool("FREEZE_STATIC")?,
+            dedupe_frames: env_bool("DEDUPE_FRAMES")?,
+            protect_region: env_value("PROTECT_REGION")?,
+            detect_text: env_bool("DETECT_TEXT")?,
+            drop_mode: env_value("DROP_MODE")?,
+            quantizer: env_value("QUANTIZER")?,
+            sample_fac: env_value("SAMPLE_FAC")?
+                .map(|value| check_sample_fac(value).map_err(|e| invalid_env("SAMPLE_FAC", &value.to_string(), e)))
+                .transpose()?,
+            color_space: env_value("COLOR_SPACE")?,
+            deband: env_bool("DEBAND")?,
+            presets: BTreeMap::new(),
+            env: None,
+        })
+    }
+
+    /// 读取单个配置文件，文件不存在时返回None
+    fn from_file(path: &Path) -> Result<Option<Self>, GifError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(path).with_path(path)?;
+        let config: Config = toml::from_str(&content)
+            .map_err(|e| GifError::ConfigParse { path: path.to_path_buf(), message: e.to_string() })?;
+        config.validate().map_err(|message| GifError::ConfigParse { path: path.to_path_buf(), message })?;
+        Ok(Some(config))
+    }
+
+    /// 检查字段的取值范围，用户预设中的字段也一并检查
+    fn validate(&self) -> Result<(), String> {
+        if let Some(sample_fac) = self.sample_fac {
+            check_sample_fac(sample_fac).map_err(|e| format!("sample-fac {}: {}", e, sample_fac))?;
+        }
+        for (name, preset) in &self.presets {
+            preset.validate().map_err(|e| format!("presets.{}: {}", name, e))?;
+        }
+        Ok(())
+    }
+
+    /// 合并配置，other中设置的字段和同名预设优先
+    fn merge(self, other: Config) -> Config {
+        let mut presets = self.presets;
+        presets.extend(other.presets);
+        
+        Config {
+            target: other.target.or(self.target),
+            min_frames: other.min_frames.or(self.min_frames),
+            threads: other.threads.or(self.threads),
+            lossy_levels: other.lossy_levels.or(self.lossy_levels),
+            backend: other.backend.or(self.backend),
+            temp_dir: other.temp_dir.or(self.temp_dir),
+            preset: other.preset.or(self.preset),
+            profile: other.profile.or(self.profile),
+            max_width: other.max_width.or(self.max_width),
+            max_height: other.max_height.or(self.max_height),
+            sharpen: other.sharpen.or(self.sharpen),
+            max_fps: other.max_fps.or(self.max_fps),
+            colors: other.colors.or(self.colors),
+            strict: other.strict.or(self.strict),
+            format: other.format.or(self.format),
+            loop_count: other.loop_count.or(self.loop_count),
+            collapse_static: other.collapse_static.or(self.collapse_static),
+            lossless: other.lossless.or(self.lossless),
+            no_lossy: other.no_lossy.or(self.no_lossy),
+            keep_all_frames: other.keep_all_frames.or(self.keep_all_frames),
+            prefer: other.prefer.or(self.prefer),
+            max_drop: other.max_drop.or(self.max_drop),
+            optimizer: other.optimizer.or(self.optimizer),
+            optimizer_budget: other.optimizer_budget.or(self.optimizer_budget),
+            autocrop: other.autocrop.or(self.autocrop),
+            denoise: other.denoise.or(self.denoise),
+            key_background: other.key_background.or(self.key_background),
+            transparent_color: other.transparent_color.or(self.transparent_color),
+            tolerance: other.tolerance.or(self.tolerance),
+            freeze_static: other.freeze_static.or(self.freeze_static),
+            dedupe_frames: other.dedupe_frames.or(self.dedupe_frames),
+            protect_region: other.protect_region.or(self.protect_region),
+            detect_text: other.detect_text.or(self.detect_text),
+            drop_mode: other.drop_mode.or(self.drop_mode),
+            quantizer: other.quantizer.or(self.quantizer),
+            sample_fac: other.sample_fac.or(self.sample_fac),
+            color_space: other.color_space.or(self.color_space),
+            deband: other.deband.or(self.deband),
+            presets,
+            env: other.env.or(self.env),
+        }
+    }
+
+    /// 将配置应用到压缩选项上
+    ///
+    /// 先应用配置文件，再应用环境变量。每一层中选择的预设最先应用，
+    /// 然后是内容类型，其余字段可以覆盖两者中的值。
+    pub fn apply(&self, options: &mut CompressionOptions) -> Result<(), GifError> {
+        self.apply_layer(self, options)?;
+        if let Some(env) = &self.env {
+            self.apply_layer(env, options)?;
+        }
+        Ok(())
+    }
+
+    /// 应用一层配置，预设名称在当前配置的用户预设中查找
+    fn apply_layer(&self, layer: &Config, options: &mut CompressionOptions) -> Result<(), GifError> {
+        if let Some(name) = &layer.preset {
+            self.apply_preset(name, options)?;
+        }
+        if let Some(profile) = layer.profile {
+            profile.apply(options);
+        }
+        layer.apply_fields(options);
+        Ok(())
+    }
+    
+    /// 按名称应用预设，用户预设优先于同名内置预设
+    pub fn apply_preset(&self, name: &str, options: &mut CompressionOptions) -> Result<(), GifError> {
+        match self.presets.get(name) {
+            Some(user) => {
+                // 用户预设只能继承内置预设，避免循环引用
+                if let Some(base) = &user.preset {
+                    builtin_preset(base)?.apply(options);
+                }
+                if let Some(profile) = user.profile {
+                    profile.apply(options);
+                }
+                user.apply_fields(options);
+            }
+            None => builtin_preset(name)?.apply(options),
+        }
+        Ok(())
+    }
+    
+    /// 应用除预设以外的字段
+    fn apply_fields(&self, options: &mut CompressionOptions) {
+        if let Some(target) = self.target {
+            options.target_size_kb = target;
+        }
+        if let Some(min_frames) = self.min_frames {
+            options.min_frame_percent = min_frames;
+        }
+        if let Some(threads) = self.threads {
+            options.threads = threads;
+        }
+        if let Some(levels) = &self.lossy_levels {
+            options.lossy_levels = levels.clone();
+        }
+        if let Some(backend) = self.backend {
+            options.backend = backend;
+        }
+        if let Some(dir) = &self.temp_dir {
+            options.temp_dir = Some(dir.clone());
+        }
+        if let Some(max_width) = self.max_width {
+            options.max_width = Some(max_width);
+        }
+        if let Some(max_height) = self.max_height {
+            options.max_height = Some(max_height);
+        }
+        if let Some(sharpen) = self.sharpen {
+            options.sharpen = sharpen;
+        }
+        if let Some(max_fps) = self.max_fps {
+            options.max_fps = Some(max_fps);
+        }
+        if let Some(colors) = self.colors {
+            options.colors = Some(colors);
+        }
+        if let Some(strict) = self.strict {
+            options.strict = strict;
+        }
+        if let Some(format) = self.format {
+            options.format = format;
+        }
+        if let Some(loop_count) = self.loop_count {
+            options.loop_count = Some(loop_count);
+        }
+        if let Some(collapse_static) = self.collapse_static {
+            options.collapse_static = collapse_static;
+        }
+        if let Some(lossless) = self.lossless {
+            options.lossless = lossless;
+        }
+        if let Some(no_lossy) = self.no_lossy {
+            options.no_lossy = no_lossy;
+        }
+        if let Some(keep_all_frames) = self.keep_all_frames {
+            options.keep_all_frames = keep_all_frames;
+        }
+        if let Some(prefer) = self.prefer {
+            options.prefer = Some(prefer);
+        }
+        if let Some(max_drop) = self.max_drop {
+            options.max_drop = Some(max_drop);
+        }
+        if let Some(optimizer) = self.optimizer {
+            options.optimizer = optimizer;
+        }
+        if let Some(optimizer_budget) = self.optimizer_budget {
+            options.optimizer_budget = optimizer_budget;
+        }
+        if let Some(autocrop) = self.autocrop {
+            options.autocrop = autocrop;
+        }
+        if let Some(denoise) = self.denoise {
+            options.denoise = Some(denoise);
+        }
+        if let Some(key_background) = self.key_background {
+            options.key_background = key_background;
+        }
+        if let Some(color) = self.transparent_color {
+            options.transparent_color = Some(color);
+        }
+        if let Some(tolerance) = self.tolerance {
+            options.tolerance = tolerance;
+        }
+        if let Some(freeze_static) = self.freeze_static {
+            options.freeze_static = freeze_static;
+        }
+        if let Some(dedupe_frames) = self.dedupe_frames {
+            options.dedupe_frames = dedupe_frames;
+        }
+        if let Some(region) = self.protect_region {
+            options.protect_region = Some(region);
+        }
+        if let Some(detect_text) = self.detect_text {
+            options.detect_text = detect_text;
+        }
+        if let Some(drop_mode) = self.drop_mode {
+            options.drop_mode = drop_mode;
+        }
+        if let Some(quantizer) = self.quantizer {
+            options.quantizer = quantizer;
+        }
+        if let Some(sample_fac) = self.sample_fac {
+            options.sample_fac = sample_fac;
+        }
+        if let Some(color_space) = self.color_space {
+            options.color_space = color_space;
+        }
+        if let Some(deband) = self.deband {
+            options.deband = deband;
+        }
+    }
+}
+
+/// 查找内置预设
+fn builtin_preset(name: &str) -> Result<&'static preset::Preset, GifError> {
+    preset::find(name).ok_or_else(|| GifError::UnknownPreset { name: name.to_string() })
+}
+
+/// NeuQuant采样因子超出范围时报错
+fn check_sample_fac(value: u32) -> Result<u32, String> {
+    if SAMPLE_FAC_RANGE.contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!("应为{}-{}", SAMPLE_FAC_RANGE.start(), SAMPLE_FAC_RANGE.end()))
+    }
+}
+
+/// 读取非空的环境变量
+fn env_string(name: &str) -> Option<String> {
+    std::env::var(format!("{}{}", ENV_PREFIX, name))
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// 读取并解析环境变量
+fn env_value<T>(name: &str) -> Result<Option<T>, GifError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env_string(name) {
+        Some(value) => value
+            .trim()
+            .parse::<T>()
+            .map(Some)
+            .map_err(|e| invalid_env(name, &value, e)),
+        None => Ok(None),
+    }
+}
+
+/// 读取布尔环境变量，接受 `true`/`false` 和 `1`/`0`
+fn env_bool(name: &str) -> Result<Option<bool>, GifError> {
+    env_string(name).map(|value| parse_bool(name, &value)).transpose()
+}
+
+fn parse_bool(name: &str, value: &str) -> Result<bool, GifError> {
+    match value.trim() {
+        "1" => Ok(true),
+        "0" => Ok(false),
+        other => other.parse().map_err(|e| invalid_env(name, value, e)),
+    }
+}
+
+/// 读取逗号分隔的列表环境变量，例如 `GIF_COMPRESSOR_LOSSY_LEVELS=30,60,90`
+fn env_list<T>(name: &str) -> Result<Option<Vec<T>>, GifError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    env_string(name).map(|value| parse_list(name, &value)).transpose()
+}
+
+fn parse_list<T>(name: &str, value: &str) -> Result<Vec<T>, GifError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .split(',')
+        .map(|item| item.trim().parse())
+        .collect::<Result<Vec<T>, T::Err>>()
+        .map_err(|e| invalid_env(name, value, e))
+}
+
+fn invalid_env(name: &str, value: &str, error: impl Display) -> GifError {
+    GifError::InvalidEnv {
+        name: format!("{}{}", ENV_PREFIX, name),
+        value: value.to_string(),
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::DEFAULT_LOSSY_LEVELS;
+
+    fn applied(config: &Config) -> CompressionOptions {
+        let mut options = CompressionOptions::default();
+        config.apply(&mut options).unwrap();
+        options
+    }
+
+    #[test]
+    fn later_file_overrides_earlier_file() {
+        let user = Config {
+            target: Some(100.0),
+            colors: Some(64),
+            presets: BTreeMap::from([("mine".to_string(), Config { target: Some(1.0), ..Config::default() })]),
+            ..Config::default()
+        };
+        let project = Config {
+            target: Some(200.0),
+            presets: BTreeMap::from([
+                ("mine".to_string(), Config { target: Some(2.0), ..Config::default() }),
+                ("other".to_string(), Config::default()),
+            ]),
+            ..Config::default()
+        };
+        let config = user.merge(project);
+        assert_eq!(config.target, Some(200.0));
+        assert_eq!(config.colors, Some(64));
+        assert_eq!(config.presets["mine"].target, Some(2.0));
+        assert!(config.presets.contains_key("other"));
+    }
+
+    #[test]
+    fn layer_applies_preset_then_profile_then_fields() {
+        // email 预设限制为128色，录屏内容类型取消颜色限制
+        let config = Config { preset: Some("email".to_string()), profile: Some(Profile::Screen), ..Config::default() };
+        let options = applied(&config);
+        assert_eq!(options.target_size_kb, 1000.0);
+        assert_eq!(options.colors, None);
+        assert_eq!(options.lossy_levels, [10, 20, 40]);
+
+        let config = Config { colors: Some(32), lossy_levels: Some(vec![5]), ..config };
+        let options = applied(&config);
+        assert_eq!(options.colors, Some(32));
+        assert_eq!(options.lossy_levels, [5]);
+    }
+
+    #[test]
+    fn env_layer_applies_after_file() {
+        let file = Config { target: Some(100.0), colors: Some(64), ..Config::default() };
+
+        let env = Config { target: Some(300.0), ..Config::default() };
+        let options = applied(&Config { env: Some(Box::new(env)), ..file.clone() });
+        assert_eq!(options.target_size_kb, 300.0);
+        assert_eq!(options.colors, Some(64));
+
+        // 环境变量中的预设覆盖配置文件中的字段
+        let env = Config { preset: Some("slack".to_string()), ..Config::default() };
+        let options = applied(&Config { env: Some(Box::new(env)), ..file });
+        assert_eq!(options.target_size_kb, 128.0);
+        assert_eq!(options.colors, None);
+        assert_eq!(options.lossy_levels, DEFAULT_LOSSY_LEVELS);
+    }
+
+    #[test]
+    fn env_preset_uses_file_presets() {
+        let file = Config {
+            presets: BTreeMap::from([("mine".to_string(), Config { target: Some(42.0), ..Config::default() })]),
+            ..Config::default()
+        };
+        let env = Config { preset: Some("mine".to_string()), ..Config::default() };
+        assert_eq!(applied(&Config { env: Some(Box::new(env)), ..file }).target_size_kb, 42.0);
+    }
+
+    #[test]
+    fn user_preset_overrides_same_name_builtin() {
+        let config = Config {
+            presets: BTreeMap::from([("slack".to_string(), Config { target: Some(64.0), ..Config::default() })]),
+            ..Config::default()
+        };
+        let mut options = CompressionOptions::default();
+        config.apply_preset("slack", &mut options).unwrap();
+        assert_eq!(options.target_size_kb, 64.0);
+        // 没有继承内置的 slack 预设
+        assert_eq!(options.max_width, None);
+    }
+
+    #[test]
+    fn user_preset_inherits_builtin() {
+        let config = Config {
+            presets: BTreeMap::from([(
+                "small-email".to_string(),
+                Config { preset: Some("email".to_string()), target: Some(300.0), ..Config::default() },
+            )]),
+            ..Config::default()
+        };
+        let mut options = CompressionOptions::default();
+        config.apply_preset("small-email", &mut options).unwrap();
+        assert_eq!(options.target_size_kb, 300.0);
+        assert_eq!(options.max_width, Some(600));
+        assert_eq!(options.loop_count, Some(0));
+    }
+
+    #[test]
+    fn unknown_preset_is_an_error() {
+        let config = Config {
+            presets: BTreeMap::from([(
+                "broken".to_string(),
+                Config { preset: Some("no-such-builtin".to_string()), ..Config::default() },
+            )]),
+            ..Config::default()
+        };
+        let mut options = CompressionOptions::default();
+        assert!(matches!(
+            config.apply_preset("missing", &mut options),
+            Err(GifError::UnknownPreset { name }) if name == "missing"
+        ));
+        assert!(matches!(
+            config.apply_preset("broken", &mut options),
+            Err(GifError::UnknownPreset { name }) if name == "no-such-builtin"
+        ));
+    }
+
+    #[test]
+    fn parses_env_bool() {
+        assert!(parse_bool("STRICT", "1").unwrap());
+        assert!(!parse_bool("STRICT", " 0 ").unwrap());
+        assert!(parse_bool("STRICT", "true").unwrap());
+        assert!(!parse_bool("STRICT", "false").unwrap());
+        match parse_bool("STRICT", "yes") {
+            Err(GifError::InvalidEnv { name, value, .. }) => {
+                assert_eq!(name, "GIF_COMPRESSOR_STRICT");
+                assert_eq!(value, "yes");
+            }
+            other => panic!("应当返回环境变量错误: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_env_list() {
+        assert_eq!(parse_list::<u32>("LOSSY_LEVELS", "30, 60,90").unwrap(), [30, 60, 90]);
+        assert_eq!(parse_list::<u32>("LOSSY_LEVELS", "45").unwrap(), [45]);
+        assert!(matches!(parse_list::<u32>("LOSSY_LEVELS", "30,,90"), Err(GifError::InvalidEnv { .. })));
+        assert!(matches!(parse_list::<u32>("LOSSY_LEVELS", "30,x"), Err(GifError::InvalidEnv { .. })));
+    }
+}
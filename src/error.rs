@@ -0,0 +1,185 @@
+//! 错误类型
+//!
+//! 每个变体对应一个稳定的数字错误码（见 [`GifError::code`]），
+//! C API 通过 `gif_last_error_code()` 返回，JSON中以 `code` 字段输出。
+//! 错误码只会新增，不会修改或复用。
+
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use thiserror::Error;
+
+/// 自定义错误类型
+#[derive(Error, Debug)]
+pub enum GifError {
+    #[error("IO错误: {0}")]
+    Io(#[from] std::io::Error),
+    
+    #[error("图像处理错误: {0}")]
+    Image(#[from] image::error::ImageError),
+    
+    #[error("GIF编码错误: {0}")]
+    Encode(#[from] gif::EncodingError),
+    
+    #[error("GIF解码错误: {0}")]
+    Decode(#[from] gif::DecodingError),
+    
+    #[error("预览窗口错误: {message}")]
+    Preview { message: String },
+    
+    #[error("GIF没有帧")]
+    NoFrames,
+    
+    #[error("未找到gifsicle命令，请确保已安装")]
+    GifsicleNotFound,
+    
+    #[error("未找到ffmpeg命令，请确保已安装")]
+    FfmpegNotFound,
+    
+    #[error("命令执行失败 ({}): {command}\n{stderr}", status.map_or("被信号终止".to_string(), |code| format!("退出码 {}", code)))]
+    CommandFailed {
+        /// 完整的命令行
+        command: String,
+        /// 退出码，被信号终止时为None
+        status: Option<i32>,
+        /// 标准错误输出
+        stderr: String,
+    },
+    
+    #[error("输入文件不存在: {}", path.display())]
+    InputFileNotFound { path: PathBuf },
+    
+    #[error("临时目录创建失败 {}: {source}", path.display())]
+    TempDirFailed {
+        /// 临时目录所在的父目录
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    
+    #[error("临时文件不存在: {}", path.display())]
+    TempFileMissing { path: PathBuf },
+    
+    #[error("读写文件失败 {}: {source}", path.display())]
+    File {
+        /// 出错的文件或目录
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    
+    #[error("配置文件解析失败 {}: {message}", path.display())]
+    ConfigParse { path: PathBuf, message: String },
+    
+    #[error("环境变量 {name} 的值 {value:?} 无效: {message}")]
+    InvalidEnv { name: String, value: String, message: String },
+    
+    #[error("未知的预设: {name}")]
+    UnknownPreset { name: String },
+    
+    #[error("JSON序列化失败: {0}")]
+    Json(#[from] serde_json::Error),
+    
+    #[error("没有找到有效的优化结果")]
+    NoValidResults,
+    
+    #[error("无法满足硬性限制: 最小只能压缩到 {size_kb:.2} KB，超过上限 {limit_kb} KB")]
+    SizeLimitExceeded { size_kb: f64, limit_kb: f64 },
+    
+    #[error("无法满足硬性限制: 输出尺寸 {width}x{height} 超出上限 {}x{}",
+        max_width.map_or("_".to_string(), |v| v.to_string()),
+        max_height.map_or("_".to_string(), |v| v.to_string()))]
+    DimensionLimitExceeded {
+        width: u32,
+        height: u32,
+        max_width: Option<u32>,
+        max_height: Option<u32>,
+    },
+    
+    #[error("当前构建未启用 {feature} 特性")]
+    FeatureDisabled { feature: &'static str },
+    
+    #[error("压缩已取消")]
+    Cancelled,
+    
+    #[error("压缩任务异常退出: {message}")]
+    TaskFailed { message: String },
+    
+    #[error("超出时间限制，还没有任何压缩结果")]
+    TimedOut,
+}
+
+impl GifError {
+    /// 稳定的数字错误码
+    ///
+    /// | 范围 | 类别 |
+    /// |------|------|
+    /// | 1-9 | IO与编解码 |
+    /// | 10-19 | 外部命令 |
+    /// | 20-29 | 文件与临时目录 |
+    /// | 30-39 | 配置 |
+    /// | 40-49 | 压缩结果 |
+    /// | 50-59 | 构建特性 |
+    /// | 60-69 | 任务控制 |
+    /// | 70-79 | 交互界面 |
+    pub fn code(&self) -> u32 {
+        match self {
+            GifError::Io(_) => 1,
+            GifError::Image(_) => 2,
+            GifError::Encode(_) => 3,
+            GifError::NoFrames => 4,
+            GifError::Decode(_) => 5,
+            GifError::GifsicleNotFound => 10,
+            GifError::FfmpegNotFound => 11,
+            GifError::CommandFailed { .. } => 12,
+            GifError::InputFileNotFound { .. } => 20,
+            GifError::TempDirFailed { .. } => 21,
+            GifError::TempFileMissing { .. } => 22,
+            GifError::File { .. } => 23,
+            GifError::ConfigParse { .. } => 30,
+            GifError::InvalidEnv { .. } => 31,
+            GifError::UnknownPreset { .. } => 32,
+            GifError::Json(_) => 33,
+            GifError::NoValidResults => 40,
+            GifError::SizeLimitExceeded { .. } => 41,
+            GifError::DimensionLimitExceeded { .. } => 42,
+            GifError::FeatureDisabled { .. } => 50,
+            GifError::Cancelled => 60,
+            GifError::TaskFailed { .. } => 61,
+            GifError::TimedOut => 62,
+            GifError::Preview { .. } => 70,
+        }
+    }
+    
+    /// 根据外部命令的输出构造错误
+    pub(crate) fn command_failed(program: &str, args: &[String], output: &Output) -> Self {
+        GifError::CommandFailed {
+            command: format!("{} {}", program, args.join(" ")),
+            status: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).trim_end().to_string(),
+        }
+    }
+}
+
+/// 为IO错误附加出错的文件路径
+///
+/// ```ignore
+/// let data = fs::read(&path).with_path(&path)?;
+/// ```
+pub trait WithPath<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, GifError>;
+}
+
+impl<T> WithPath<T> for std::io::Result<T> {
+    fn with_path(self, path: impl AsRef<Path>) -> Result<T, GifError> {
+        self.map_err(|source| GifError::File { path: path.as_ref().to_path_buf(), source })
+    }
+}
+
+/// 序列化为 `{"code": 错误码, "message": 错误信息}`
+impl Serialize for GifError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("GifError", 2)?;
+        state.serialize_field("code", &self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.end()
+    }
+}
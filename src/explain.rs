@@ -0,0 +1,191 @@
+//! 解释压缩结果
+//!
+//! 记录压缩过程中的所有候选结果，压缩完成后说明选中了哪一个、
+//! 其他候选为什么被放弃，以及想要更小或更清晰的结果时可以调整哪些参数。
+
+use crate::progress::{ProgressEvent, Stage, StopReason};
+use crate::report::CompressionReport;
+use crate::reporter::{Reporter, SharedReporter};
+use std::sync::Mutex;
+
+/// 判断两个大小相同的容差(KB)
+const SIZE_EPSILON_KB: f64 = 0.005;
+
+/// 一个候选结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    /// 产生它的搜索阶段
+    pub stage: Stage,
+    pub skip: usize,
+    /// 0表示未使用有损压缩
+    pub lossy: u32,
+    pub colors: u16,
+    /// 1表示原尺寸
+    pub scale: f64,
+    pub outcome: Outcome,
+}
+
+/// 候选的压缩结果
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// 压缩完成
+    Done { size_kb: f64 },
+    /// 在完成前被终止
+    Stopped(StopReason),
+}
+
+impl Candidate {
+    fn describe(&self) -> String {
+        if self.stage == Stage::Base {
+            return "保留所有帧的基础优化结果".to_string();
+        }
+        let mut text = format!("每 {} 帧取1帧", self.skip);
+        if self.scale < 1.0 {
+            text.push_str(&format!(", 缩放到 {:.0}%", self.scale * 100.0));
+        }
+        if self.lossy > 0 {
+            text.push_str(&format!(", lossy={}", self.lossy));
+        }
+        if self.colors < 256 {
+            text.push_str(&format!(", {} 色", self.colors));
+        }
+        text
+    }
+
+    /// 画质损失是否不低于另一个候选：抽帧更多、尺寸更小、lossy更高或颜色更少
+    fn degrades_more_than(&self, other: &Candidate) -> bool {
+        self.skip >= other.skip && self.scale <= other.scale && self.lossy >= other.lossy && self.colors <= other.colors
+    }
+}
+
+/// 转发日志和事件，同时记录所有候选结果和最终写入输出文件的结果
+pub struct CandidateRecorder {
+    inner: SharedReporter,
+    candidates: Mutex<Vec<Candidate>>,
+    selected: Mutex<Option<Candidate>>,
+}
+
+impl CandidateRecorder {
+    pub fn new(inner: SharedReporter) -> Self {
+        Self { inner, candidates: Mutex::new(Vec::new()), selected: Mutex::new(None) }
+    }
+
+    /// 目前记录到的候选结果
+    pub fn candidates(&self) -> Vec<Candidate> {
+        self.candidates.lock().map(|c| c.clone()).unwrap_or_default()
+    }
+
+    /// 最后一次写入输出文件的结果
+    pub fn selected(&self) -> Option<Candidate> {
+        self.selected.lock().ok().and_then(|selected| *selected)
+    }
+
+    /// 清空已记录的候选结果，用于调整参数后重新压缩
+    pub fn clear(&self) {
+        if let Ok(mut candidates) = self.candidates.lock() {
+            candidates.clear();
+        }
+        if let Ok(mut selected) = self.selected.lock() {
+            *selected = None;
+        }
+    }
+}
+
+impl Reporter for CandidateRecorder {
+    fn log(&self, message: &str) {
+        self.inner.log(message);
+    }
+
+    fn event(&self, event: &ProgressEvent) {
+        match *event {
+            ProgressEvent::Candidate { stage, skip, lossy, colors, scale, size_kb } => {
+                if let Ok(mut candidates) = self.candidates.lock() {
+                    candidates.push(Candidate { stage, skip, lossy, colors, scale, outcome: Outcome::Done { size_kb } });
+                }
+            }
+            ProgressEvent::CandidateStopped { stage, skip, lossy, colors, scale, reason } => {
+                if let Ok(mut candidates) = self.candidates.lock() {
+                    candidates.push(Candidate { stage, skip, lossy, colors, scale, outcome: Outcome::Stopped(reason) });
+                }
+            }
+            ProgressEvent::Selected { stage, skip, lossy, colors, scale, size_kb } => {
+                if let Ok(mut selected) = self.selected.lock() {
+                    *selected = Some(Candidate { stage, skip, lossy, colors, scale, outcome: Outcome::Done { size_kb } });
+                }
+            }
+            _ => {}
+        }
+        self.inner.event(event);
+    }
+}
+
+/// 生成对压缩结果的解释，每个元素是一行
+///
+/// `selected` 是最后写入输出文件的结果，没有经过候选搜索时为None。
+pub fn explain(report: &CompressionReport, candidates: &[Candidate], selected: Option<Candidate>) -> Vec<String> {
+    let target = report.options.target_size_kb;
+    let skip = report.strategy.map_or(1, |strategy| strategy.skip);
+    // 输出文件之后没有被替换时，选中的结果与报告中的大小相同
+    let winner = selected.filter(|selected| {
+        matches!(selected.outcome, Outcome::Done { size_kb } if (size_kb - report.size_kb).abs() < SIZE_EPSILON_KB)
+    });
+
+    let mut lines = vec!["\n=== 结果说明 ===".to_string()];
+    match winner {
+        Some(winner) => lines.push(format!("选中: {}（{}）, {:.2} KB", winner.describe(), winner.stage.name(), report.size_kb)),
+        None => lines.push(format!("选中: 没有经过候选搜索的结果, {:.2} KB", report.size_kb)),
+    }
+    if report.target_reached {
+        lines.push(format!("已达到目标 {} KB，画质损失最小的满足条件的结果优先。", target));
+    } else {
+        lines.push(format!("所有候选都超出目标 {} KB，选用了最小的结果。", target));
+    }
+
+    let mut others: Vec<&Candidate> = candidates.iter().filter(|c| Some(**c) != winner).collect();
+    others.sort_by(|a, b| {
+        (a.skip, a.lossy, b.colors)
+            .cmp(&(b.skip, b.lossy, a.colors))
+            .then(b.scale.total_cmp(&a.scale))
+    });
+    if !others.is_empty() {
+        lines.push("其他候选:".to_string());
+    }
+    for candidate in others {
+        let line = match candidate.outcome {
+            Outcome::Stopped(StopReason::TooLarge) => "超过当时可能被选中的大小，提前终止".to_string(),
+            Outcome::Stopped(StopReason::Cancelled | StopReason::TimedOut) => "搜索在它完成前已经结束".to_string(),
+            Outcome::Done { size_kb } => {
+                let reason = if size_kb > target {
+                    format!("超出目标 {:.2} KB", size_kb - target)
+                } else if winner.is_some_and(|w| candidate.degrades_more_than(&w)) {
+                    "满足目标但画质损失更大".to_string()
+                } else {
+                    format!("满足目标，但{}没有选中它", candidate.stage.name())
+                };
+                format!("{:.2} KB，{}", size_kb, reason)
+            }
+        };
+        lines.push(format!("  {}: {}", candidate.describe(), line));
+    }
+
+    lines.push("想要更小的文件:".to_string());
+    lines.push("  --max-width/--max-height 缩小尺寸，文件大小大致随面积下降".to_string());
+    lines.push("  --colors 减少颜色数，或 --max-fps 降低帧率".to_string());
+    if skip > 1 || !report.target_reached {
+        lines.push("  --min-frames 降低保留帧数的下限，允许更激进的抽帧".to_string());
+    }
+    if let Some(max_drop) = report.options.max_drop
+        && !report.target_reached
+    {
+        lines.push(format!("  --max-drop 提高抽帧比例的上限（当前 {}）", max_drop));
+    }
+    lines.push("想要更清晰的结果:".to_string());
+    lines.push("  --target 提高目标大小，可以保留更多帧并降低lossy级别".to_string());
+    if report.lossy.is_some() {
+        lines.push("  在配置文件中用 lossy-levels 限制lossy上限，例如 [20, 40, 60]".to_string());
+    }
+    if skip > 1 {
+        lines.push("  --min-frames 提高保留帧数的下限，动作会更流畅".to_string());
+    }
+    lines
+}
@@ -0,0 +1,188 @@
+//! C ABI 绑定
+//!
+//! 选项以JSON字符串传入，字段与配置文件相同，例如
+//! `{"target": 256, "max-width": 128, "preset": "discord-emoji"}`，传NULL时使用默认选项。
+//! 头文件见 `include/gif_compressor.h`。
+
+use crate::config::Config;
+use crate::options::CompressionOptions;
+use crate::reporter::SilentReporter;
+use crate::{compress_bytes_with_reporter, compress_with_reporter, GifError};
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+
+/// 成功
+pub const GIF_COMPRESS_OK: c_int = 0;
+/// 参数无效（空指针或非UTF-8路径）
+pub const GIF_COMPRESS_ERR_INVALID_ARGUMENT: c_int = 1;
+/// 选项JSON无效
+pub const GIF_COMPRESS_ERR_INVALID_OPTIONS: c_int = 2;
+/// 压缩失败
+pub const GIF_COMPRESS_ERR_FAILED: c_int = 3;
+/// 内部错误（panic）
+pub const GIF_COMPRESS_ERR_PANIC: c_int = 4;
+
+thread_local! {
+    // 当前线程最近一次调用的错误信息和错误码
+    static LAST_ERROR: RefCell<Option<(CString, c_int)>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    set_last_error_with_code(message, 0);
+}
+
+fn set_last_error_with_code(message: String, code: c_int) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some((message, code)));
+}
+
+fn set_gif_error(e: GifError) {
+    set_last_error_with_code(e.to_string(), e.code() as c_int);
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+}
+
+/// 读取C字符串参数
+unsafe fn read_str<'a>(value: *const c_char, name: &str) -> Result<&'a str, c_int> {
+    if value.is_null() {
+        set_last_error(format!("{} 不能为空", name));
+        return Err(GIF_COMPRESS_ERR_INVALID_ARGUMENT);
+    }
+    unsafe { CStr::from_ptr(value) }.to_str().map_err(|_| {
+        set_last_error(format!("{} 不是有效的UTF-8字符串", name));
+        GIF_COMPRESS_ERR_INVALID_ARGUMENT
+    })
+}
+
+/// 解析选项JSON，NULL表示默认选项
+unsafe fn read_options(options_json: *const c_char) -> Result<CompressionOptions, c_int> {
+    let mut options = CompressionOptions::default();
+    if options_json.is_null() {
+        return Ok(options);
+    }
+
+    let json = unsafe { read_str(options_json, "options_json") }?;
+    let config: Config = serde_json::from_str(json).map_err(|e| {
+        set_last_error(format!("选项JSON无效: {}", e));
+        GIF_COMPRESS_ERR_INVALID_OPTIONS
+    })?;
+    config.apply(&mut options).map_err(|e| {
+        set_gif_error(e);
+        GIF_COMPRESS_ERR_INVALID_OPTIONS
+    })?;
+    Ok(options)
+}
+
+/// 执行压缩并把错误转换为状态码，同时拦截panic避免跨越FFI边界
+fn run(f: impl FnOnce() -> Result<(), c_int>) -> c_int {
+    clear_last_error();
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => GIF_COMPRESS_OK,
+        Ok(Err(code)) => code,
+        Err(_) => {
+            set_last_error("内部错误".to_string());
+            GIF_COMPRESS_ERR_PANIC
+        }
+    }
+}
+
+fn compress_failed(e: GifError) -> c_int {
+    set_gif_error(e);
+    GIF_COMPRESS_ERR_FAILED
+}
+
+/// 压缩文件
+///
+/// # Safety
+///
+/// `input_path` 和 `output_path` 必须是有效的以NUL结尾的字符串；
+/// `options_json` 可以为NULL。
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gif_compress(
+    input_path: *const c_char,
+    output_path: *const c_char,
+    options_json: *const c_char,
+) -> c_int {
+    run(|| {
+        let input = unsafe { read_str(input_path, "input_path") }?;
+        let output = unsafe { read_str(output_path, "output_path") }?;
+        let options = unsafe { read_options(options_json) }?;
+        compress_with_reporter(input, output, &options, Arc::new(SilentReporter))
+            .map(|_| ())
+            .map_err(compress_failed)
+    })
+}
+
+/// 压缩内存中的GIF数据
+///
+/// 成功时 `*output` 指向新分配的缓冲区，长度写入 `*output_len`，
+/// 使用完毕后必须调用 `gif_free_buffer` 释放。
+///
+/// # Safety
+///
+/// `input` 必须指向至少 `input_len` 字节的可读内存；`output` 和 `output_len`
+/// 必须是有效的可写指针；`options_json` 可以为NULL。
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gif_compress_buffer(
+    input: *const u8,
+    input_len: usize,
+    options_json: *const c_char,
+    output: *mut *mut u8,
+    output_len: *mut usize,
+) -> c_int {
+    run(|| {
+        if input.is_null() || output.is_null() || output_len.is_null() {
+            set_last_error("input、output 和 output_len 不能为空".to_string());
+            return Err(GIF_COMPRESS_ERR_INVALID_ARGUMENT);
+        }
+        let data = unsafe { std::slice::from_raw_parts(input, input_len) };
+        let options = unsafe { read_options(options_json) }?;
+        let result = compress_bytes_with_reporter(data, &options, Arc::new(SilentReporter))
+            .map_err(compress_failed)?;
+
+        let len = result.len();
+        let buffer = Box::into_raw(result.into_boxed_slice()) as *mut u8;
+        unsafe {
+            *output = buffer;
+            *output_len = len;
+        }
+        Ok(())
+    })
+}
+
+/// 释放 `gif_compress_buffer` 分配的缓冲区
+///
+/// # Safety
+///
+/// `buffer` 和 `len` 必须来自同一次 `gif_compress_buffer` 调用，且只能释放一次。
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn gif_free_buffer(buffer: *mut u8, len: usize) {
+    if buffer.is_null() {
+        return;
+    }
+    drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buffer, len)) });
+}
+
+/// 当前线程最近一次调用的错误信息，没有错误时返回NULL
+///
+/// 返回的指针在同一线程下一次调用本库函数之前有效。
+#[unsafe(no_mangle)]
+pub extern "C" fn gif_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some((message, _)) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// 当前线程最近一次调用的库错误码（见 `GifError::code`）
+///
+/// 没有错误或错误不是由压缩库产生（例如参数无效）时返回0。
+#[unsafe(no_mangle)]
+pub extern "C" fn gif_last_error_code() -> c_int {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(0, |(_, code)| *code))
+}
@@ -0,0 +1,363 @@
+//! 压缩前的逐帧处理
+//!
+//! 纯Rust后端直接处理解码后的帧；gifsicle后端先把处理后的帧写入临时GIF，
+//! 再在此基础上执行原有的压缩流程。
+
+use crate::{GifError, WithPath};
+use crate::native::{self, DecodedFrame};
+use crate::options::{CompressionOptions, Denoise, HexColor};
+use crate::reporter::Reporter;
+use image::imageops::{self, FilterType};
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use tempfile::NamedTempFile;
+
+/// 与上一帧的差异不超过此值（每个通道）的像素视为未变化
+const FREEZE_TOLERANCE: u8 = 10;
+
+/// 检测背景时边框像素与背景色每个通道允许的差异
+const BACKGROUND_TOLERANCE: u8 = 24;
+
+/// 每帧边框上至少有这么多像素是同一颜色才视为纯色背景
+const BACKGROUND_BORDER_RATIO: f64 = 0.6;
+
+/// 感知哈希的边长，哈希共 HASH_SIZE * HASH_SIZE 位
+const HASH_SIZE: u32 = 16;
+
+/// 汉明距离不超过此值的两帧作为同组候选
+const HASH_DISTANCE: u32 = 8;
+
+/// 自动裁剪时边框像素与角落颜色每个通道允许的差异
+const CROP_TOLERANCE: u8 = 8;
+
+/// 反锐化掩模的模糊半径
+const SHARPEN_SIGMA: f32 = 0.8;
+
+/// 是否启用了任何逐帧处理
+pub(crate) fn enabled(options: &CompressionOptions) -> bool {
+    options.autocrop
+        || options.denoise.is_some()
+        || options.key_background
+        || options.transparent_color.is_some()
+        || options.freeze_static || options.dedupe_frames || resize_sharpen(options)
+}
+
+/// 是否需要在缩小后锐化，gifsicle后端此时改为在预处理中缩小
+fn resize_sharpen(options: &CompressionOptions) -> bool {
+    options.sharpen > 0 && (options.max_width.is_some() || options.max_height.is_some())
+}
+
+/// 依次执行启用的处理
+pub(crate) fn apply(frames: &mut Vec<DecodedFrame>, options: &CompressionOptions, reporter: &dyn Reporter) {
+    if options.autocrop {
+        autocrop(frames, reporter);
+    }
+    if let Some(strength) = options.denoise {
+        denoise(frames, strength);
+    }
+    if options.key_background {
+        match detect_background(frames.iter().map(|frame| &frame.image)) {
+            Some(color) => {
+                reporter.log(&format!("检测到纯色背景 {}，转为透明", HexColor(color)));
+                key_color(frames, color, options.tolerance);
+            }
+            None => reporter.log("未检测到纯色背景，不做透明处理"),
+        }
+    }
+    if let Some(color) = options.transparent_color {
+        key_color(frames, color.0, options.tolerance);
+    }
+    if options.dedupe_frames {
+        dedupe_frames(frames, reporter);
+    }
+    if options.freeze_static {
+        freeze_static_regions(frames);
+    }
+}
+
+/// 解码输入文件，处理后写入临时GIF
+pub(crate) fn write_filtered<P: AsRef<Path>>(
+    input_path: P,
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<NamedTempFile, GifError> {
+    reporter.log("逐帧预处理...");
+    let mut frames = native::decode_frames(&fs::read(&input_path).with_path(&input_path)?)?;
+    if resize_sharpen(options) {
+        // 已经满足尺寸限制，gifsicle的--resize-fit不会再缩放
+        frames = native::resize_frames(frames, options);
+    }
+    apply(&mut frames, options, reporter);
+
+    let mut builder = tempfile::Builder::new();
+    builder.suffix(".gif");
+    let file = match options.temp_dir() {
+        Some(dir) => builder.tempfile_in(dir),
+        None => builder.tempfile(),
+    }?;
+    fs::write(file.path(), native::encode_exact(&frames, options)?).with_path(file.path())?;
+    Ok(file)
+}
+
+/// 把一组帧平均混合为一帧，用于抽帧时保留被丢弃帧的运动
+pub(crate) fn blend<'a>(images: impl IntoIterator<Item = &'a RgbaImage>) -> RgbaImage {
+    let mut images = images.into_iter();
+    let first = images.next().expect("至少需要一帧");
+    let mut sums: Vec<u32> = first.as_raw().iter().map(|&v| v as u32).collect();
+    let mut count = 1;
+    for image in images {
+        for (sum, &value) in sums.iter_mut().zip(image.as_raw()) {
+            *sum += value as u32;
+        }
+        count += 1;
+    }
+
+    let pixels = sums.iter().map(|sum| ((sum + count / 2) / count) as u8).collect();
+    RgbaImage::from_raw(first.width(), first.height(), pixels).expect("尺寸与第一帧相同")
+}
+
+/// 冻结几乎不变的像素
+///
+/// 与上一帧（已处理）相比每个通道的差异都不超过容差的像素直接沿用上一帧的值，
+/// 这样屏幕录制中的摄像头噪点和编码噪声不会破坏gifsicle的帧间透明优化。
+fn freeze_static_regions(frames: &mut [DecodedFrame]) {
+    for i in 1..frames.len() {
+        let (before, after) = frames.split_at_mut(i);
+        let previous = &before[i - 1].image;
+        for (pixel, prev) in after[0].image.pixels_mut().zip(previous.pixels()) {
+            if pixel.0.iter().zip(prev.0.iter()).all(|(a, b)| a.abs_diff(*b) <= FREEZE_TOLERANCE) {
+                *pixel = *prev;
+            }
+        }
+    }
+}
+
+/// 裁掉所有帧都相同的纯色边框
+///
+/// 上边和左边与第一帧左上角的颜色比较，下边和右边与右下角的颜色比较，
+/// 这样上下或左右颜色不同的边距（例如标题栏和状态栏）也能裁掉。
+fn autocrop(frames: &mut [DecodedFrame], reporter: &dyn Reporter) {
+    let (width, height) = frames[0].image.dimensions();
+    let top_left = *frames[0].image.get_pixel(0, 0);
+    let bottom_right = *frames[0].image.get_pixel(width - 1, height - 1);
+
+    let matches = |x: u32, y: u32, color: &Rgba<u8>| {
+        frames.iter().all(|frame| similar(frame.image.get_pixel(x, y), color, CROP_TOLERANCE))
+    };
+    let row = |y: u32, color: &Rgba<u8>| (0..width).all(|x| matches(x, y, color));
+    let column = |x: u32, color: &Rgba<u8>, top: u32, bottom: u32| (top..bottom).all(|y| matches(x, y, color));
+
+    let mut top = 0;
+    while top + 1 < height && row(top, &top_left) {
+        top += 1;
+    }
+    let mut bottom = height;
+    while bottom - 1 > top && row(bottom - 1, &bottom_right) {
+        bottom -= 1;
+    }
+    let mut left = 0;
+    while left + 1 < width && column(left, &top_left, top, bottom) {
+        left += 1;
+    }
+    let mut right = width;
+    while right - 1 > left && column(right - 1, &bottom_right, top, bottom) {
+        right -= 1;
+    }
+
+    if (left, top, right, bottom) == (0, 0, width, height) {
+        reporter.log("未检测到可裁掉的边框");
+        return;
+    }
+
+    reporter.log(&format!("裁掉纯色边框: {}x{} -> {}x{} (偏移 {},{})",
+        width, height, right - left, bottom - top, left, top));
+    for frame in frames {
+        frame.image = imageops::crop_imm(&frame.image, left, top, right - left, bottom - top).to_image();
+    }
+}
+
+/// 图像边框上的像素
+fn border_pixels(image: &RgbaImage) -> impl Iterator<Item = &Rgba<u8>> {
+    let (width, height) = image.dimensions();
+    image
+        .enumerate_pixels()
+        .filter(move |(x, y, _)| *x == 0 || *y == 0 || *x == width - 1 || *y == height - 1)
+        .map(|(_, _, pixel)| pixel)
+}
+
+/// 检测所有帧共同的纯色背景
+///
+/// 取所有帧边框上最常见的不透明颜色，每一帧的边框上都有足够多的像素与它相近时
+/// 视为背景。
+pub(crate) fn detect_background<'a>(mut images: impl Iterator<Item = &'a RgbaImage> + Clone) -> Option<[u8; 3]> {
+    let mut counts: HashMap<[u8; 3], usize> = HashMap::new();
+    for pixel in images.clone().flat_map(border_pixels).filter(|p| p[3] >= 128) {
+        *counts.entry([pixel[0], pixel[1], pixel[2]]).or_insert(0) += 1;
+    }
+    let (color, _) = counts.into_iter().max_by_key(|&(color, count)| (count, color))?;
+
+    let key = Rgba([color[0], color[1], color[2], 255]);
+    images
+        .all(|image| {
+            let (total, matched) = border_pixels(image)
+                .fold((0, 0), |(total, matched), p| (total + 1, matched + similar(p, &key, BACKGROUND_TOLERANCE) as usize));
+            matched as f64 >= total as f64 * BACKGROUND_BORDER_RATIO
+        })
+        .then_some(color)
+}
+
+/// 把与指定颜色相近的不透明像素转为透明
+pub(crate) fn key_color(frames: &mut [DecodedFrame], color: [u8; 3], tolerance: u8) {
+    let key = Rgba([color[0], color[1], color[2], 255]);
+    for frame in frames {
+        for pixel in frame.image.pixels_mut() {
+            if pixel[3] >= 128 && similar(&Rgba([pixel[0], pixel[1], pixel[2], 255]), &key, tolerance) {
+                *pixel = Rgba([0, 0, 0, 0]);
+            }
+        }
+    }
+}
+
+/// 反锐化掩模，`amount` 为0-100的强度，0时原样返回
+pub(crate) fn sharpen(image: RgbaImage, amount: u8) -> RgbaImage {
+    if amount == 0 {
+        return image;
+    }
+    let sharpened = imageops::unsharpen(&image, SHARPEN_SIGMA, 0);
+    let amount = amount.min(100) as i32;
+    let pixels = image
+        .as_raw()
+        .iter()
+        .zip(sharpened.as_raw())
+        .map(|(&original, &sharp)| {
+            (original as i32 + (sharp as i32 - original as i32) * amount / 100).clamp(0, 255) as u8
+        })
+        .collect();
+    RgbaImage::from_raw(image.width(), image.height(), pixels).expect("尺寸不变")
+}
+
+/// 降噪强度对应的差异阈值和空间滤波次数
+fn denoise_params(strength: Denoise) -> (u8, usize) {
+    match strength {
+        Denoise::Light => (8, 0),
+        Denoise::Medium => (16, 1),
+        Denoise::Strong => (24, 2),
+    }
+}
+
+/// 两个像素每个通道的差异都不超过阈值
+fn similar(a: &Rgba<u8>, b: &Rgba<u8>, threshold: u8) -> bool {
+    a.0.iter().zip(b.0.iter()).all(|(x, y)| x.abs_diff(*y) <= threshold)
+}
+
+/// 对相似的像素求平均，差异超过阈值的像素（边缘和真实运动）不参与
+fn average_similar<'a>(
+    center: &Rgba<u8>,
+    candidates: impl Iterator<Item = &'a Rgba<u8>>,
+    threshold: u8,
+) -> Rgba<u8> {
+    let mut sums = center.0.map(|v| v as u32);
+    let mut count = 1;
+    for pixel in candidates.filter(|p| similar(center, p, threshold)) {
+        for (sum, &v) in sums.iter_mut().zip(pixel.0.iter()) {
+            *sum += v as u32;
+        }
+        count += 1;
+    }
+    Rgba(sums.map(|sum| ((sum + count / 2) / count) as u8))
+}
+
+/// 降噪
+///
+/// 先在时间上与前后两帧中相似的像素平均，再做保边的空间平均（只平均3x3邻域中
+/// 相似的像素），去除摄像头噪点和JPEG伪影，同时保留真实的运动和边缘。
+fn denoise(frames: &mut [DecodedFrame], strength: Denoise) {
+    let (threshold, spatial_passes) = denoise_params(strength);
+
+    let originals: Vec<RgbaImage> = frames.iter().map(|frame| frame.image.clone()).collect();
+    for (i, frame) in frames.iter_mut().enumerate() {
+        let neighbors: Vec<&RgbaImage> = [i.checked_sub(1), Some(i + 1)]
+            .into_iter()
+            .flatten()
+            .filter_map(|j| originals.get(j))
+            .collect();
+        for (x, y, pixel) in frame.image.enumerate_pixels_mut() {
+            *pixel = average_similar(&originals[i][(x, y)], neighbors.iter().map(|image| &image[(x, y)]), threshold);
+        }
+    }
+
+    for _ in 0..spatial_passes {
+        for frame in frames.iter_mut() {
+            let source = frame.image.clone();
+            let (width, height) = source.dimensions();
+            for (x, y, pixel) in frame.image.enumerate_pixels_mut() {
+                let neighbors = (y.saturating_sub(1)..(y + 2).min(height))
+                    .flat_map(|ny| (x.saturating_sub(1)..(x + 2).min(width)).map(move |nx| (nx, ny)))
+                    .filter(|&position| position != (x, y))
+                    .map(|(nx, ny)| &source[(nx, ny)]);
+                *pixel = average_similar(&source[(x, y)], neighbors, threshold);
+            }
+        }
+    }
+}
+
+/// 差值哈希：缩小为灰度图后比较每行相邻像素的亮度
+fn perceptual_hash(image: &RgbaImage) -> Vec<bool> {
+    let small = imageops::resize(image, HASH_SIZE + 1, HASH_SIZE, FilterType::Triangle);
+    let luma = |x, y| {
+        let p = small.get_pixel(x, y);
+        p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114
+    };
+    (0..HASH_SIZE)
+        .flat_map(|y| (0..HASH_SIZE).map(move |x| (x, y)))
+        .map(|(x, y)| luma(x, y) < luma(x + 1, y))
+        .collect()
+}
+
+fn hamming(a: &[bool], b: &[bool]) -> u32 {
+    a.iter().zip(b).filter(|(x, y)| x != y).count() as u32
+}
+
+/// 聚类近似重复的帧
+///
+/// 按感知哈希找出候选，逐像素确认后用组内第一帧替换其余帧，即使它们不相邻
+/// （例如循环的加载动画）。GIF无法引用之前的帧，替换后再合并相邻的相同帧并累加延迟。
+fn dedupe_frames(frames: &mut Vec<DecodedFrame>, reporter: &dyn Reporter) {
+    let total = frames.len();
+    // 每组代表帧的序号和哈希
+    let mut representatives: Vec<(usize, Vec<bool>)> = Vec::new();
+    for i in 0..total {
+        let hash = perceptual_hash(&frames[i].image);
+        let found = representatives.iter().find(|(index, rep_hash)| {
+            hamming(&hash, rep_hash) <= HASH_DISTANCE
+                && native::is_near_identical(&frames[*index].image, &frames[i].image)
+        });
+        match found {
+            Some(&(index, _)) => frames[i].image = frames[index].image.clone(),
+            None => representatives.push((i, hash)),
+        }
+    }
+
+    merge_identical(frames);
+
+    reporter.log(&format!(
+        "帧聚类: {} 帧归为 {} 组，合并相邻重复帧后剩余 {} 帧",
+        total,
+        representatives.len(),
+        frames.len()
+    ));
+}
+
+/// 完全相同的相邻帧合并为一帧，延迟累加，显示效果不变
+pub(crate) fn merge_identical(frames: &mut Vec<DecodedFrame>) {
+    let mut merged: Vec<DecodedFrame> = Vec::with_capacity(frames.len());
+    for frame in frames.drain(..) {
+        match merged.last_mut() {
+            Some(last) if last.image == frame.image => last.delay = last.delay.saturating_add(frame.delay),
+            _ => merged.push(frame),
+        }
+    }
+    *frames = merged;
+}
@@ -0,0 +1,2081 @@
+pub mod analysis;
+pub mod batch;
+pub mod cancel;
+pub mod compose;
+pub mod config;
+mod error;
+pub mod explain;
+pub mod ffi;
+mod filters;
+mod lossless;
+mod motion;
+pub mod native;
+mod optimizer;
+pub mod options;
+pub mod paths;
+pub mod preset;
+pub mod progress;
+pub mod quality;
+mod quantize;
+pub mod report;
+pub mod reporter;
+mod text;
+#[cfg(feature = "async")]
+pub mod async_api;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "preview")]
+pub mod preview;
+#[cfg(feature = "webm")]
+mod webm;
+
+pub use error::{GifError, WithPath};
+
+use cancel::{run_command, run_command_until};
+use options::{Backend, CompressionOptions, DropMode, Optimizer, OutputFormat, Prefer};
+use progress::{ProgressEvent, Stage, StopReason};
+use reporter::{ConsoleReporter, Reporter, SharedReporter};
+use report::CompressionReport;
+use image::{codecs::gif::GifDecoder, AnimationDecoder, ImageDecoder};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use serde::{Deserialize, Serialize};
+use tempfile::{NamedTempFile, TempDir};
+
+/// 第二轮细搜索中lossy级别的步长
+const FINE_LOSSY_STEP: u32 = 15;
+
+/// 模拟退火搜索空间中lossy级别的步长
+const ANNEAL_LOSSY_STEP: u32 = 10;
+
+/// 获取文件大小（KB）
+fn get_file_size_kb<P: AsRef<Path>>(path: P) -> Result<f64, GifError> {
+    let metadata = fs::metadata(path)?;
+    Ok(metadata.len() as f64 / 1024.0)
+}
+
+/// 获取GIF的帧数
+fn get_frame_count<P: AsRef<Path>>(path: P) -> Result<usize, GifError> {
+    let file = File::open(&path).with_path(&path)?;
+    let decoder = GifDecoder::new(BufReader::new(file))?;
+    let frames = decoder.into_frames();
+    let count = frames.count();
+    Ok(count)
+}
+
+/// 获取GIF的尺寸
+fn get_dimensions<P: AsRef<Path>>(path: P) -> Result<(u32, u32), GifError> {
+    let file = File::open(&path).with_path(&path)?;
+    let decoder = GifDecoder::new(BufReader::new(file))?;
+    Ok(decoder.dimensions())
+}
+
+/// 检查输出文件是否满足大小和尺寸限制
+fn check_constraints<P: AsRef<Path>>(path: P, options: &CompressionOptions) -> Result<(), GifError> {
+    let size = get_file_size_kb(&path)?;
+    if size > options.target_size_kb {
+        return Err(GifError::SizeLimitExceeded { size_kb: size, limit_kb: options.target_size_kb });
+    }
+    
+    let (width, height) = get_dimensions(&path)?;
+    if options.max_width.is_some_and(|max| width > max)
+        || options.max_height.is_some_and(|max| height > max)
+    {
+        return Err(GifError::DimensionLimitExceeded {
+            width,
+            height,
+            max_width: options.max_width,
+            max_height: options.max_height,
+        });
+    }
+    
+    Ok(())
+}
+
+/// 严格模式下检查输出文件，不满足限制时删除输出文件
+fn enforce_constraints<P: AsRef<Path>>(path: P, options: &CompressionOptions) -> Result<(), GifError> {
+    if !options.strict {
+        return Ok(());
+    }
+    if let Err(e) = check_constraints(&path, options) {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// 获取GIF的平均帧率
+fn get_average_fps<P: AsRef<Path>>(path: P) -> Result<f64, GifError> {
+    let file = File::open(&path).with_path(&path)?;
+    let decoder = GifDecoder::new(BufReader::new(file))?;
+    
+    let mut count = 0usize;
+    let mut total_ms = 0.0;
+    for frame in decoder.into_frames() {
+        let (numer, denom) = frame?.delay().numer_denom_ms();
+        let delay_ms = numer as f64 / denom as f64;
+        // 浏览器会把小于20ms的帧延迟按100ms处理
+        total_ms += if delay_ms < 20.0 { 100.0 } else { delay_ms };
+        count += 1;
+    }
+    
+    if count == 0 {
+        return Err(GifError::NoFrames);
+    }
+    Ok(count as f64 * 1000.0 / total_ms)
+}
+
+/// 根据尺寸和颜色限制生成gifsicle参数
+fn transform_args(options: &CompressionOptions) -> Vec<String> {
+    let mut args = Vec::new();
+    
+    if options.max_width.is_some() || options.max_height.is_some() {
+        let dimension = |value: Option<u32>| value.map_or("_".to_string(), |v| v.to_string());
+        args.push("--resize-fit".to_string());
+        args.push(format!("{}x{}", dimension(options.max_width), dimension(options.max_height)));
+    }
+    
+    if let Some(colors) = options.colors {
+        args.push("--colors".to_string());
+        args.push(colors.clamp(2, 256).to_string());
+        if options.deband {
+            // gifsicle只在减色时抖动
+            args.push("--dither".to_string());
+        }
+    }
+    
+    args
+}
+
+/// 根据循环次数生成gifsicle参数
+fn loop_arg(options: &CompressionOptions) -> String {
+    match options.loop_count {
+        None => "--loopcount=forever".to_string(),
+        Some(0) => "--no-loopcount".to_string(), // 没有循环扩展时只播放一次
+        Some(count) => format!("--loopcount={}", count),
+    }
+}
+
+/// 创建本次压缩的工作目录
+fn new_work_dir(temp_dir: Option<&Path>) -> Result<TempDir, GifError> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("gif_compressor_");
+    match temp_dir {
+        Some(dir) => builder.tempdir_in(dir),
+        None => builder.tempdir(),
+    }
+    .map_err(|source| GifError::TempDirFailed {
+        path: temp_dir.map_or_else(std::env::temp_dir, Path::to_path_buf),
+        source,
+    })
+}
+
+/// 在指定目录（未指定时为系统临时目录）中创建临时文件
+fn new_temp_file(temp_dir: Option<&Path>) -> std::io::Result<NamedTempFile> {
+    match temp_dir {
+        Some(dir) => NamedTempFile::new_in(dir),
+        None => NamedTempFile::new(),
+    }
+}
+
+/// 提取GIF帧并保存为新的GIF
+fn extract_frames<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_path: P,
+    output_path: Q,
+    skip: usize,
+    delay: u16,
+    options: &CompressionOptions,
+) -> Result<(), GifError> {
+    // 打开输入文件
+    let file = File::open(&input_path).with_path(&input_path)?;
+    let decoder = GifDecoder::new(BufReader::new(file))?;
+    
+    // 提取所有帧
+    let frames = decoder.into_frames().collect_frames()?;
+    let total_frames = frames.len();
+    
+    // 根据skip参数选择帧，混合模式下每组帧平均为一帧；
+    // delay为0时每个保留帧的延迟为被合并帧的延迟之和
+    let frame_delay = |frame: &image::Frame| {
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        (numer as f64 / denom as f64 / 10.0).round() as u32
+    };
+    let mut selected_frames = Vec::new();
+    let mut delays = Vec::new();
+    for i in (0..total_frames).step_by(skip) {
+        let group = &frames[i..(i + skip).min(total_frames)];
+        match options.drop_mode {
+            DropMode::Blend if group.len() > 1 => {
+                selected_frames.push(image::Frame::new(filters::blend(group.iter().map(image::Frame::buffer))));
+            }
+            _ => selected_frames.push(frames[i].clone()),
+        }
+        delays.push(match delay {
+            0 => group.iter().map(frame_delay).sum(),
+            delay => delay as u32,
+        });
+    }
+    
+    // 插帧模式下在相邻的保留帧之间插入合成的中间帧，两者平分原来的帧延迟
+    if options.drop_mode == DropMode::Interpolate && skip > 1 && selected_frames.len() > 1 {
+        let mut interpolated = Vec::with_capacity(selected_frames.len() * 2);
+        let mut interpolated_delays = Vec::with_capacity(delays.len() * 2);
+        for (pair, &delay) in selected_frames.windows(2).zip(&delays) {
+            interpolated.push(pair[0].clone());
+            interpolated.push(image::Frame::new(motion::midpoint(pair[0].buffer(), pair[1].buffer())));
+            interpolated_delays.push(delay / 2);
+            interpolated_delays.push(delay - delay / 2);
+        }
+        interpolated.extend(selected_frames.pop());
+        interpolated_delays.extend(delays.pop());
+        selected_frames = interpolated;
+        delays = interpolated_delays;
+    }
+    
+    if selected_frames.is_empty() {
+        // 至少保留一帧
+        if !frames.is_empty() {
+            selected_frames.push(frames[0].clone());
+            delays.push(match delay {
+                0 => frame_delay(&frames[0]),
+                delay => delay as u32,
+            });
+        } else {
+            return Err(GifError::NoFrames);
+        }
+    }
+    
+    // 由于GIF格式复杂，我们使用临时目录和gifsicle来完成帧提取和合并
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("gif_frames_");
+    let frames_dir = match options.temp_dir() {
+        Some(dir) => builder.tempdir_in(dir),
+        None => builder.tempdir(),
+    }
+    .map_err(|source| GifError::TempDirFailed {
+        path: options.temp_dir().map_or_else(std::env::temp_dir, Path::to_path_buf),
+        source,
+    })?;
+    
+    // 保存所有选择的帧到临时目录，并收集路径字符串
+    let mut frame_paths = Vec::new();
+    for (i, frame) in selected_frames.iter().enumerate() {
+        let frame_path = frames_dir.path().join(format!("frame_{}.gif", i));
+        let frame_file = File::create(&frame_path).with_path(&frame_path)?;
+        let mut frame_writer = BufWriter::new(frame_file);
+        
+        // 使用image库保存单帧GIF
+        frame.buffer().write_to(&mut frame_writer, image::ImageOutputFormat::Gif)?;
+        
+        // 保存路径字符串
+        frame_paths.push(frame_path.to_string_lossy().to_string());
+    }
+    
+    // 使用gifsicle合并帧
+    let output_path_str = output_path.as_ref().to_string_lossy().to_string();
+    
+    // 检查gifsicle是否存在
+    match Command::new("gifsicle").arg("--version").output() {
+        Ok(_) => {}, // 命令存在，继续执行
+        Err(_) => return Err(GifError::GifsicleNotFound),
+    }
+    
+    // 构建优化的参数列表
+    let mut gifsicle_args = Vec::with_capacity(frame_paths.len() * 3 + 8);
+    
+    // 添加优化选项
+    gifsicle_args.push("--no-warnings".to_string());        // 减少不必要的输出
+    gifsicle_args.push("--no-conserve-memory".to_string()); // 使用更多内存提高速度
+    gifsicle_args.push("--no-app-extensions".to_string());  // 移除应用扩展数据
+    gifsicle_args.push("--no-comments".to_string());        // 移除注释
+    gifsicle_args.push("--no-names".to_string());           // 移除名称元数据
+    gifsicle_args.push("-o".to_string());
+    gifsicle_args.push(output_path_str);
+    gifsicle_args.push(loop_arg(options));
+    
+    // 添加所有帧路径，每帧前面是它自己的延迟
+    for (path, &delay) in frame_paths.iter().zip(&delays) {
+        let delay = (delay.min(u16::MAX as u32) as u16).max(options.min_delay());
+        gifsicle_args.push("--delay".to_string());
+        gifsicle_args.push(delay.to_string());
+        gifsicle_args.push(path.clone());
+    }
+    
+    // 执行gifsicle命令
+    let _output = run_command(Command::new("gifsicle").args(&gifsicle_args), &options.cancel)?;
+    
+    // 检查命令是否成功
+    if !_output.status.success() {
+        return Err(GifError::command_failed("gifsicle", &gifsicle_args, &_output));
+    }
+    
+    Ok(())
+}
+
+/// 表示临时文件 - 优化版本
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    fn new(temp_file: NamedTempFile) -> Result<Self, std::io::Error> {
+        // 使用 keep() 来获取路径并取消自动删除
+        match temp_file.keep() {
+            Ok((_file, path)) => Ok(Self { path }), // keep 成功，返回 Self
+            Err(persist_error) => Err(persist_error.error), // keep 失败，返回 IO 错误
+        }
+    }
+    
+    fn path_str(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+    
+    // 当不再需要文件时手动删除
+    fn cleanup(&self) -> std::io::Result<()> {
+        if self.path.exists() {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+// Clone实现，允许复制TempFile
+impl Clone for TempFile {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+        }
+    }
+}
+
+/// 压缩策略结构
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Strategy {
+    /// 每隔多少帧取1帧
+    pub skip: usize,
+    /// 抽帧后的帧延迟(1/100秒)，0表示保留被合并帧的延迟之和
+    pub delay: u16,
+}
+
+/// 策略处理结果
+struct StrategyResult {
+    size: f64,
+    file: Option<TempFile>,
+    success: bool,
+    strategy: Strategy,
+    lossy: Option<u32>,
+}
+
+impl StrategyResult {
+    fn failed(strategy: Strategy) -> Self {
+        Self {
+            size: f64::MAX,
+            file: None,
+            success: false,
+            strategy,
+            lossy: None,
+        }
+    }
+}
+
+/// 共享状态结构体，用于线程间通信
+struct SharedState {
+    // 是否找到满足目标大小的结果
+    found_target: AtomicBool,
+    // 当前已找到的最佳大小，初始值为正无穷；非负浮点数的位模式与数值顺序一致
+    best_size: std::sync::atomic::AtomicU64,
+}
+
+impl SharedState {
+    fn new() -> Self {
+        Self {
+            found_target: AtomicBool::new(false),
+            best_size: std::sync::atomic::AtomicU64::new(f64::INFINITY.to_bits()),
+        }
+    }
+    
+    // 更新最佳大小（如果提供的大小更小）
+    fn update_best_size(&self, size: f64) -> bool {
+        let size_bits = size.to_bits();
+        let mut current = self.best_size.load(Ordering::Relaxed);
+        
+        loop {
+            // 如果新大小不比当前更好，不更新
+            if size_bits >= current {
+                return false;
+            }
+            
+            // 尝试原子更新，成功则返回true
+            match self.best_size.compare_exchange(
+                current,
+                size_bits,
+                Ordering::SeqCst,
+                Ordering::Relaxed
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+    
+    // 获取当前最佳大小
+    fn get_best_size(&self) -> f64 {
+        let bits = self.best_size.load(Ordering::Relaxed);
+        f64::from_bits(bits)
+    }
+    
+    // 设置已找到目标
+    fn set_found_target(&self) {
+        self.found_target.store(true, Ordering::Relaxed);
+    }
+    
+    // 检查是否已找到目标
+    fn is_target_found(&self) -> bool {
+        self.found_target.load(Ordering::Relaxed)
+    }
+}
+
+/// 处理单个策略
+fn process_strategy(
+    input_path: &str,
+    strategy: Strategy,
+    options: &CompressionOptions,
+    thread_id: usize,
+    shared_state: &SharedState,
+    reporter: &SharedReporter,
+) -> StrategyResult {
+    let target_size_kb = options.target_size_kb;
+    
+    // 创建跟踪输出的记录器
+    let output_prefix = format!("线程 {}: ", thread_id);
+    let log = |msg: &str| {
+        let message = format!("{}{}", output_prefix, msg);
+        reporter.log(&message);
+    };
+    
+    // 如果已经找到目标，立即返回
+    if shared_state.is_target_found() {
+        log("已有其他线程找到满足条件的结果，提前退出");
+        return StrategyResult::failed(strategy);
+    }
+    
+    let skip = strategy.skip;
+    let delay = strategy.delay;
+    
+    // 预计剩余帧数
+    let expected_frames = match get_frame_count(input_path) {
+        Ok(count) => (count as f64 / skip as f64).ceil() as usize,
+        Err(_) => 0,
+    };
+    
+    log(&format!("策略: 保留约 {} 帧 (每 {} 帧取1帧), 帧延迟: {}ms", 
+                expected_frames, skip, delay));
+    
+    // 使用image库提取帧
+    let temp_frames = match new_temp_file(options.temp_dir()) {
+        Ok(file) => match TempFile::new(file) {
+            Ok(tf) => tf,
+            Err(e) => {
+                log(&format!("  创建临时文件(keep)失败: {}", e));
+                return StrategyResult::failed(strategy);
+            }
+        },
+        Err(_) => {
+            log("  创建 NamedTempFile 失败");
+            return StrategyResult::failed(strategy);
+        }
+    };
+    
+    // 检查是否有线程已经找到结果
+    if shared_state.is_target_found() {
+        log("已有其他线程找到满足条件的结果，提前退出");
+        return StrategyResult::failed(strategy);
+    }
+    
+    let temp_frames_path = temp_frames.path_str();
+    
+    if let Err(e) = extract_frames(input_path, &temp_frames_path, skip, delay, options) {
+        log(&format!("  帧提取失败: {}", e));
+        return StrategyResult::failed(strategy);
+    }
+    
+    // 检查是否有线程已经找到结果
+    if shared_state.is_target_found() {
+        log("已有其他线程找到满足条件的结果，提前退出");
+        return StrategyResult::failed(strategy);
+    }
+    
+    // 检查提取是否成功
+    match get_file_size_kb(&temp_frames_path) {
+        Ok(size) if size < 1.0 => {
+            log("  帧提取生成的文件过小");
+            return StrategyResult::failed(strategy);
+        },
+        Ok(_) => {}, // 文件大小正常，继续处理
+        Err(_) => {
+            log("  无法读取提取的帧大小");
+            return StrategyResult::failed(strategy);
+        }
+    };
+    
+    // 优化提取后的帧
+    let temp_frames_opt = match new_temp_file(options.temp_dir()) {
+        Ok(file) => match TempFile::new(file) {
+            Ok(tf) => tf,
+            Err(e) => {
+                log(&format!("  创建优化临时文件(keep)失败: {}", e));
+                return StrategyResult::failed(strategy);
+            }
+        },
+        Err(_) => {
+            log("  创建优化 NamedTempFile 失败");
+            return StrategyResult::failed(strategy);
+        }
+    };
+    
+    // 检查是否有线程已经找到结果
+    if shared_state.is_target_found() {
+        log("已有其他线程找到满足条件的结果，提前退出");
+        return StrategyResult::failed(strategy);
+    }
+    
+    let temp_frames_opt_path = temp_frames_opt.path_str();
+    
+    let mut args = vec!["-O3".to_string()];
+    args.extend(transform_args(options));
+    args.extend([temp_frames_path.clone(), "-o".to_string(), temp_frames_opt_path.clone()]);
+    
+    let stop = || shared_state.is_target_found();
+    let _output = match run_command_until(Command::new("gifsicle").args(&args), &options.cancel, stop) {
+        Ok(Some(output)) => output,
+        Ok(None) => {
+            log("已有其他线程找到满足条件的结果，终止正在运行的gifsicle");
+            return StrategyResult::failed(strategy);
+        }
+        Err(_) => {
+            log("  执行gifsicle帧优化失败");
+            return StrategyResult::failed(strategy);
+        }
+    };
+    
+    if !_output.status.success() {
+        log("  帧优化失败");
+        return StrategyResult::failed(strategy);
+    }
+    
+    // 清理第一个临时文件，不再需要它
+    let _ = temp_frames.cleanup();
+    
+    let frames_size = match get_file_size_kb(&temp_frames_opt_path) {
+        Ok(size) => size,
+        Err(_) => {
+            log("  无法读取优化后帧大小");
+            return StrategyResult::failed(strategy);
+        }
+    };
+    
+    log(&format!("  抽帧后大小: {:.2} KB", frames_size));
+    reporter.event(&ProgressEvent::Candidate {
+        stage: Stage::Grid,
+        skip,
+        lossy: 0,
+        colors: options.colors.unwrap_or(256),
+        scale: 1.0,
+        size_kb: frames_size,
+    });
+    
+    if frames_size <= target_size_kb {
+        log("  已达到目标大小!");
+        // 设置标志通知其他线程已找到满足条件的结果
+        shared_state.set_found_target();
+        return StrategyResult {
+            size: frames_size,
+            file: Some(temp_frames_opt),
+            success: true,
+            strategy,
+            lossy: None,
+        };
+    }
+    
+    // 跟踪当前策略下的最佳结果
+    let mut best_size = frames_size;
+    let mut best_file = Some(temp_frames_opt);
+    let mut best_lossy = None;
+    
+    // 批量尝试不同的lossy值
+    // 创建临时文件和对应的lossy级别
+    let lossy_levels: &[u32] = if options.lossy_allowed() {
+        &options.lossy_levels
+    } else {
+        &[]
+    };
+    
+    // 每次处理两个lossy级别，平衡进程创建开销和并行效率
+    let chunk_size = 2;
+    
+    for chunk in lossy_levels.chunks(chunk_size) {
+        // 已取消时不再尝试，保留的文件由主线程清理
+        if options.cancel.is_cancelled() {
+            log("压缩已取消，提前退出");
+            break;
+        }
+        if options.cancel.is_expired() {
+            log("超出时间限制，使用这个策略目前最好的结果");
+            break;
+        }
+        
+        // 先检查是否有线程已经找到结果
+        if shared_state.is_target_found() {
+            log("已有其他线程找到满足条件的结果，提前退出");
+            return StrategyResult {
+                size: best_size,
+                file: best_file,
+                success: true,
+                strategy,
+                lossy: best_lossy,
+            };
+        }
+        
+        let mut temp_files = Vec::with_capacity(chunk.len());
+        let mut results = Vec::with_capacity(chunk.len());
+        
+        // 创建这一批次的临时文件
+        for &level in chunk {
+            match new_temp_file(options.temp_dir()) {
+                Ok(file) => {
+                    // 修改 TempFile::new 调用，处理 Result
+                    match TempFile::new(file) {
+                        Ok(tf) => temp_files.push((level, tf)),
+                        Err(e) => log(&format!("  创建lossy={}临时文件(keep)失败: {}", level, e)),
+                    }
+                },
+                Err(_) => {
+                    log(&format!("  创建lossy={} NamedTempFile 失败", level));
+                }
+            }
+        }
+        
+        let current_best_path = match &best_file {
+            Some(file) => file.path_str(),
+            None => break,
+        };
+        
+        // 处理这一批次的lossy级别
+        for (level, temp_file) in &temp_files {
+            let temp_path = temp_file.path_str();
+            
+            // 创建lossy参数
+            let lossy_arg = format!("--lossy={}", level);
+            
+            // 优化的gifsicle命令参数
+            let args = vec![
+                "-O3", 
+                "--no-warnings",
+                "--no-conserve-memory", 
+                "--no-comments", 
+                "--no-names",
+                &lossy_arg,
+                &current_best_path, 
+                "-o", 
+                &temp_path
+            ];
+            
+            // 输出已经大于所有策略目前最小的结果时不可能被选中
+            let limit = || best_size.min(shared_state.get_best_size());
+            let stop = || stop() || exceeds_size(&temp_file.path, limit());
+            let stopped = |reason| ProgressEvent::CandidateStopped {
+                stage: Stage::Grid,
+                skip,
+                lossy: *level,
+                colors: options.colors.unwrap_or(256),
+                scale: 1.0,
+                reason,
+            };
+            match run_command_until(Command::new("gifsicle").args(&args), &options.cancel, stop) {
+                Ok(None) if shared_state.is_target_found() => {
+                    log(&format!("  已有其他线程找到满足条件的结果，终止lossy={}的gifsicle", level));
+                    reporter.event(&stopped(StopReason::Cancelled));
+                    break;
+                }
+                Ok(None) => {
+                    log(&format!("  lossy={} 已超过 {:.2} KB，提前终止", level, limit()));
+                    reporter.event(&stopped(StopReason::TooLarge));
+                }
+                Err(GifError::TimedOut) => {
+                    reporter.event(&stopped(StopReason::TimedOut));
+                }
+                Ok(Some(output)) if output.status.success() => {
+                    match get_file_size_kb(&temp_path) {
+                        Ok(size) => {
+                            log(&format!("  抽帧 + lossy={} 后大小: {:.2} KB", level, size));
+                            reporter.event(&ProgressEvent::Candidate {
+                                stage: Stage::Grid,
+                                skip,
+                                lossy: *level,
+                                colors: options.colors.unwrap_or(256),
+                                scale: 1.0,
+                                size_kb: size,
+                            });
+                            results.push((*level, size));
+                        },
+                        Err(_) => {
+                            log(&format!("  无法读取lossy={}压缩后大小", level));
+                        }
+                    }
+                },
+                _ => {
+                    log(&format!("  lossy={}压缩失败", level));
+                }
+            };
+        }
+        
+        // 处理这一批次的结果
+        for (level, size) in results.iter() {
+            if *size <= target_size_kb {
+                log(&format!("  lossy={} 已达到目标大小!", level));
+                
+                // 找到对应的临时文件
+                if let Some((_, temp_file)) = temp_files.iter().find(|(l, _)| *l == *level) {
+                    // 如果当前结果比之前的好，替换并清理旧文件
+                    if best_size > *size {
+                        if let Some(old_file) = best_file.take() {
+                            let _ = old_file.cleanup(); // 清理旧文件
+                        }
+                        best_size = *size;
+                        best_file = Some(temp_file.clone());
+                        best_lossy = Some(*level);
+                    }
+                }
+                
+                // 设置标志通知其他线程已找到满足条件的结果
+                shared_state.set_found_target();
+                break;
+            } else if *size < best_size {
+                // 找到对应的临时文件
+                if let Some((_, temp_file)) = temp_files.iter().find(|(l, _)| *l == *level) {
+                    // 替换旧文件并清理
+                    if let Some(old_file) = best_file.take() {
+                        let _ = old_file.cleanup(); // 清理旧文件
+                    }
+                    best_size = *size;
+                    best_file = Some(temp_file.clone());
+                    best_lossy = Some(*level);
+                }
+            }
+        }
+        
+        // 如果已找到目标，不再处理更多批次
+        if shared_state.is_target_found() {
+            break;
+        }
+        
+        // 清理这批次中未被选中的临时文件
+        for (_level, temp_file) in &temp_files {
+            if let Some(best) = &best_file {
+                if best.path != temp_file.path {
+                    let _ = temp_file.cleanup();
+                }
+            } else {
+                let _ = temp_file.cleanup();
+            }
+        }
+    }
+    
+    // Prepare the result to be returned
+    let final_best_file_for_return = best_file.clone(); // Clone the Option<TempFile>
+
+    // If we have a best file locally, prevent its Drop implementation from running
+    // because we are transferring responsibility via the clone.
+    if let Some(local_best) = best_file {
+         std::mem::forget(local_best);
+    }
+
+    // Return the result containing the cloned Option<TempFile>
+    StrategyResult {
+        size: best_size,
+        file: final_best_file_for_return,
+        success: true, // Assuming we found at least one valid result
+        strategy,
+        lossy: best_lossy,
+    }
+}
+
+/// 文件是否已经大于 `limit_kb`，用于在gifsicle运行中监视输出文件
+fn exceeds_size(path: &Path, limit_kb: f64) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.len() as f64 / 1024.0 > limit_kb)
+}
+
+/// 对基础优化后的文件做一次gifsicle压缩，返回输出文件和大小
+///
+/// 运行中输出文件超过 `limit_kb` 时终止gifsicle并返回None，这个候选已经不可能被选中。
+fn gifsicle_candidate(
+    input_path: &str,
+    scale: f64,
+    colors: Option<u16>,
+    lossy: u32,
+    limit_kb: f64,
+    options: &CompressionOptions,
+) -> Result<Option<(NamedTempFile, f64)>, GifError> {
+    let output = new_temp_file(options.temp_dir())?;
+    let mut args = vec!["-O3".to_string(), "--no-warnings".to_string()];
+    if lossy > 0 {
+        args.push(format!("--lossy={}", lossy));
+    }
+    if let Some(colors) = colors {
+        args.push("--colors".to_string());
+        args.push(colors.to_string());
+    }
+    if scale < 1.0 {
+        args.push("--scale".to_string());
+        args.push(scale.to_string());
+    }
+    args.extend([input_path.to_string(), "-o".to_string(), output.path().to_string_lossy().to_string()]);
+    
+    let stop = || exceeds_size(output.path(), limit_kb);
+    let Some(result) = run_command_until(Command::new("gifsicle").args(&args), &options.cancel, stop)? else {
+        return Ok(None);
+    };
+    if !result.status.success() {
+        return Err(GifError::command_failed("gifsicle", &args, &result));
+    }
+    let size = get_file_size_kb(output.path())?;
+    Ok(Some((output, size)))
+}
+
+/// 候选结果是否优于当前最佳：已达到目标的结果不再被替换，否则取较小的一个
+fn is_better_candidate(size: f64, best: Option<f64>, target_size_kb: f64) -> bool {
+    match best {
+        None => true,
+        Some(best) if best <= target_size_kb => false,
+        Some(best) => size <= target_size_kb || size < best,
+    }
+}
+
+/// 时间用完时放弃这一步，输出文件中已经是目前为止最好的结果
+fn skip_on_timeout<T: Default>(result: Result<T, GifError>) -> Result<T, GifError> {
+    match result {
+        Err(GifError::TimedOut) => Ok(T::default()),
+        result => result,
+    }
+}
+
+/// 按 [`is_better_candidate`] 的规则，候选结果超过多大时不可能被选中
+fn candidate_limit(best: Option<f64>, target_size_kb: f64) -> f64 {
+    best.map_or(f64::INFINITY, |best| best.max(target_size_kb))
+}
+
+/// 一个gifsicle候选的压缩参数
+#[derive(Debug, Clone)]
+struct Candidate {
+    stage: Stage,
+    /// 抽帧并做过基础优化的输入文件
+    source: String,
+    /// None表示不抽帧
+    strategy: Option<Strategy>,
+    scale: f64,
+    /// None表示不再减色
+    colors: Option<u16>,
+    lossy: u32,
+}
+
+impl Candidate {
+    fn skip(&self) -> usize {
+        self.strategy.map_or(1, |strategy| strategy.skip)
+    }
+    
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if self.skip() > 1 {
+            parts.push(format!("每 {} 帧取1帧", self.skip()));
+        }
+        if self.scale < 1.0 {
+            parts.push(format!("缩放到 {:.0}%", self.scale * 100.0));
+        }
+        if let Some(colors) = self.colors {
+            parts.push(format!("{} 色", colors));
+        }
+        parts.push(format!("lossy={}", self.lossy));
+        parts.join(", ")
+    }
+    
+    /// 颜色数为None时使用 `max_colors`
+    fn event(&self, max_colors: u16, size_kb: f64) -> ProgressEvent {
+        ProgressEvent::Candidate {
+            stage: self.stage,
+            skip: self.skip(),
+            lossy: self.lossy,
+            colors: self.colors.unwrap_or(max_colors),
+            scale: self.scale,
+            size_kb,
+        }
+    }
+    
+    fn stopped(&self, max_colors: u16, reason: StopReason) -> ProgressEvent {
+        ProgressEvent::CandidateStopped {
+            stage: self.stage,
+            skip: self.skip(),
+            lossy: self.lossy,
+            colors: self.colors.unwrap_or(max_colors),
+            scale: self.scale,
+            reason,
+        }
+    }
+    
+    fn selected(&self, max_colors: u16, size_kb: f64) -> ProgressEvent {
+        ProgressEvent::Selected {
+            stage: self.stage,
+            skip: self.skip(),
+            lossy: self.lossy,
+            colors: self.colors.unwrap_or(max_colors),
+            scale: self.scale,
+            size_kb,
+        }
+    }
+}
+
+/// 候选搜索中目前最好的结果
+struct Best {
+    file: NamedTempFile,
+    size: f64,
+    candidate: Candidate,
+}
+
+impl Best {
+    /// 把结果写入输出文件
+    fn save(&self, output_path: &Path, options: &CompressionOptions, reporter: &dyn Reporter) -> Result<(), GifError> {
+        fs::copy(self.file.path(), output_path).with_path(output_path)?;
+        let max_colors = options.colors.unwrap_or(256).clamp(2, 256);
+        reporter.event(&self.candidate.selected(max_colors, self.size));
+        Ok(())
+    }
+}
+
+/// 并行压缩一组候选，按 [`is_better_candidate`] 的规则更新目前最好的结果
+///
+/// `candidates` 按画质从高到低排列，每次并行压缩最多 `threads` 个，输出超过 `limit_kb`
+/// 的候选提前终止。时间用完时被终止的候选直接跳过，由调用方结束搜索。
+fn run_candidates(
+    candidates: &[Candidate],
+    limit_kb: f64,
+    mut best: Option<Best>,
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<Option<Best>, GifError> {
+    let max_colors = options.colors.unwrap_or(256).clamp(2, 256);
+    for chunk in candidates.chunks(options.threads.max(1)) {
+        options.cancel.check()?;
+        let results: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = chunk
+                .iter()
+                .map(|candidate| scope.spawn(move || {
+                    gifsicle_candidate(&candidate.source, candidate.scale, candidate.colors, candidate.lossy, limit_kb, options)
+                }))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().unwrap_or(Err(GifError::NoValidResults))).collect()
+        });
+        
+        for (candidate, result) in chunk.iter().zip(results) {
+            let (file, size) = match result {
+                Ok(Some(result)) => result,
+                Ok(None) => {
+                    reporter.log(&format!("{} 已超过 {:.2} KB，提前终止", candidate.describe(), limit_kb));
+                    reporter.event(&candidate.stopped(max_colors, StopReason::TooLarge));
+                    continue;
+                }
+                Err(GifError::Cancelled) => return Err(GifError::Cancelled),
+                Err(GifError::TimedOut) => {
+                    reporter.event(&candidate.stopped(max_colors, StopReason::TimedOut));
+                    continue;
+                }
+                Err(e) => {
+                    reporter.log(&format!("  {} 压缩失败: {}", candidate.describe(), e));
+                    continue;
+                }
+            };
+            reporter.log(&format!("{} 后大小: {:.2} KB", candidate.describe(), size));
+            reporter.event(&candidate.event(max_colors, size));
+            
+            if is_better_candidate(size, best.as_ref().map(|best| best.size), options.target_size_kb) {
+                best = Some(Best { file, size, candidate: candidate.clone() });
+            }
+        }
+    }
+    Ok(best)
+}
+
+/// 按抽帧策略提取帧并做基础优化，作为之后lossy和缩放的输入
+fn prepare_strategy_source(
+    input_path: &str,
+    strategy: Strategy,
+    options: &CompressionOptions,
+) -> Result<NamedTempFile, GifError> {
+    let extracted = new_temp_file(options.temp_dir())?;
+    extract_frames(input_path, extracted.path(), strategy.skip, strategy.delay, options)?;
+    
+    let optimized = new_temp_file(options.temp_dir())?;
+    let mut args = vec!["-O3".to_string()];
+    args.extend(transform_args(options));
+    args.extend([
+        extracted.path().to_string_lossy().to_string(),
+        "-o".to_string(),
+        optimized.path().to_string_lossy().to_string(),
+    ]);
+    
+    let output = run_command(Command::new("gifsicle").args(&args), &options.cancel)?;
+    if !output.status.success() {
+        return Err(GifError::command_failed("gifsicle", &args, &output));
+    }
+    Ok(optimized)
+}
+
+/// 在第一轮选中的结果附近做第二轮细搜索
+///
+/// 第一轮的抽帧间隔和lossy级别间隔较大，选中的结果常常比目标小很多，画质损失也多于必要。
+/// `candidates` 按画质从高到低排列，并行压缩后选中第一个达到目标的，写入输出文件并返回它的
+/// 抽帧策略、lossy级别和大小。
+fn fine_search(
+    input_path: &str,
+    base_path: &Path,
+    candidates: &[(Option<Strategy>, u32)],
+    output_path: &Path,
+    options: &CompressionOptions,
+    reporter: &SharedReporter,
+) -> Result<Option<(Option<Strategy>, u32, f64)>, GifError> {
+    if candidates.is_empty() || options.cancel.is_expired() {
+        return Ok(None);
+    }
+    reporter.log(&format!("\n在选中结果附近细搜索 {} 个候选...", candidates.len()));
+    
+    // 每个抽帧间隔只提取一次帧
+    let mut prepared: Vec<(usize, NamedTempFile)> = Vec::new();
+    let mut searched = Vec::with_capacity(candidates.len());
+    for &(strategy, lossy) in candidates {
+        let source = match strategy {
+            None => base_path.to_path_buf(),
+            Some(strategy) => match prepared.iter().find(|(skip, _)| *skip == strategy.skip) {
+                Some((_, file)) => file.path().to_path_buf(),
+                None => {
+                    let file = prepare_strategy_source(input_path, strategy, options)?;
+                    let path = file.path().to_path_buf();
+                    prepared.push((strategy.skip, file));
+                    path
+                }
+            },
+        };
+        searched.push(Candidate {
+            stage: Stage::Fine,
+            source: source.to_string_lossy().to_string(),
+            strategy,
+            scale: 1.0,
+            colors: None,
+            lossy,
+        });
+    }
+    
+    // 候选按画质从高到低排列，第一个达到目标的画质最好
+    let best = run_candidates(&searched, options.target_size_kb, None, options, reporter.as_ref())?;
+    match best.filter(|best| best.size <= options.target_size_kb) {
+        Some(best) => {
+            best.save(output_path, options, reporter.as_ref())?;
+            reporter.log(&format!("细搜索找到画质更好的结果: {:.2} KB", best.size));
+            Ok(Some((best.candidate.strategy, best.candidate.lossy, best.size)))
+        }
+        None => {
+            reporter.log("细搜索没有找到画质更好且达到目标的结果");
+            Ok(None)
+        }
+    }
+}
+
+/// [`refine_to_target`] 的结果
+#[derive(Default)]
+struct Refined {
+    /// 找到的更小结果的大小和lossy级别
+    result: Option<(f64, Option<u32>)>,
+    /// 是否一直尝试到了画质下限
+    at_floor: bool,
+}
+
+/// 抽帧和lossy的搜索都达不到目标时，从最接近目标的结果开始按固定顺序继续降级
+///
+/// 依次减少颜色数、在最少颜色数下提高lossy级别（不超过 `lossy_levels` 中的最大值）、
+/// 最后缩小尺寸，每一步并行尝试该步的所有级别，选中第一个达到目标的；降到画质下限
+/// （最少的颜色数、最大的lossy级别、最小的缩放比例）仍达不到时使用最小的结果。
+/// 抽帧策略不变，找到比 `size` 更小的结果时写入输出文件。
+fn refine_to_target(
+    input_path: &str,
+    base_path: &Path,
+    params: (Option<Strategy>, Option<u32>),
+    size: f64,
+    output_path: &Path,
+    options: &CompressionOptions,
+    reporter: &SharedReporter,
+) -> Result<Refined, GifError> {
+    let (strategy, lossy) = params;
+    let lossy = lossy.unwrap_or(0);
+    let max_colors = options.colors.unwrap_or(256).clamp(2, 256);
+    let color_levels: Vec<u16> = native::COLOR_LEVELS.iter().copied().filter(|&c| c < max_colors).collect();
+    let floor_colors = color_levels.last().copied();
+    let lossy_levels: Vec<u32> = if options.lossy_allowed() {
+        options.lossy_levels.iter().copied().filter(|&level| level > lossy).collect()
+    } else {
+        Vec::new()
+    };
+    let max_lossy = lossy_levels.last().copied().unwrap_or(lossy);
+    
+    reporter.log("\n仍超出目标大小，依次减少颜色数、提高lossy级别、缩小尺寸...");
+    let source = match strategy {
+        Some(strategy) => Some(prepare_strategy_source(input_path, strategy, options)?),
+        None => None,
+    };
+    let source_path = source.as_ref().map_or(base_path, |file| file.path()).to_string_lossy().to_string();
+    let candidate = |scale, colors, lossy| Candidate {
+        stage: Stage::Refine,
+        source: source_path.clone(),
+        strategy,
+        scale,
+        colors,
+        lossy,
+    };
+    
+    let steps: Vec<Vec<Candidate>> = vec![
+        color_levels.iter().map(|&colors| candidate(1.0, Some(colors), lossy)).collect(),
+        lossy_levels.iter().map(|&level| candidate(1.0, floor_colors, level)).collect(),
+        native::SCALE_LEVELS[1..].iter().map(|&scale| candidate(scale, floor_colors, max_lossy)).collect(),
+    ];
+    
+    let mut best = None;
+    for step in steps.iter().filter(|step| !step.is_empty()) {
+        if options.cancel.is_expired() {
+            break;
+        }
+        best = run_candidates(step, size, best, options, reporter.as_ref())?;
+        if best.as_ref().is_some_and(|best| best.size <= options.target_size_kb) {
+            break;
+        }
+    }
+    // 只有时间用完或达到目标时才会提前结束，否则最后一步已经尝试了最小的缩放比例
+    let at_floor = !options.cancel.is_expired() && best.as_ref().is_none_or(|best| best.size > options.target_size_kb);
+    
+    match best {
+        Some(best) if best.size < size => {
+            best.save(output_path, options, reporter.as_ref())?;
+            if best.size <= options.target_size_kb {
+                reporter.log(&format!("已达到目标大小! 大小: {:.2} KB", best.size));
+            }
+            let lossy = best.candidate.lossy;
+            Ok(Refined { result: Some((best.size, (lossy > 0).then_some(lossy))), at_floor })
+        }
+        _ => Ok(Refined { result: None, at_floor }),
+    }
+}
+
+/// 按 `--prefer` 指定的顺序搜索
+///
+/// 优先保留的方面最后降级，每一组候选只有最先降级的方面不同并行压缩，
+/// 组与组之间按顺序尝试，选中第一个达到目标大小的候选；都达不到时输出最小的结果。
+///
+/// `frame_levels` 中的None表示不抽帧，直接使用基础优化的结果。
+fn optimize_preferred(
+    input_path: &str,
+    base_path: &str,
+    output_path: &Path,
+    prefer: Prefer,
+    frame_levels: &[Option<Strategy>],
+    options: &CompressionOptions,
+    reporter: &SharedReporter,
+) -> Result<(Option<Strategy>, Option<u32>), GifError> {
+    let mut lossy_levels = vec![0];
+    if options.lossy_allowed() {
+        lossy_levels.extend(options.lossy_levels.iter().copied());
+    }
+    
+    reporter.log(match prefer {
+        Prefer::Frames => "优先保留帧数: 先提高lossy级别，再缩小尺寸，最后才抽帧",
+        Prefer::Quality => "优先保留画质: 先抽帧，再缩小尺寸，最后才使用有损压缩",
+        Prefer::Resolution => "优先保留尺寸: 先提高lossy级别，再抽帧，最后才缩小尺寸",
+    });
+    
+    // 每个抽帧策略只提取一次
+    let mut sources: HashMap<usize, NamedTempFile> = HashMap::new();
+    let mut best: Option<Best> = None;
+    for group in prefer.groups(frame_levels, &lossy_levels, &native::SCALE_LEVELS) {
+        options.cancel.check()?;
+        if options.cancel.is_expired() {
+            break;
+        }
+        for strategy in group.iter().filter_map(|(strategy, _, _)| *strategy) {
+            if sources.contains_key(&strategy.skip) {
+                continue;
+            }
+            match prepare_strategy_source(input_path, strategy, options) {
+                Ok(file) => {
+                    sources.insert(strategy.skip, file);
+                }
+                Err(GifError::Cancelled) => return Err(GifError::Cancelled),
+                Err(e) => reporter.log(&format!("  每 {} 帧取1帧时帧提取失败: {}", strategy.skip, e)),
+            }
+        }
+        
+        let source_path = |strategy: Option<Strategy>| match strategy {
+            None => Some(base_path.to_string()),
+            Some(strategy) => sources.get(&strategy.skip).map(|file| file.path().to_string_lossy().to_string()),
+        };
+        
+        let candidates: Vec<Candidate> = group
+            .iter()
+            .filter_map(|&(strategy, lossy, scale)| {
+                Some(Candidate { stage: Stage::Prefer, source: source_path(strategy)?, strategy, scale, colors: None, lossy })
+            })
+            .collect();
+        let limit = candidate_limit(best.as_ref().map(|best| best.size), options.target_size_kb);
+        best = run_candidates(&candidates, limit, best, options, reporter.as_ref())?;
+        
+        if best.as_ref().is_some_and(|best| best.size <= options.target_size_kb) {
+            break;
+        }
+    }
+    
+    let best = best.ok_or_else(|| options.cancel.no_results())?;
+    best.save(output_path, options, reporter.as_ref())?;
+    let (size, strategy, lossy) = (best.size, best.candidate.strategy, best.candidate.lossy);
+    if size <= options.target_size_kb {
+        reporter.log(&format!("已达到目标大小! 最终大小: {:.2} KB", size));
+    } else {
+        reporter.log(&format!("\n无法达到目标大小 {} KB。", options.target_size_kb));
+        reporter.log(&format!("最接近的大小是 {:.2} KB，已保存到输出文件。", size));
+    }
+    Ok((strategy, (lossy > 0).then_some(lossy)))
+}
+
+/// 按 `--optimizer` 在抽帧间隔、lossy级别和颜色数的联合空间中搜索
+///
+/// 每个抽帧间隔只在第一次用到时提取一次帧。`keep_all_allowed` 为true时也尝试不抽帧，
+/// 直接使用基础优化的结果。
+fn optimize_search(
+    input_path: &str,
+    base_path: &str,
+    output_path: &Path,
+    keep_all_allowed: bool,
+    strategies: &[Strategy],
+    options: &CompressionOptions,
+    reporter: &SharedReporter,
+) -> Result<(Option<Strategy>, Option<u32>), GifError> {
+    let mut frame_levels: Vec<Option<Strategy>> = Vec::new();
+    if keep_all_allowed {
+        frame_levels.push(None);
+    }
+    frame_levels.extend(strategies.iter().copied().map(Some));
+    
+    let max_lossy = if options.lossy_allowed() {
+        options.lossy_levels.iter().copied().max().unwrap_or(0)
+    } else {
+        0
+    };
+    let max_colors = options.colors.unwrap_or(256).clamp(2, 256);
+    let mut colors = vec![max_colors];
+    colors.extend(native::COLOR_LEVELS.iter().copied().filter(|&c| c < max_colors));
+    let space = optimizer::Space {
+        skips: frame_levels.iter().map(|strategy| strategy.map_or(1, |strategy| strategy.skip)).collect(),
+        lossy: (0..=max_lossy).step_by(ANNEAL_LOSSY_STEP as usize).collect(),
+        colors,
+    };
+    
+    let sources: Vec<Mutex<Option<NamedTempFile>>> = frame_levels.iter().map(|_| Mutex::new(None)).collect();
+    let result = optimizer::search(&space, options, reporter.as_ref(), |point| {
+        let source = match frame_levels[point.skip] {
+            None => base_path.to_string(),
+            Some(strategy) => {
+                let mut source = sources[point.skip].lock().unwrap_or_else(PoisonError::into_inner);
+                match source.as_ref() {
+                    Some(file) => file.path().to_string_lossy().to_string(),
+                    None => {
+                        let file = prepare_strategy_source(input_path, strategy, options)?;
+                        let path = file.path().to_string_lossy().to_string();
+                        *source = Some(file);
+                        path
+                    }
+                }
+            }
+        };
+        // 基础优化已经按 --colors 限制了颜色数
+        let colors = (point.colors > 0).then(|| space.colors[point.colors]);
+        // 超出目标的大小也用于计分和拟合模型，不提前终止
+        gifsicle_candidate(&source, 1.0, colors, space.lossy[point.lossy], f64::INFINITY, options)?
+            .ok_or_else(|| options.cancel.stop_reason())
+    })?;
+    
+    let (point, file, size) = result.ok_or_else(|| options.cancel.no_results())?;
+    fs::copy(file.path(), output_path).with_path(output_path)?;
+    let (skip, lossy, colors) = (space.skips[point.skip], space.lossy[point.lossy], space.colors[point.colors]);
+    reporter.log(&format!("选中: 每 {} 帧取1帧, lossy={}, {} 色", skip, lossy, colors));
+    reporter.event(&ProgressEvent::Selected { stage: Stage::Optimizer, skip, lossy, colors, scale: 1.0, size_kb: size });
+    if size <= options.target_size_kb {
+        reporter.log(&format!("已达到目标大小! 最终大小: {:.2} KB", size));
+    } else {
+        reporter.log(&format!("\n无法达到目标大小 {} KB。", options.target_size_kb));
+        reporter.log(&format!("最接近的大小是 {:.2} KB，已保存到输出文件。", size));
+    }
+    Ok((frame_levels[point.skip], (lossy > 0).then_some(lossy)))
+}
+
+/// 保留所有帧时的搜索
+///
+/// 按缩放比例、颜色数的顺序逐级放宽，每一级并行尝试所有lossy级别，
+/// 选中第一个达到目标大小的组合中lossy最小的一个；都达不到时输出最小的结果。
+/// 返回选中的lossy级别。
+fn optimize_keep_all_frames(
+    base_path: &str,
+    output_path: &Path,
+    options: &CompressionOptions,
+    reporter: &SharedReporter,
+) -> Result<Option<u32>, GifError> {
+    let max_colors = options.colors.unwrap_or(256).clamp(2, 256);
+    let mut color_levels = vec![max_colors];
+    color_levels.extend(native::COLOR_LEVELS.iter().copied().filter(|&c| c < max_colors));
+    
+    let mut lossy_levels = vec![0];
+    if options.lossy_allowed() {
+        lossy_levels.extend(options.lossy_levels.iter().copied());
+    }
+    
+    reporter.log(&format!("保留所有帧: 依次尝试 {} 个缩放比例、{} 个颜色数和 {} 个lossy级别",
+        native::SCALE_LEVELS.len(), color_levels.len(), lossy_levels.len()));
+    
+    let mut best: Option<Best> = None;
+    for &scale in &native::SCALE_LEVELS {
+        for &colors in &color_levels {
+            options.cancel.check()?;
+            if options.cancel.is_expired() {
+                break;
+            }
+            // 基础优化已经应用了 --colors，颜色数不变时不再减色；同一级中lossy越小越好
+            let candidates: Vec<Candidate> = lossy_levels
+                .iter()
+                .map(|&lossy| Candidate {
+                    stage: Stage::KeepAll,
+                    source: base_path.to_string(),
+                    strategy: None,
+                    scale,
+                    colors: (colors < max_colors).then_some(colors),
+                    lossy,
+                })
+                .collect();
+            let limit = candidate_limit(best.as_ref().map(|best| best.size), options.target_size_kb);
+            best = run_candidates(&candidates, limit, best, options, reporter.as_ref())?;
+            
+            if best.as_ref().is_some_and(|best| best.size <= options.target_size_kb) {
+                break;
+            }
+        }
+        if best.as_ref().is_some_and(|best| best.size <= options.target_size_kb) || options.cancel.is_expired() {
+            break;
+        }
+    }
+    
+    let best = best.ok_or_else(|| options.cancel.no_results())?;
+    best.save(output_path, options, reporter.as_ref())?;
+    let (size, lossy) = (best.size, best.candidate.lossy);
+    if size <= options.target_size_kb {
+        reporter.log(&format!("已达到目标大小! 最终大小: {:.2} KB", size));
+    } else {
+        reporter.log(&format!("\n保留所有帧时无法达到目标大小 {} KB。", options.target_size_kb));
+        reporter.log(&format!("最接近的大小是 {:.2} KB，已保存到输出文件。", size));
+    }
+    Ok((lossy > 0).then_some(lossy))
+}
+
+/// 优化GIF到目标大小 (并行版本)
+///
+/// 返回最终选中的抽帧策略和lossy级别，None表示未抽帧或未使用有损压缩。
+pub fn optimize_gif<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_path: P,
+    output_path: Q,
+    options: &CompressionOptions,
+    reporter: &SharedReporter,
+) -> Result<(Option<Strategy>, Option<u32>), GifError> {
+    let target_size_kb = options.target_size_kb;
+    let min_frame_percent = options.min_frame_percent;
+    
+    // 获取初始文件大小
+    let original_size = get_file_size_kb(&input_path)?;
+    reporter.log(&format!("原始大小: {:.2} KB", original_size));
+    
+    // 获取初始帧数
+    let original_frame_count = get_frame_count(&input_path)?;
+    reporter.log(&format!("原始帧数: {}", original_frame_count));
+    
+    // 根据帧率上限计算最小抽帧间隔，大于1时不能直接使用未抽帧的结果
+    let mut min_skip = options.min_skip(get_average_fps(&input_path)?);
+    let max_drop_skip = options.max_drop_skip(original_frame_count);
+    if options.keep_all_frames && min_skip > 1 {
+        reporter.log("保留所有帧: 忽略帧率上限");
+    } else if min_skip > max_drop_skip {
+        reporter.log(&format!("帧率上限需要每 {} 帧取1帧，超出 --max-drop 允许的比例，改为每 {} 帧取1帧",
+            min_skip, max_drop_skip));
+        min_skip = max_drop_skip;
+    }
+    let keep_all_allowed = min_skip <= 1 || options.keep_all_frames;
+    
+    // 如果已经小于目标大小且无需缩放或减色，直接复制
+    if original_size <= target_size_kb && keep_all_allowed && !options.has_transforms() {
+        reporter.log("文件已经小于目标大小，无需压缩");
+        fs::copy(&input_path, &output_path).with_path(&output_path)?;
+        return Ok((None, None));
+    }
+    
+    // 检查gifsicle是否存在
+    match Command::new("gifsicle").arg("--version").output() {
+        Ok(_) => {}, // 命令存在，继续执行
+        Err(_) => return Err(GifError::GifsicleNotFound),
+    }
+    
+    if options.no_lossy {
+        reporter.log("不使用有损压缩，只抽帧");
+    } else if let Some(region) = options.protect_region {
+        reporter.log(&format!("保护区域 {}: gifsicle后端不使用有损压缩，只抽帧", region));
+    }
+    
+    // 本次压缩的工作目录，所有临时文件都放在这里，最后一个使用它的线程退出时整体删除
+    let work_dir = Arc::new(new_work_dir(options.temp_dir())?);
+    let mut options = options.clone();
+    options.temp_dir = Some(work_dir.path().to_path_buf());
+    let options = &options;
+    
+    // 基础优化 - 使用gifsicle的最高优化级别和更多高级选项
+    let temp_file = new_temp_file(options.temp_dir())?;
+    let temp_file_opt = TempFile::new(temp_file)?;
+    let temp_file_opt_path = temp_file_opt.path_str();
+    
+    // 保存基础优化文件路径的副本，以便后续可能需要作为备选
+    let temp_file_opt_path_copy = PathBuf::from(&temp_file_opt_path);
+    
+    // 使用String而不是&str，避免生命周期问题
+    let input_path_str = input_path.as_ref().to_string_lossy().to_string();
+    
+    // 构建优化的参数列表
+    let mut args: Vec<String> = vec![
+        "-O3".to_string(),                     // 最高级别优化
+        "--no-warnings".to_string(),           // 不显示警告
+        "--no-conserve-memory".to_string(),    // 使用更多内存以提高速度
+        "--no-comments".to_string(),           // 删除注释以减小文件大小
+        "--no-names".to_string(),              // 删除图像和对象名称
+        "--careful".to_string(),               // 更慎重的优化，避免损坏文件
+    ];
+    args.extend(transform_args(options));      // 尺寸和颜色限制
+    if options.loop_count.is_some() {
+        args.push(loop_arg(options));          // 循环次数
+    }
+    args.push(input_path_str.clone());         // 输入文件
+    args.push("-o".to_string());               // 输出选项
+    args.push(temp_file_opt_path.clone());     // 输出文件
+    
+    let _output = run_command(Command::new("gifsicle").args(&args), &options.cancel)?;
+    
+    if !_output.status.success() {
+        return Err(GifError::command_failed("gifsicle", &args, &_output));
+    }
+    
+    let opt_size = get_file_size_kb(&temp_file_opt_path)?;
+    reporter.log(&format!("基础优化后大小: {:.2} KB", opt_size));
+    reporter.event(&ProgressEvent::BaseOptimized { size_kb: opt_size });
+    let base_selected = |size_kb| ProgressEvent::Selected {
+        stage: Stage::Base,
+        skip: 1,
+        lossy: 0,
+        colors: options.colors.unwrap_or(256),
+        scale: 1.0,
+        size_kb,
+    };
+    
+    // 如果已经达到目标大小，直接复制
+    if opt_size <= target_size_kb && keep_all_allowed {
+        fs::copy(&temp_file_opt_path, &output_path).with_path(&output_path)?;
+        reporter.event(&base_selected(opt_size));
+        enforce_constraints(&output_path, options)?;
+        return Ok((None, None));
+    }
+    
+    if options.keep_all_frames {
+        let lossy = optimize_keep_all_frames(&temp_file_opt_path, output_path.as_ref(), options, reporter)?;
+        let _ = temp_file_opt.cleanup();
+        enforce_constraints(&output_path, options)?;
+        return Ok((None, lossy));
+    }
+    
+    // 计算最小保留帧数
+    let min_frames = std::cmp::max(3, (original_frame_count as f64 * min_frame_percent as f64 / 100.0) as usize);
+    
+    // 构建抽帧策略
+    let mut strategies = Vec::new();
+    
+    // 从2抽1开始，最多抽到保留最小帧数
+    let max_skip = (((original_frame_count as f64) / (min_frames as f64)).ceil() as usize).clamp(2, 10);
+    let max_skip = std::cmp::max(max_skip, min_skip);
+    let make_strategy = |skip: usize| Strategy {
+        skip,
+        delay: std::cmp::max(
+            ((100.0 * skip as f64) / original_frame_count as f64) as u16 + 10,
+            options.min_delay(),
+        ),
+    };
+    
+    for skip in std::cmp::max(2, min_skip)..=max_skip {
+        strategies.push(make_strategy(skip));
+    }
+    
+    // 如果帧数很多，尝试更激进的抽帧策略
+    if original_frame_count > 30 {
+        let aggressive_skips = [max_skip + 5, max_skip + 10];
+        for &skip in &aggressive_skips {
+            if original_frame_count / skip >= min_frames {
+                strategies.push(make_strategy(skip));
+            }
+        }
+    }
+    
+    // 无论目标大小多紧，抽掉的帧数都不超过 --max-drop
+    if let Some(max_drop) = options.max_drop {
+        strategies.retain(|strategy| strategy.skip <= max_drop_skip);
+        reporter.log(&format!("最多抽掉 {} 的帧: 最多每 {} 帧取1帧", max_drop, max_drop_skip));
+    }
+    
+    if options.optimizer != Optimizer::Grid {
+        if options.prefer.is_some() {
+            reporter.log("使用 --optimizer 时忽略 --prefer");
+        }
+        let result = optimize_search(
+            &input_path_str,
+            &temp_file_opt_path,
+            output_path.as_ref(),
+            keep_all_allowed,
+            &strategies,
+            options,
+            reporter,
+        )?;
+        let _ = temp_file_opt.cleanup();
+        enforce_constraints(&output_path, options)?;
+        return Ok(result);
+    }
+    
+    if let Some(prefer) = options.prefer {
+        let mut frame_levels: Vec<Option<Strategy>> = Vec::new();
+        if keep_all_allowed {
+            frame_levels.push(None);
+        }
+        frame_levels.extend(strategies.iter().copied().map(Some));
+        let result = optimize_preferred(
+            &input_path_str,
+            &temp_file_opt_path,
+            output_path.as_ref(),
+            prefer,
+            &frame_levels,
+            options,
+            reporter,
+        )?;
+        let _ = temp_file_opt.cleanup();
+        enforce_constraints(&output_path, options)?;
+        return Ok(result);
+    }
+    
+    // 限制线程数，不超过策略数量
+    let thread_count = std::cmp::min(options.threads, strategies.len());
+    reporter.log(&format!("开始使用 {} 个线程并行处理 {} 个压缩策略...", thread_count, strategies.len()));
+    
+    // 创建通道以接收处理结果
+    let (tx, rx): (Sender<StrategyResult>, Receiver<StrategyResult>) = mpsc::channel();
+    
+    // 创建线程池
+    let input_path_arc = Arc::new(input_path_str);
+    let mut handles = Vec::new();
+    
+    // 创建共享状态
+    let shared_state = Arc::new(SharedState::new());
+    let options_arc = Arc::new(options.clone());
+    
+    // 设置初始最佳大小为基础优化后的大小
+    if keep_all_allowed {
+        shared_state.update_best_size(opt_size);
+    }
+    
+    for (i, chunk) in strategies.into_iter().enumerate() {
+        let tx_clone = tx.clone();
+        let input_path_clone = Arc::clone(&input_path_arc);
+        let shared_state_clone = Arc::clone(&shared_state);
+        let options_clone = Arc::clone(&options_arc);
+        let reporter_clone = Arc::clone(reporter);
+        let work_dir_clone = Arc::clone(&work_dir);
+        
+        // 创建线程处理这个策略
+        let handle = thread::spawn(move || {
+            let _work_dir = work_dir_clone;
+            let result = process_strategy(
+                &input_path_clone,
+                chunk,
+                &options_clone,
+                i + 1,
+                &shared_state_clone,
+                &reporter_clone
+            );
+            
+            // 如果这是一个好的结果，更新共享状态中的最佳大小
+            if result.success && result.size < shared_state_clone.get_best_size() {
+                let is_better = shared_state_clone.update_best_size(result.size);
+                
+                // 如果我们的结果被接受为更好的结果，并且达到了目标大小，设置found_target标志
+                if is_better && result.size <= target_size_kb {
+                    shared_state_clone.set_found_target();
+                }
+            }
+            
+            // 发送结果到主线程
+            let _ = tx_clone.send(result);
+        });
+        
+        handles.push(handle);
+    }
+    
+    // 丢弃发送者以允许接收者知道何时所有发送者都已完成
+    drop(tx);
+    
+    // 等待并收集所有策略的结果
+    let mut best_size = opt_size;
+    let mut at_floor = false;
+    let mut best_file: Option<TempFile> = Some(temp_file_opt);
+    let mut best_params = (None, None);
+    let mut found_solution = false;
+    let mut files_to_cleanup: Vec<TempFile> = Vec::new(); // <--- 新增：待清理文件列表
+    
+    // 帧率超出上限时，基础优化结果不能作为候选
+    if !keep_all_allowed {
+        best_size = f64::MAX;
+        files_to_cleanup.extend(best_file.take());
+    }
+    
+    // 从通道接收结果
+    for result in rx.iter() {
+        if !result.success {
+            // 如果结果的文件存在，也要加入清理列表
+            if let Some(file) = result.file {
+                files_to_cleanup.push(file);
+            }
+            continue;
+        }
+        
+        // 确保 result.file 是 Some
+        let result_file = match result.file {
+            Some(file) => file,
+            None => continue, // 没有文件，无法比较或使用
+        };
+
+        if result.size <= target_size_kb {
+            // 清理之前的最佳文件（如果有的话），将其加入待清理列表
+            if let Some(old_file) = best_file.take() {
+                // let _ = old_file.cleanup(); // <--- 移除：不再立即清理
+                files_to_cleanup.push(old_file); // <--- 修改：加入待清理列表
+            }
+            
+            best_size = result.size;
+            best_file = Some(result_file); // 使用 result_file
+            best_params = (Some(result.strategy), result.lossy);
+            found_solution = true;
+            reporter.log(&format!("找到达到目标大小的策略! 大小: {:.2} KB", best_size));
+            // 设置标志，以便其他线程可以提前退出
+            shared_state.set_found_target();
+            break; // 提前退出循环，不再处理其他结果
+        } else if result.size < best_size {
+            // 清理之前的最佳文件（如果有的话），将其加入待清理列表
+            if let Some(old_file) = best_file.take() {
+                // let _ = old_file.cleanup(); // <--- 移除：不再立即清理
+                files_to_cleanup.push(old_file); // <--- 修改：加入待清理列表
+            }
+            
+            best_size = result.size;
+            best_file = Some(result_file); // 使用 result_file
+            best_params = (Some(result.strategy), result.lossy);
+        } else {
+            // 该结果不比当前最佳结果好，将其文件加入待清理列表
+            // if let Some(file) = result.file { // <--- 移除
+            //     let _ = file.cleanup(); // <--- 移除
+            // } // <--- 移除
+            files_to_cleanup.push(result_file); // <--- 修改：加入待清理列表
+        }
+    }
+    
+    // 取消时等待所有线程终止子进程后再返回，工作目录随之删除
+    if options.cancel.is_cancelled() {
+        reporter.log("压缩已取消，等待所有线程退出...");
+        for handle in handles {
+            let _ = handle.join();
+        }
+        return Err(GifError::Cancelled);
+    }
+    
+    // 我们不再等待所有线程完成
+    // 如果已经找到满足条件的结果，其他线程会自动退出
+    // 如果我们想要优雅地等待，可以设置一个超时
+    if found_solution {
+        reporter.log("已找到满足条件的结果，不再等待其他线程");
+    } else {
+        reporter.log("尚未找到满足目标大小的结果，等待所有线程完成...");
+        // 等待所有线程完成
+        for handle in handles {
+            let _ = handle.join();
+        }
+    }
+    
+    // 使用找到的最佳文件
+    if let Some(best) = best_file {
+        reporter.log("\n复制最佳结果到输出文件...");
+        
+        // 添加文件存在性验证
+        reporter.log(&format!("检查文件存在性: {}", &best.path.display()));
+        if !best.path.exists() {
+            reporter.log("⚠️ 警告：文件不存在，尝试使用备份");
+            
+            // 如果基础优化文件还存在（备份），尝试直接使用它
+            if keep_all_allowed && temp_file_opt_path_copy.exists() {
+                reporter.log(&format!("使用基础优化文件作为备选: {}", &temp_file_opt_path_copy.display()));
+                fs::copy(&temp_file_opt_path_copy, &output_path).with_path(&output_path)?;
+                reporter.event(&base_selected(opt_size));
+                best_params = (None, None);
+            } else {
+                reporter.log("❌ 错误：基础优化文件也不存在");
+                return Err(GifError::TempFileMissing { path: best.path.clone() });
+            }
+        } else {
+            // 文件存在，执行正常复制
+            fs::copy(&best.path, &output_path).with_path(&output_path)?;
+            reporter.event(&match best_params {
+                (Some(strategy), lossy) => ProgressEvent::Selected {
+                    stage: Stage::Grid,
+                    skip: strategy.skip,
+                    lossy: lossy.unwrap_or(0),
+                    colors: options.colors.unwrap_or(256),
+                    scale: 1.0,
+                    size_kb: best_size,
+                },
+                (None, _) => base_selected(best_size),
+            });
+        }
+        
+        // 第一轮的网格较粗，在选中结果附近找画质更好、仍达到目标的结果
+        if found_solution
+            && let (Some(strategy), lossy) = best_params
+        {
+            let lossy = lossy.unwrap_or(0);
+            let max_lossy = if options.lossy_allowed() {
+                options.lossy_levels.iter().copied().max().unwrap_or(0)
+            } else {
+                0
+            };
+            // 按画质从高到低: 少抽一帧（lossy可以略高），再是同样的抽帧间隔下更低的lossy
+            let mut candidates: Vec<(Option<Strategy>, u32)> = Vec::new();
+            let fewer = strategy.skip - 1;
+            if (fewer >= 2 && fewer >= min_skip) || (fewer == 1 && keep_all_allowed) {
+                let fewer = (fewer > 1).then(|| make_strategy(fewer));
+                for level in [lossy.saturating_sub(FINE_LOSSY_STEP), lossy, lossy + FINE_LOSSY_STEP] {
+                    if level <= max_lossy.max(lossy) && !candidates.contains(&(fewer, level)) {
+                        candidates.push((fewer, level));
+                    }
+                }
+            }
+            if lossy > 0 {
+                candidates.push((Some(strategy), lossy.saturating_sub(FINE_LOSSY_STEP)));
+            }
+            
+            if let Some((strategy, lossy, size)) = skip_on_timeout(fine_search(
+                &input_path_arc,
+                &temp_file_opt_path_copy,
+                &candidates,
+                output_path.as_ref(),
+                options,
+                reporter,
+            ))? {
+                best_size = size;
+                best_params = (strategy, (lossy > 0).then_some(lossy));
+            }
+        }
+        
+        // 仍超出目标时继续降级，直到达到目标或降到画质下限
+        if best_size > target_size_kb {
+            let refined = skip_on_timeout(refine_to_target(
+                &input_path_arc,
+                &temp_file_opt_path_copy,
+                best_params,
+                best_size,
+                output_path.as_ref(),
+                options,
+                reporter,
+            ))?;
+            at_floor = refined.at_floor;
+            if let Some((size, lossy)) = refined.result {
+                best_size = size;
+                best_params.1 = lossy;
+            }
+        }
+        
+        let final_size = get_file_size_kb(&output_path)?;
+        reporter.log(&format!("完成! 最终大小: {:.2} KB", final_size));
+
+        // 清理临时文件
+        reporter.log("清理临时文件...");
+        let _ = best.cleanup(); // 手动清理最佳文件
+        for file_to_clean in files_to_cleanup {
+            let _ = file_to_clean.cleanup(); // 手动清理其他文件
+        }
+        
+        // 严格模式下无法满足限制时直接报错，不保留输出文件
+        enforce_constraints(&output_path, options)?;
+
+    } else {
+        // 如果 best_file 是 None (例如基础优化后就满足条件，但逻辑上应该总有 best_file)
+        // 确保清理所有可能产生的临时文件
+        reporter.log("清理临时文件...");
+        for file_to_clean in files_to_cleanup {
+            let _ = file_to_clean.cleanup();
+        }
+        return Err(options.cancel.no_results());
+    }
+    
+    // 如果还是没达到目标大小，给出提示
+    if best_size > target_size_kb {
+        reporter.log(&format!("\n无法达到目标大小 {} KB。", target_size_kb));
+        reporter.log(&format!("最接近的大小是 {:.2} KB，已保存到输出文件。", best_size));
+    }
+    if best_size > target_size_kb && at_floor {
+        reporter.log(&format!("已降到画质下限（{} 色、允许的最大lossy级别、缩小到 {:.0}%）。",
+            native::COLOR_LEVELS[native::COLOR_LEVELS.len() - 1],
+            native::SCALE_LEVELS[native::SCALE_LEVELS.len() - 1] * 100.0));
+    }
+    
+    Ok(best_params)
+}
+
+/// 所有帧几乎相同时返回用于输出的单帧图像
+fn static_still(
+    data: &[u8],
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<Option<image::RgbaImage>, GifError> {
+    if !options.collapse_static {
+        return Ok(None);
+    }
+    let still = native::static_frame(data, options)?;
+    if still.is_some() {
+        reporter.log("所有帧几乎相同，输出单帧图像");
+    }
+    Ok(still)
+}
+
+/// 静态GIF输出为单帧图像，输出路径以 `.png` 结尾时输出PNG，返回是否已输出
+fn collapse_static<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_path: P,
+    output_path: Q,
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<bool, GifError> {
+    let Some(image) = static_still(&fs::read(&input_path).with_path(&input_path)?, options, reporter)? else {
+        return Ok(false);
+    };
+    
+    let is_png = output_path
+        .as_ref()
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("png"));
+    if is_png {
+        image.save_with_format(&output_path, image::ImageFormat::Png)?;
+        // 尺寸已经按限制缩放，只需要检查大小
+        let size = get_file_size_kb(&output_path)?;
+        if options.strict && size > options.target_size_kb {
+            let _ = fs::remove_file(&output_path);
+            return Err(GifError::SizeLimitExceeded { size_kb: size, limit_kb: options.target_size_kb });
+        }
+    } else {
+        fs::write(&output_path, native::encode_still(&image, options)?).with_path(&output_path)?;
+        enforce_constraints(&output_path, options)?;
+    }
+    
+    reporter.log(&format!("完成! 最终大小: {:.2} KB", get_file_size_kb(&output_path)?));
+    Ok(true)
+}
+
+/// 按选项中的输出格式压缩文件，返回压缩报告
+pub fn compress<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_path: P,
+    output_path: Q,
+    options: &CompressionOptions,
+) -> Result<CompressionReport, GifError> {
+    compress_with_reporter(input_path, output_path, options, Arc::new(ConsoleReporter))
+}
+
+/// 压缩文件，日志和进度事件输出到指定的 `Reporter`
+pub fn compress_with_reporter<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_path: P,
+    output_path: Q,
+    options: &CompressionOptions,
+    reporter: SharedReporter,
+) -> Result<CompressionReport, GifError> {
+    options.cancel.check()?;
+    
+    // 检查输入文件是否存在
+    if !input_path.as_ref().exists() {
+        return Err(GifError::InputFileNotFound { path: input_path.as_ref().to_path_buf() });
+    }
+    
+    // 如果线程数为0，使用系统CPU核心数
+    let mut options = options.clone();
+    if options.threads == 0 {
+        options.threads = num_cpus::get();
+    }
+    
+    let original_size_kb = get_file_size_kb(&input_path)?;
+    reporter.event(&ProgressEvent::Started { original_size_kb });
+    
+    // 纯Rust后端在缩放后的帧上检测，这里只处理gifsicle后端
+    if options.format == OutputFormat::Gif
+        && options.backend == Backend::Gifsicle
+        && !options.lossless
+        && options.detect_text
+        && options.protect_region.is_none()
+    {
+        let frames = native::decode_frames(&fs::read(&input_path).with_path(&input_path)?)?;
+        options.protect_region = text::detect_text_region(&frames[0].image);
+        text::log_detected(options.protect_region, reporter.as_ref());
+    }
+    
+    // gifsicle后端需要逐帧处理时先写入临时GIF，之后以它作为输入
+    let filtered = if options.format == OutputFormat::Gif
+        && options.backend == Backend::Gifsicle
+        && !options.lossless
+        && filters::enabled(&options)
+    {
+        Some(filters::write_filtered(&input_path, &options, reporter.as_ref())?)
+    } else {
+        None
+    };
+    let source = filtered.as_ref().map_or(input_path.as_ref(), |file| file.path());
+    
+    let (strategy, lossy) = match options.format {
+        OutputFormat::Gif if options.lossless => {
+            lossless::compress(&input_path, &output_path, &options, reporter.as_ref())?;
+            (None, None)
+        }
+        OutputFormat::Gif if options.collapse_static
+            && collapse_static(&input_path, &output_path, &options, reporter.as_ref())? => (None, None),
+        OutputFormat::Gif if options.backend == Backend::Native => {
+            let data = fs::read(&input_path).with_path(&input_path)?;
+            let (output, strategy) = native::compress(&data, &options, reporter.as_ref())?;
+            fs::write(&output_path, output).with_path(&output_path)?;
+            (strategy, None)
+        }
+        OutputFormat::Gif => optimize_gif(source, &output_path, &options, &reporter)?,
+        #[cfg(feature = "webm")]
+        OutputFormat::Webm => {
+            webm::optimize_webm(&input_path, &output_path, &options, reporter.as_ref())?;
+            (None, None)
+        }
+        #[cfg(not(feature = "webm"))]
+        OutputFormat::Webm => return Err(GifError::FeatureDisabled { feature: "webm" }),
+    };
+    
+    let size_kb = get_file_size_kb(&output_path)?;
+    let timed_out = options.cancel.is_expired();
+    if timed_out {
+        reporter.log(&format!("超出时间限制，已输出目前为止最好的结果: {:.2} KB", size_kb));
+    }
+    reporter.event(&ProgressEvent::Finished { size_kb });
+    Ok(CompressionReport {
+        input: input_path.as_ref().to_path_buf(),
+        output: output_path.as_ref().to_path_buf(),
+        original_size_kb,
+        size_kb,
+        target_reached: size_kb <= options.target_size_kb,
+        strategy,
+        lossy,
+        timed_out,
+        options,
+    })
+}
+
+/// 把 `overlay` 叠加到 `base` 上，再把合成结果压缩到目标大小
+///
+/// 报告中的原始大小是合成结果的大小。
+pub fn compose_with_reporter<P: AsRef<Path>, O: AsRef<Path>, Q: AsRef<Path>>(
+    base_path: P,
+    overlay_path: O,
+    output_path: Q,
+    placement: &compose::Placement,
+    options: &CompressionOptions,
+    reporter: SharedReporter,
+) -> Result<CompressionReport, GifError> {
+    for path in [base_path.as_ref(), overlay_path.as_ref()] {
+        if !path.exists() {
+            return Err(GifError::InputFileNotFound { path: path.to_path_buf() });
+        }
+    }
+    
+    reporter.log("合成动画...");
+    let base = fs::read(&base_path).with_path(&base_path)?;
+    let overlay = fs::read(&overlay_path).with_path(&overlay_path)?;
+    let composed = compose::compose(&base, &overlay, placement, options)?;
+    let composed_file = new_temp_file(options.temp_dir())?;
+    fs::write(composed_file.path(), composed).with_path(composed_file.path())?;
+    
+    let mut report = compress_with_reporter(composed_file.path(), output_path, options, reporter)?;
+    report.input = base_path.as_ref().to_path_buf();
+    Ok(report)
+}
+
+/// 压缩内存中的GIF数据，返回压缩后的文件内容
+///
+/// 纯Rust后端直接在内存中处理，其他后端借助临时文件复用基于文件的压缩流程。
+pub fn compress_bytes(data: &[u8], options: &CompressionOptions) -> Result<Vec<u8>, GifError> {
+    compress_bytes_with_reporter(data, options, Arc::new(ConsoleReporter))
+}
+
+/// 压缩内存中的GIF数据，日志和进度事件输出到指定的 `Reporter`
+pub fn compress_bytes_with_reporter(
+    data: &[u8],
+    options: &CompressionOptions,
+    reporter: SharedReporter,
+) -> Result<Vec<u8>, GifError> {
+    if options.backend == Backend::Native && options.format == OutputFormat::Gif {
+        reporter.event(&ProgressEvent::Started { original_size_kb: data.len() as f64 / 1024.0 });
+        let output = if options.lossless {
+            lossless::compress_bytes(data, options, reporter.as_ref())?
+        } else {
+            match static_still(data, options, reporter.as_ref())? {
+                Some(image) => native::encode_still(&image, options)?,
+                None => native::compress_bytes(data, options, reporter.as_ref())?,
+            }
+        };
+        reporter.event(&ProgressEvent::Finished { size_kb: output.len() as f64 / 1024.0 });
+        return Ok(output);
+    }
+    
+    let input_file = new_temp_file(options.temp_dir())?;
+    fs::write(input_file.path(), data).with_path(input_file.path())?;
+    
+    // ffmpeg根据扩展名判断输出格式
+    let suffix = match options.format {
+        OutputFormat::Gif => ".gif",
+        OutputFormat::Webm => ".webm",
+    };
+    let mut builder = tempfile::Builder::new();
+    builder.suffix(suffix);
+    let output_file = match options.temp_dir() {
+        Some(dir) => builder.tempfile_in(dir),
+        None => builder.tempfile(),
+    }?;
+    
+    compress_with_reporter(input_file.path(), output_file.path(), options, reporter)?;
+    fs::read(output_file.path()).with_path(output_file.path())
+}
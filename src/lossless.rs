@@ -0,0 +1,196 @@
+//! 无损压缩
+//!
+//! 只删除元数据、合并完全相同的相邻帧并做帧间差分优化，输出的每一帧画面和显示时长
+//! 都与输入完全一致。无法达到目标大小时如实报告，不会退而抽帧或使用有损压缩。
+
+use crate::cancel::run_command;
+use crate::native;
+use crate::options::{Backend, CompressionOptions};
+use crate::reporter::Reporter;
+use crate::{filters, get_file_size_kb, loop_arg, new_temp_file, GifError, WithPath};
+use gif::Repeat;
+use image::RgbaImage;
+use std::collections::HashSet;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// 无损模式会忽略的、改变画面的选项
+fn ignored_options(options: &CompressionOptions) -> Vec<&'static str> {
+    let mut ignored = Vec::new();
+    if options.max_width.is_some() || options.max_height.is_some() {
+        ignored.push("尺寸限制");
+    }
+    if options.colors.is_some() {
+        ignored.push("颜色数");
+    }
+    if options.max_fps.is_some() {
+        ignored.push("帧率上限");
+    }
+    if options.collapse_static {
+        ignored.push("--collapse-static");
+    }
+    if filters::enabled(options) {
+        ignored.push("逐帧处理");
+    }
+    ignored
+}
+
+fn log_ignored(options: &CompressionOptions, reporter: &dyn Reporter) {
+    let ignored = ignored_options(options);
+    if !ignored.is_empty() {
+        reporter.log(&format!("无损模式: 忽略会改变画面的选项 ({})", ignored.join(", ")));
+    }
+}
+
+/// 原文件的循环次数，与 `CompressionOptions::loop_count` 的含义相同
+fn source_loop_count(data: &[u8]) -> Result<Option<u16>, GifError> {
+    let mut decoder = gif::DecodeOptions::new().read_info(Cursor::new(data))?;
+    while decoder.next_frame_info()?.is_some() {}
+    Ok(match decoder.repeat() {
+        Repeat::Infinite => None,
+        Repeat::Finite(count) => Some(count),
+    })
+}
+
+/// 每帧颜色（包括透明）都不超过256种时才能不经量化地重新编码
+fn fits_palette(image: &RgbaImage) -> bool {
+    let mut colors = HashSet::new();
+    image.pixels().all(|pixel| {
+        // 完全透明的像素编码后都是同一个透明色
+        colors.insert(if pixel[3] == 0 { [0; 4] } else { pixel.0 });
+        colors.len() <= 256
+    })
+}
+
+/// 合并完全相同的相邻帧后重新编码，无法保证无损时返回None
+fn reencode(data: &[u8], options: &CompressionOptions, reporter: &dyn Reporter) -> Result<Option<Vec<u8>>, GifError> {
+    let mut frames = native::decode_frames(data)?;
+    if !frames.iter().all(|frame| fits_palette(&frame.image)) {
+        reporter.log("部分帧超过256种颜色，无法无损地重新编码，只做元数据和帧间优化");
+        return Ok(None);
+    }
+
+    let total = frames.len();
+    filters::merge_identical(&mut frames);
+    if frames.len() < total {
+        reporter.log(&format!("合并完全相同的相邻帧: {} 帧 -> {} 帧", total, frames.len()));
+    }
+
+    // 未指定循环次数时保留原文件的设置
+    let mut options = options.clone();
+    if options.loop_count.is_none() {
+        options.loop_count = source_loop_count(data)?;
+    }
+    Ok(Some(native::encode_exact(&frames, &options)?))
+}
+
+/// 在内存中无损压缩，返回原文件和重新编码结果中较小的一个
+pub(crate) fn compress_bytes(
+    data: &[u8],
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<Vec<u8>, GifError> {
+    log_ignored(options, reporter);
+    reporter.log(&format!("原始大小: {:.2} KB", data.len() as f64 / 1024.0));
+
+    let output = match reencode(data, options, reporter)? {
+        Some(output) if output.len() < data.len() => output,
+        _ => data.to_vec(),
+    };
+    report_result(output.len() as f64 / 1024.0, options, reporter)?;
+    Ok(output)
+}
+
+/// 无损压缩文件
+///
+/// gifsicle后端对原文件和重新编码的结果分别做 `-O3` 优化并删除元数据，取较小的一个；
+/// 纯Rust后端没有帧间差分优化，只在重新编码更小时使用。
+pub(crate) fn compress<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_path: P,
+    output_path: Q,
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<(), GifError> {
+    let data = fs::read(&input_path).with_path(&input_path)?;
+    if options.backend == Backend::Native {
+        fs::write(&output_path, compress_bytes(&data, options, reporter)?).with_path(&output_path)?;
+        return Ok(());
+    }
+
+    if Command::new("gifsicle").arg("--version").output().is_err() {
+        return Err(GifError::GifsicleNotFound);
+    }
+
+    log_ignored(options, reporter);
+    reporter.log(&format!("原始大小: {:.2} KB", data.len() as f64 / 1024.0));
+
+    let reencoded = match reencode(&data, options, reporter)? {
+        Some(output) => {
+            let file = new_temp_file(options.temp_dir())?;
+            fs::write(file.path(), output).with_path(file.path())?;
+            Some(file)
+        }
+        None => None,
+    };
+
+    let mut sources: Vec<PathBuf> = vec![input_path.as_ref().to_path_buf()];
+    sources.extend(reencoded.as_ref().map(|file| file.path().to_path_buf()));
+
+    let mut best: Option<(f64, tempfile::NamedTempFile)> = None;
+    for source in &sources {
+        let optimized = new_temp_file(options.temp_dir())?;
+        let mut args: Vec<String> = vec![
+            "-O3".to_string(),
+            "--no-warnings".to_string(),
+            "--no-comments".to_string(),
+            "--no-names".to_string(),
+            "--no-extensions".to_string(),
+            "--careful".to_string(),
+        ];
+        if options.loop_count.is_some() {
+            args.push(loop_arg(options));
+        }
+        args.push(source.to_string_lossy().to_string());
+        args.push("-o".to_string());
+        args.push(optimized.path().to_string_lossy().to_string());
+
+        let output = run_command(Command::new("gifsicle").args(&args), &options.cancel)?;
+        if !output.status.success() {
+            return Err(GifError::command_failed("gifsicle", &args, &output));
+        }
+
+        let size = get_file_size_kb(optimized.path())?;
+        reporter.log(&format!("无损优化后大小: {:.2} KB", size));
+        if best.as_ref().is_none_or(|(best_size, _)| size < *best_size) {
+            best = Some((size, optimized));
+        }
+    }
+
+    let (size, best) = best.ok_or(GifError::NoValidResults)?;
+    let original_size = data.len() as f64 / 1024.0;
+    // 优化结果反而更大时保留原文件
+    report_result(size.min(original_size), options, reporter)?;
+    if size < original_size {
+        fs::copy(best.path(), &output_path).with_path(&output_path)?;
+    } else {
+        fs::write(&output_path, &data).with_path(&output_path)?;
+    }
+    Ok(())
+}
+
+/// 报告是否达到目标大小，严格模式下未达到时报错
+fn report_result(size: f64, options: &CompressionOptions, reporter: &dyn Reporter) -> Result<(), GifError> {
+    if size <= options.target_size_kb {
+        reporter.log(&format!("完成! 最终大小: {:.2} KB", size));
+        return Ok(());
+    }
+    if options.strict {
+        return Err(GifError::SizeLimitExceeded { size_kb: size, limit_kb: options.target_size_kb });
+    }
+    reporter.log(&format!("\n无损模式下无法达到目标大小 {} KB，最小为 {:.2} KB，已保存到输出文件。",
+        options.target_size_kb, size));
+    reporter.log("没有抽帧也没有使用有损压缩；可以去掉 --lossless 以允许改变画面。");
+    Ok(())
+}
@@ -1,971 +1,941 @@
-use anyhow::Result;
-use clap::{App, Arg};
-use image::{codecs::gif::GifDecoder, AnimationDecoder};
-use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
-use std::path::{Path, PathBuf};
+use clap::{App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
+use gif_compressor::analysis;
+use gif_compressor::batch::{self, BatchEntry};
+use gif_compressor::cancel::CancellationToken;
+use gif_compressor::compose::{Corner, LoopAlign, Pip, Placement};
+use gif_compressor::config::Config;
+use gif_compressor::explain::{self, CandidateRecorder};
+use gif_compressor::options::{Backend, ColorSpace, CompressionOptions, Denoise, DropMode, HexColor, Optimizer, OutputFormat, Percent, Prefer, Profile, Quantizer, Region, SAMPLE_FAC_RANGE};
+use gif_compressor::report::CompressionReport;
+use gif_compressor::reporter::{Reporter, ReporterKind, SharedReporter};
+use gif_compressor::{compose_with_reporter, compress_with_reporter, paths, preset, quality, GifError, WithPath};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
 use std::process::Command;
-use std::sync::mpsc::{self, Sender, Receiver};
+use std::str::FromStr;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
 use std::thread;
-use tempfile::NamedTempFile;
-use thiserror::Error;
+use std::time::{Duration, Instant};
 
-/// 自定义错误类型
-#[derive(Error, Debug)]
-pub enum GifError {
-    #[error("IO错误: {0}")]
-    Io(#[from] std::io::Error),
+fn main() -> Result<(), GifError> {
+    // 记录开始时间
+    let start_time = Instant::now();
+    
+    // 解析命令行参数
+    let matches = App::new("GIF压缩工具")
+        .version("1.0")
+        .author("Rust GIF Compressor")
+        .about("压缩GIF到目标大小，保持颜色和尺寸")
+        .setting(AppSettings::SubcommandsNegateReqs)
+        .arg(Arg::with_name("input")
+            .help("输入GIF文件路径，@列表文件 批量压缩其中的每个文件；使用 --files-from 时为输出目录")
+            .required_unless("files-from")
+            .index(1))
+        .arg(Arg::with_name("output")
+            .help("输出GIF文件路径，批量压缩时为输出目录（省略时为当前目录）")
+            .index(2))
+        .arg(Arg::with_name("files-from")
+            .long("files-from")
+            .value_name("FILE")
+            .help("从列表文件批量读取输入路径，- 表示标准输入，与 @列表文件 相同")
+            .takes_value(true))
+        .arg(Arg::with_name("null")
+            .short("0")
+            .long("null")
+            .help("列表中的路径以NUL分隔（find -print0 的输出），路径可以包含空格和换行"))
+        .arg(Arg::with_name("per-file-timeout")
+            .long("per-file-timeout")
+            .value_name("秒")
+            .help("批量压缩时每个文件的时间限制，超时后停止搜索并输出目前最好的结果，继续下一个文件")
+            .conflicts_with("confirm")
+            .takes_value(true)
+            .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())))
+        .args(&option_args())
+        .arg(Arg::with_name("dry-run")
+            .long("dry-run")
+            .help("只分析输入文件并估计几组参数能达到的大小，不写出任何文件"))
+        .subcommand(SubCommand::with_name("compose")
+            .about("把一个GIF叠加到另一个GIF上，再压缩到目标大小")
+            .arg(Arg::with_name("base")
+                .help("底层GIF文件路径")
+                .required(true)
+                .index(1))
+            .arg(Arg::with_name("overlay")
+                .help("叠加在上层的GIF文件路径")
+                .required(true)
+                .index(2))
+            .arg(Arg::with_name("output")
+                .help("输出GIF文件路径")
+                .required(true)
+                .index(3))
+            .arg(Arg::with_name("x")
+                .long("x")
+                .value_name("PX")
+                .allow_hyphen_values(true)
+                .help("上层左上角的横坐标，可以为负数 (默认: 0)")
+                .takes_value(true)
+                .validator(check::<i64>("应为整数", |_| true)))
+            .arg(Arg::with_name("y")
+                .long("y")
+                .value_name("PX")
+                .allow_hyphen_values(true)
+                .help("上层左上角的纵坐标，可以为负数 (默认: 0)")
+                .takes_value(true)
+                .validator(check::<i64>("应为整数", |_| true)))
+            .arg(Arg::with_name("scale")
+                .long("scale")
+                .value_name("FACTOR")
+                .help("上层的缩放比例 (默认: 1.0)")
+                .takes_value(true)
+                .validator(check::<f64>("应为大于0的数", |v| v.is_finite() && *v > 0.0)))
+            .arg(Arg::with_name("align")
+                .long("align")
+                .value_name("MODE")
+                .help("两者时长不同时上层的对齐方式: loop 循环播放, stretch 拉伸到底层时长, once 播放一次后停在最后一帧 (默认: loop，画中画布局下为 stretch)")
+                .possible_values(&["loop", "stretch", "once"])
+                .takes_value(true))
+            .arg(Arg::with_name("pip")
+                .long("pip")
+                .value_name("CORNER")
+                .help("画中画布局：把上层缩小后放在底层的指定角落，并自动拉伸到底层时长")
+                .possible_values(&["top-left", "top-right", "bottom-left", "bottom-right"])
+                .conflicts_with_all(&["x", "y", "scale"])
+                .takes_value(true))
+            .arg(Arg::with_name("pip-size")
+                .long("pip-size")
+                .value_name("PERCENT")
+                .help("画中画小窗最多占底层宽度和高度的百分比，大于0且不超过100 (默认: 25)")
+                .requires("pip")
+                .takes_value(true)
+                .validator(check::<f64>("应为大于0且不超过100的数", |v| *v > 0.0 && *v <= 100.0)))
+            .arg(Arg::with_name("margin")
+                .long("margin")
+                .value_name("PX")
+                .help("画中画小窗与画布边缘的距离 (默认: 8)")
+                .requires("pip")
+                .takes_value(true)
+                .validator(check::<u32>("应为非负整数", |_| true)))
+            .args(&option_args()))
+        .get_matches();
     
-    #[error("图像处理错误: {0}")]
-    Image(#[from] image::error::ImageError),
+    if let ("compose", Some(sub)) = matches.subcommand() {
+        return compose(sub, start_time);
+    }
     
-    #[error("GIF没有帧")]
-    NoFrames,
+    let reporter = build_reporter(&matches);
+    let options = load_options(&matches, reporter.as_ref())?;
     
-    #[error("未找到gifsicle命令，请确保已安装")]
-    GifsicleNotFound,
+    // --files-from 时唯一的位置参数是输出目录
+    let list = match matches.value_of("files-from") {
+        Some(list) => {
+            if matches.is_present("output") {
+                clap::Error::with_description("使用 --files-from 时只需要指定输出目录", ErrorKind::TooManyValues).exit();
+            }
+            Some((list, matches.value_of("input")))
+        }
+        None => {
+            let input = matches.value_of("input").unwrap();
+            input.strip_prefix('@').map(|list| (list, matches.value_of("output")))
+        }
+    };
+    if let Some((list, output_dir)) = list {
+        if list == "-" && matches.is_present("confirm") {
+            clap::Error::with_description("从标准输入读取列表时不能使用 --confirm", ErrorKind::ArgumentConflict).exit();
+        }
+        let entries = batch::read_list(list, matches.is_present("null"))?;
+        return run_batch(&matches, Path::new(list), entries, output_dir, options, &reporter, start_time);
+    }
+    if matches.is_present("per-file-timeout") {
+        clap::Error::with_description("--per-file-timeout 只能用于批量压缩", ErrorKind::ArgumentConflict).exit();
+    }
     
-    #[error("gifsicle命令执行失败: {0}")]
-    GifsicleExecFailed(String),
+    let input = matches.value_of("input").unwrap();
     
-    #[error("输入文件不存在: {0}")]
-    InputFileNotFound(String),
+    // 检查输入文件是否存在
+    if !Path::new(input).exists() {
+        return Err(GifError::InputFileNotFound { path: input.into() });
+    }
     
-    #[error("没有找到有效的优化结果")]
-    NoValidResults,
+    if matches.is_present("dry-run") {
+        return dry_run(input, &options, reporter.as_ref());
+    }
     
-    #[error("临时目录创建失败: {0}")]
-    TempDirFailed(String),
+    let output = matches.value_of("output").unwrap_or_else(|| {
+        clap::Error::with_description("缺少输出文件路径", ErrorKind::MissingRequiredArgument).exit()
+    });
+    create_parent_dir(output)?;
     
-    #[error("{0}")]
-    Other(String),
+    let recorder = Arc::new(CandidateRecorder::new(reporter.clone()));
+    let result = compress_file(&matches, Path::new(input), Path::new(output), options, reporter.as_ref(), &recorder);
+    finish(&matches, result, &recorder, reporter.as_ref(), start_time)
 }
 
-// 添加从anyhow::Error到GifError的实现
-impl From<anyhow::Error> for GifError {
-    fn from(err: anyhow::Error) -> Self {
-        GifError::Other(err.to_string())
+/// 压缩单个文件，指定了 `--confirm` 时先由用户确认
+fn compress_file(
+    matches: &ArgMatches,
+    input: &Path,
+    output: &Path,
+    options: CompressionOptions,
+    reporter: &dyn Reporter,
+    recorder: &Arc<CandidateRecorder>,
+) -> Result<CompressionReport, GifError> {
+    reporter.log(&format!("开始压缩 '{}' 到 '{}' (目标: {} KB, 线程数: {})", 
+             input.display(), output.display(), options.target_size_kb, options.threads));
+    if matches.is_present("confirm") {
+        compress_confirmed(output, Some(input), options, reporter, |path, options| {
+            recorder.clear();
+            compress_with_reporter(input, path, options, recorder.clone())
+        })
+    } else {
+        compress_with_reporter(input, output, &options, recorder.clone())
     }
 }
 
-/// 获取文件大小（KB）
-fn get_file_size_kb<P: AsRef<Path>>(path: P) -> Result<f64, GifError> {
-    let metadata = fs::metadata(path)?;
-    Ok(metadata.len() as f64 / 1024.0)
-}
-
-/// 获取GIF的帧数
-fn get_frame_count<P: AsRef<Path>>(path: P) -> Result<usize, GifError> {
-    let file = File::open(path)?;
-    let decoder = GifDecoder::new(BufReader::new(file))?;
-    let frames = decoder.into_frames();
-    let count = frames.count();
-    Ok(count)
-}
+/// 作用于整次批量运行、不能写在列表行中的参数
+const BATCH_WIDE_ARGS: [&str; 4] = ["confirm", "explain", "report", "reporter"];
 
-/// 提取GIF帧并保存为新的GIF
-fn extract_frames<P: AsRef<Path>, Q: AsRef<Path>>(
-    input_path: P,
-    output_path: Q,
-    skip: usize,
-    delay: u16,
+/// 依次压缩列表中的每个文件，输出到同一个目录
+///
+/// 先解析所有行的参数，有错误时不开始压缩；单个文件失败不影响其余文件，
+/// 全部完成后返回第一个错误。指定了 `--per-file-timeout` 时，超时的文件输出目前最好的结果，
+/// 报告中记为 `timed-out`。
+fn run_batch(
+    matches: &ArgMatches,
+    list: &Path,
+    entries: Vec<BatchEntry>,
+    output_dir: Option<&str>,
+    options: CompressionOptions,
+    reporter: &SharedReporter,
+    start_time: Instant,
 ) -> Result<(), GifError> {
-    // 打开输入文件
-    let file = File::open(&input_path)?;
-    let decoder = GifDecoder::new(BufReader::new(file))?;
-    
-    // 提取所有帧
-    let frames = decoder.into_frames().collect_frames()?;
-    let total_frames = frames.len();
-    
-    // 根据skip参数选择帧
-    let mut selected_frames = Vec::new();
-    for i in (0..total_frames).step_by(skip) {
-        selected_frames.push(frames[i].clone());
-    }
-    
-    if selected_frames.is_empty() {
-        // 至少保留一帧
-        if !frames.is_empty() {
-            selected_frames.push(frames[0].clone());
-        } else {
-            return Err(GifError::NoFrames);
+    let config = Config::load()?;
+    let output_dir = Path::new(output_dir.unwrap_or("."));
+    let mut used = HashSet::new();
+    let mut jobs = Vec::new();
+    for entry in entries {
+        let mut options = options.clone();
+        if !entry.overrides.is_empty() {
+            let overrides = App::new("gif_compressor")
+                .setting(AppSettings::NoBinaryName)
+                .args(&option_args())
+                .get_matches_from_safe(&entry.overrides)
+                .map_err(|e| GifError::ConfigParse {
+                    path: list.to_path_buf(),
+                    message: format!("第 {} 行: {}", entry.line, e.message),
+                })?;
+            // 这些参数作用于整次运行，不能只对一行生效
+            if let Some(name) = BATCH_WIDE_ARGS.iter().find(|name| overrides.is_present(name)) {
+                return Err(GifError::ConfigParse {
+                    path: list.to_path_buf(),
+                    message: format!("第 {} 行: --{} 不能只对一行生效，请在命令行中指定", entry.line, name),
+                });
+            }
+            apply_args(&overrides, &config, &mut options, reporter.as_ref())?;
+            if options.threads == 0 {
+                options.threads = num_cpus::get();
+            }
         }
+        let output = batch::output_path(output_dir, &entry.input, options.format, &mut used);
+        jobs.push((entry.input, output, options));
     }
     
-    // 由于GIF格式复杂，我们使用临时目录和gifsicle来完成帧提取和合并
-    let temp_dir = tempfile::Builder::new()
-        .prefix("gif_frames_")
-        .tempdir()
-        .map_err(|e| GifError::TempDirFailed(e.to_string()))?;
-    
-    // 保存所有选择的帧到临时目录，并收集路径字符串
-    let mut frame_paths = Vec::new();
-    for (i, frame) in selected_frames.iter().enumerate() {
-        let frame_path = temp_dir.path().join(format!("frame_{}.gif", i));
-        let frame_file = File::create(&frame_path)?;
-        let mut frame_writer = BufWriter::new(frame_file);
-        
-        // 使用image库保存单帧GIF
-        frame.buffer().write_to(&mut frame_writer, image::ImageOutputFormat::Gif)?;
-        
-        // 保存路径字符串
-        frame_paths.push(frame_path.to_string_lossy().to_string());
+    if matches.is_present("dry-run") {
+        for (input, _, options) in &jobs {
+            dry_run(&input.to_string_lossy(), options, reporter.as_ref())?;
+        }
+        return Ok(());
     }
     
-    // 使用gifsicle合并帧
-    let output_path_str = output_path.as_ref().to_string_lossy().to_string();
-    let delay_str = delay.to_string();
-    
-    // 检查gifsicle是否存在
-    match Command::new("gifsicle").arg("--version").output() {
-        Ok(_) => {}, // 命令存在，继续执行
-        Err(_) => return Err(GifError::GifsicleNotFound),
+    fs::create_dir_all(output_dir).with_path(output_dir)?;
+    let timeout = matches.value_of("per-file-timeout").and_then(|v| v.parse::<u64>().ok());
+    let total = jobs.len();
+    let mut results = Vec::new();
+    let mut first_error = None;
+    for (i, (input, output, mut options)) in jobs.into_iter().enumerate() {
+        reporter.log(&format!("\n[{}/{}] {}", i + 1, total, input.display()));
+        let recorder = Arc::new(CandidateRecorder::new(reporter.clone()));
+        // 每个文件单独计时，上一个文件超时不影响下一个
+        options.cancel = CancellationToken::new();
+        let _timer = timeout.map(|seconds| expire_after(options.cancel.clone(), seconds, reporter.clone()));
+        let result = if input.exists() {
+            compress_file(matches, &input, &output, options, reporter.as_ref(), &recorder)
+        } else {
+            Err(GifError::InputFileNotFound { path: input.clone() })
+        };
+        match result {
+            Ok(report) => {
+                if matches.is_present("explain") {
+                    for line in explain::explain(&report, &recorder.candidates(), recorder.selected()) {
+                        reporter.log(&line);
+                    }
+                }
+                results.push(serde_json::to_value(&report)?);
+            }
+            Err(e) => {
+                reporter.log(&format!("失败: {}", e));
+                results.push(serde_json::json!({ "input": input.to_string_lossy(), "error": &e }));
+                first_error.get_or_insert(e);
+            }
+        }
     }
     
-    // 构建优化的参数列表
-    let mut gifsicle_args = Vec::with_capacity(frame_paths.len() + 8);
-    
-    // 添加优化选项
-    gifsicle_args.push("--no-warnings".to_string());        // 减少不必要的输出
-    gifsicle_args.push("--no-conserve-memory".to_string()); // 使用更多内存提高速度
-    gifsicle_args.push("--no-app-extensions".to_string());  // 移除应用扩展数据
-    gifsicle_args.push("--no-comments".to_string());        // 移除注释
-    gifsicle_args.push("--no-names".to_string());           // 移除名称元数据
-    gifsicle_args.push("-o".to_string());
-    gifsicle_args.push(output_path_str);
-    gifsicle_args.push("--delay".to_string());
-    gifsicle_args.push(delay_str);
-    gifsicle_args.push("--loopcount=forever".to_string());
-    
-    // 添加所有帧路径 (已经是String类型)
-    for path in &frame_paths {
-        gifsicle_args.push(path.clone());
+    let failed = results.iter().filter(|result| result.get("error").is_some()).count();
+    reporter.log(&format!("\n批量压缩完成: 成功 {} 个，失败 {} 个，输出目录 '{}'",
+             results.len() - failed, failed, output_dir.display()));
+    let timed_out = results.iter().filter(|result| result.get("timed-out").is_some()).count();
+    if timed_out > 0 {
+        reporter.log(&format!("其中 {} 个文件超出时间限制，输出的是目前为止最好的结果", timed_out));
     }
-    
-    // 执行gifsicle命令
-    let _output = Command::new("gifsicle")
-        .args(&gifsicle_args)
-        .output()?;
-    
-    // 检查命令是否成功
-    if !_output.status.success() {
-        let stderr = String::from_utf8_lossy(&_output.stderr).to_string();
-        return Err(GifError::GifsicleExecFailed(stderr));
+    if let Some(path) = matches.value_of("report") {
+        fs::write(path, serde_json::to_string_pretty(&results)?).with_path(path)?;
+        reporter.log(&format!("压缩报告已写入 {}", path));
     }
+    reporter.log(&format!("处理了 {} 毫秒", start_time.elapsed().as_millis()));
     
-    Ok(())
+    first_error.map_or(Ok(()), Err)
 }
 
-/// 表示临时文件 - 优化版本
-struct TempFile {
-    path: PathBuf,
+/// `seconds` 秒后让压缩提前结束，返回值被丢弃时停止计时
+fn expire_after(token: CancellationToken, seconds: u64, reporter: SharedReporter) -> mpsc::Sender<()> {
+    let (done, finished) = mpsc::channel::<()>();
+    thread::spawn(move || {
+        if finished.recv_timeout(Duration::from_secs(seconds)) == Err(RecvTimeoutError::Timeout) {
+            reporter.log(&format!("超过每个文件的时间限制 {} 秒，停止搜索", seconds));
+            token.expire();
+        }
+    });
+    done
 }
 
-impl TempFile {
-    fn new(temp_file: NamedTempFile) -> Result<Self, std::io::Error> {
-        // 使用 keep() 来获取路径并取消自动删除
-        match temp_file.keep() {
-            Ok((_file, path)) => Ok(Self { path }), // keep 成功，返回 Self
-            Err(persist_error) => Err(persist_error.error), // keep 失败，返回 IO 错误
-        }
-    }
+/// `compose` 子命令：叠加两个GIF并压缩结果
+fn compose(matches: &ArgMatches, start_time: Instant) -> Result<(), GifError> {
+    let base = matches.value_of("base").unwrap();
+    let overlay = matches.value_of("overlay").unwrap();
+    let output = matches.value_of("output").unwrap();
+    let reporter = build_reporter(matches);
+    let options = load_options(matches, reporter.as_ref())?;
     
-    fn path_str(&self) -> String {
-        self.path.to_string_lossy().to_string()
+    let mut placement = Placement::default();
+    if let Some(Ok(x)) = matches.value_of("x").map(str::parse::<i64>) {
+        placement.x = x;
     }
-    
-    // 当不再需要文件时手动删除
-    fn cleanup(&self) -> std::io::Result<()> {
-        if self.path.exists() {
-            std::fs::remove_file(&self.path)?;
-        }
-        Ok(())
+    if let Some(Ok(y)) = matches.value_of("y").map(str::parse::<i64>) {
+        placement.y = y;
     }
-}
-
-// Clone实现，允许复制TempFile
-impl Clone for TempFile {
-    fn clone(&self) -> Self {
-        Self {
-            path: self.path.clone(),
-        }
+    if let Some(Ok(scale)) = matches.value_of("scale").map(str::parse::<f64>) {
+        placement.scale = scale;
     }
-}
-
-/// 压缩策略结构
-struct Strategy {
-    skip: usize,
-    delay: u16,
-}
-
-/// 策略处理结果
-struct StrategyResult {
-    size: f64,
-    file: Option<TempFile>,
-    success: bool,
-}
-
-/// 共享状态结构体，用于线程间通信
-struct SharedState {
-    // 是否找到满足目标大小的结果
-    found_target: AtomicBool,
-    // 当前已找到的最佳大小，初始值设为最大值
-    best_size: std::sync::atomic::AtomicU64,
-}
-
-impl SharedState {
-    fn new() -> Self {
-        Self {
-            found_target: AtomicBool::new(false),
-            best_size: std::sync::atomic::AtomicU64::new(u64::MAX),
+    if let Some(Ok(corner)) = matches.value_of("pip").map(str::parse::<Corner>) {
+        let mut pip = Pip { corner, ..Pip::default() };
+        if let Some(Ok(size)) = matches.value_of("pip-size").map(str::parse::<f64>) {
+            pip.size = size / 100.0;
         }
-    }
-    
-    // 更新最佳大小（如果提供的大小更小）
-    fn update_best_size(&self, size: f64) -> bool {
-        let size_bits = size.to_bits();
-        let mut current = self.best_size.load(Ordering::Relaxed);
-        
-        loop {
-            // 如果新大小不比当前更好，不更新
-            if size_bits >= current {
-                return false;
-            }
-            
-            // 尝试原子更新，成功则返回true
-            match self.best_size.compare_exchange(
-                current,
-                size_bits,
-                Ordering::SeqCst,
-                Ordering::Relaxed
-            ) {
-                Ok(_) => return true,
-                Err(actual) => current = actual,
-            }
+        if let Some(Ok(margin)) = matches.value_of("margin").map(str::parse::<u32>) {
+            pip.margin = margin;
         }
+        placement.pip = Some(pip);
+        // 画中画通常是同一段时间的两路录像，默认让两者同时开始和结束
+        placement.align = LoopAlign::Stretch;
     }
-    
-    // 获取当前最佳大小
-    fn get_best_size(&self) -> f64 {
-        let bits = self.best_size.load(Ordering::Relaxed);
-        f64::from_bits(bits)
+    if let Some(Ok(align)) = matches.value_of("align").map(str::parse::<LoopAlign>) {
+        placement.align = align;
     }
     
-    // 设置已找到目标
-    fn set_found_target(&self) {
-        self.found_target.store(true, Ordering::Relaxed);
-    }
+    create_parent_dir(output)?;
     
-    // 检查是否已找到目标
-    fn is_target_found(&self) -> bool {
-        self.found_target.load(Ordering::Relaxed)
-    }
+    reporter.log(&format!("将 '{}' 叠加到 '{}' 上并压缩到 '{}' (目标: {} KB, 线程数: {})",
+             overlay, base, output, options.target_size_kb, options.threads));
+    let recorder = Arc::new(CandidateRecorder::new(reporter.clone()));
+    // 叠加后的画面本来就与底层不同，不计算画质评分
+    let result = if matches.is_present("confirm") {
+        compress_confirmed(Path::new(output), None, options, reporter.as_ref(), |path, options| {
+            recorder.clear();
+            compose_with_reporter(base, overlay, path, &placement, options, recorder.clone())
+        })
+    } else {
+        compose_with_reporter(base, overlay, output, &placement, &options, recorder.clone())
+    };
+    finish(matches, result, &recorder, reporter.as_ref(), start_time)
 }
 
-/// 处理单个策略
-fn process_strategy(
-    input_path: &str,
-    strategy: Strategy,
-    target_size_kb: f64,
-    thread_id: usize,
-    shared_state: &SharedState,
-) -> StrategyResult {
-    // 创建跟踪输出的记录器
-    let output_prefix = format!("线程 {}: ", thread_id);
-    let log = |msg: &str| {
-        let message = format!("{}{}", output_prefix, msg);
-        // 使用Mutex来确保输出不会被打断
-        println!("{}", message);
-    };
+fn build_reporter(matches: &ArgMatches) -> SharedReporter {
+    matches
+        .value_of("reporter")
+        .and_then(|kind| kind.parse::<ReporterKind>().ok())
+        .unwrap_or(ReporterKind::Console)
+        .build()
+}
+
+/// 按优先级合并默认值、配置文件、环境变量和命令行参数
+fn load_options(matches: &ArgMatches, reporter: &dyn Reporter) -> Result<CompressionOptions, GifError> {
+    // 默认值 < 配置文件 < 环境变量 < 命令行预设 < 命令行参数
+    let mut options = CompressionOptions::default();
+    let config = Config::load()?;
+    config.apply(&mut options)?;
+    apply_args(matches, &config, &mut options, reporter)?;
     
-    // 如果已经找到目标，立即返回
-    if shared_state.is_target_found() {
-        log("已有其他线程找到满足条件的结果，提前退出");
-        return StrategyResult {
-            size: f64::MAX,
-            file: None,
-            success: false,
-        };
+    // 未指定临时目录时使用缓存目录下的工作目录，失败则退回系统临时目录
+    if options.temp_dir.is_none() {
+        options.temp_dir = paths::work_dir();
     }
     
-    let skip = strategy.skip;
-    let delay = strategy.delay;
-    
-    // 预计剩余帧数
-    let expected_frames = match get_frame_count(input_path) {
-        Ok(count) => (count as f64 / skip as f64).ceil() as usize,
-        Err(_) => 0,
-    };
+    // 如果线程数为0，使用系统CPU核心数
+    if options.threads == 0 {
+        options.threads = num_cpus::get();
+    }
     
-    log(&format!("策略: 保留约 {} 帧 (每 {} 帧取1帧), 帧延迟: {}ms", 
-                expected_frames, skip, delay));
+    Ok(options)
+}
+
+/// 把命令行中的预设、内容类型和单独指定的参数应用到选项上
+fn apply_args(
+    matches: &ArgMatches,
+    config: &Config,
+    options: &mut CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<(), GifError> {
     
-    // 使用image库提取帧
-    let temp_frames = match NamedTempFile::new() {
-        Ok(file) => match TempFile::new(file) {
-            Ok(tf) => tf,
-            Err(e) => {
-                log(&format!("  创建临时文件(keep)失败: {}", e));
-                return StrategyResult {
-                    size: f64::MAX,
-                    file: None,
-                    success: false,
-                };
+    if let Some(name) = matches.value_of("preset") {
+        config.apply_preset(name, options)?;
+        match preset::find(name) {
+            Some(builtin) if !config.presets.contains_key(name) => {
+                reporter.log(&format!("使用预设 {}: {}", name, builtin.description));
             }
-        },
-        Err(_) => {
-            log("  创建 NamedTempFile 失败");
-            return StrategyResult {
-                size: f64::MAX,
-                file: None,
-                success: false,
-            };
+            _ => reporter.log(&format!("使用预设 {}", name)),
         }
-    };
-    
-    // 检查是否有线程已经找到结果
-    if shared_state.is_target_found() {
-        log("已有其他线程找到满足条件的结果，提前退出");
-        return StrategyResult {
-            size: f64::MAX,
-            file: None,
-            success: false,
-        };
     }
-    
-    let temp_frames_path = temp_frames.path_str();
-    
-    if let Err(e) = extract_frames(input_path, &temp_frames_path, skip, delay) {
-        log(&format!("  帧提取失败: {}", e));
-        return StrategyResult {
-            size: f64::MAX,
-            file: None,
-            success: false,
-        };
+    if let Some(Ok(profile)) = matches.value_of("profile").map(str::parse::<Profile>) {
+        profile.apply(options);
+        reporter.log(&format!("内容类型 {}", profile.description()));
     }
     
-    // 检查是否有线程已经找到结果
-    if shared_state.is_target_found() {
-        log("已有其他线程找到满足条件的结果，提前退出");
-        return StrategyResult {
-            size: f64::MAX,
-            file: None,
-            success: false,
-        };
+    if let Some(Ok(target)) = matches.value_of("target").map(str::parse::<f64>) {
+        options.target_size_kb = target;
     }
-    
-    // 检查提取是否成功
-    match get_file_size_kb(&temp_frames_path) {
-        Ok(size) if size < 1.0 => {
-            log("  帧提取生成的文件过小");
-            return StrategyResult {
-                size: f64::MAX,
-                file: None,
-                success: false,
-            };
-        },
-        Ok(_) => {}, // 文件大小正常，继续处理
-        Err(_) => {
-            log("  无法读取提取的帧大小");
-            return StrategyResult {
-                size: f64::MAX,
-                file: None,
-                success: false,
-            };
-        }
-    };
-    
-    // 优化提取后的帧
-    let temp_frames_opt = match NamedTempFile::new() {
-        Ok(file) => match TempFile::new(file) {
-            Ok(tf) => tf,
-            Err(e) => {
-                log(&format!("  创建优化临时文件(keep)失败: {}", e));
-                return StrategyResult {
-                    size: f64::MAX,
-                    file: None,
-                    success: false,
-                };
-            }
-        },
-        Err(_) => {
-            log("  创建优化 NamedTempFile 失败");
-            return StrategyResult {
-                size: f64::MAX,
-                file: None,
-                success: false,
-            };
-        }
-    };
-    
-    // 检查是否有线程已经找到结果
-    if shared_state.is_target_found() {
-        log("已有其他线程找到满足条件的结果，提前退出");
-        return StrategyResult {
-            size: f64::MAX,
-            file: None,
-            success: false,
-        };
+    if let Some(Ok(min_frames)) = matches.value_of("min-frames").map(str::parse::<u32>) {
+        options.min_frame_percent = min_frames;
     }
-    
-    let temp_frames_opt_path = temp_frames_opt.path_str();
-    
-    let args = vec!["-O3", &temp_frames_path, "-o", &temp_frames_opt_path];
-    
-    let _output = match Command::new("gifsicle")
-        .args(&args)
-        .output() {
-        Ok(output) => output,
-        Err(_) => {
-            log("  执行gifsicle帧优化失败");
-            return StrategyResult {
-                size: f64::MAX,
-                file: None,
-                success: false,
-            };
-        }
-    };
-    
-    if !_output.status.success() {
-        log("  帧优化失败");
-        return StrategyResult {
-            size: f64::MAX,
-            file: None,
-            success: false,
-        };
+    if let Some(Ok(threads)) = matches.value_of("threads").map(str::parse::<usize>) {
+        options.threads = threads;
     }
-    
-    // 清理第一个临时文件，不再需要它
-    let _ = temp_frames.cleanup();
-    
-    let frames_size = match get_file_size_kb(&temp_frames_opt_path) {
-        Ok(size) => size,
-        Err(_) => {
-            log("  无法读取优化后帧大小");
-            return StrategyResult {
-                size: f64::MAX,
-                file: None,
-                success: false,
-            };
-        }
-    };
-    
-    log(&format!("  抽帧后大小: {:.2} KB", frames_size));
-    
-    if frames_size <= target_size_kb {
-        log("  已达到目标大小!");
-        // 设置标志通知其他线程已找到满足条件的结果
-        shared_state.set_found_target();
-        return StrategyResult {
-            size: frames_size,
-            file: Some(temp_frames_opt),
-            success: true,
-        };
+    if let Some(Ok(backend)) = matches.value_of("backend").map(str::parse::<Backend>) {
+        options.backend = backend;
+    }
+    if let Some(Ok(max_width)) = matches.value_of("max-width").map(str::parse::<u32>) {
+        options.max_width = Some(max_width);
+    }
+    if let Some(Ok(max_height)) = matches.value_of("max-height").map(str::parse::<u32>) {
+        options.max_height = Some(max_height);
+    }
+    if let Some(Ok(Percent(sharpen))) = matches.value_of("sharpen").map(str::parse::<Percent>) {
+        options.sharpen = sharpen as u8;
+    }
+    if let Some(Ok(max_fps)) = matches.value_of("max-fps").map(str::parse::<f64>) {
+        options.max_fps = Some(max_fps);
+    }
+    if let Some(Ok(colors)) = matches.value_of("colors").map(str::parse::<u16>) {
+        options.colors = Some(colors);
+    }
+    if let Some(Ok(quantizer)) = matches.value_of("quantizer").map(str::parse::<Quantizer>) {
+        options.quantizer = quantizer;
+    }
+    if let Some(Ok(sample_fac)) = matches.value_of("sample-fac").map(str::parse::<u32>) {
+        options.sample_fac = sample_fac;
+    }
+    if let Some(Ok(color_space)) = matches.value_of("color-space").map(str::parse::<ColorSpace>) {
+        options.color_space = color_space;
+    }
+    if matches.is_present("deband") {
+        options.deband = true;
+    }
+    if let Some(Ok(loop_count)) = matches.value_of("loop-count").map(str::parse::<u16>) {
+        options.loop_count = Some(loop_count);
+    }
+    if matches.is_present("strict") {
+        options.strict = true;
+    }
+    if matches.is_present("collapse-static") {
+        options.collapse_static = true;
+    }
+    if matches.is_present("lossless") {
+        options.lossless = true;
+    }
+    if matches.is_present("no-lossy") {
+        options.no_lossy = true;
+    }
+    if matches.is_present("keep-all-frames") {
+        options.keep_all_frames = true;
+    }
+    if let Some(Ok(prefer)) = matches.value_of("prefer").map(str::parse::<Prefer>) {
+        options.prefer = Some(prefer);
+    }
+    if let Some(Ok(max_drop)) = matches.value_of("max-drop").map(str::parse::<Percent>) {
+        options.max_drop = Some(max_drop);
+    }
+    if let Some(Ok(optimizer)) = matches.value_of("optimizer").map(str::parse::<Optimizer>) {
+        options.optimizer = optimizer;
+    }
+    if let Some(Ok(budget)) = matches.value_of("optimizer-budget").map(str::parse::<u64>) {
+        options.optimizer_budget = budget;
+    }
+    if matches.is_present("autocrop") {
+        options.autocrop = true;
+    }
+    if let Some(Ok(denoise)) = matches.value_of("denoise").map(str::parse::<Denoise>) {
+        options.denoise = Some(denoise);
+    }
+    if matches.is_present("key-background") {
+        options.key_background = true;
+    }
+    if let Some(Ok(color)) = matches.value_of("transparent-color").map(str::parse::<HexColor>) {
+        options.transparent_color = Some(color);
+    }
+    if let Some(Ok(tolerance)) = matches.value_of("tolerance").map(str::parse::<u8>) {
+        options.tolerance = tolerance;
+    }
+    if matches.is_present("freeze-static") {
+        options.freeze_static = true;
+    }
+    if matches.is_present("dedupe-frames") {
+        options.dedupe_frames = true;
+    }
+    if let Some(Ok(region)) = matches.value_of("protect-region").map(str::parse::<Region>) {
+        options.protect_region = Some(region);
+    }
+    if matches.is_present("detect-text") {
+        options.detect_text = true;
+    }
+    if let Some(Ok(drop_mode)) = matches.value_of("drop-mode").map(str::parse::<DropMode>) {
+        options.drop_mode = drop_mode;
+    }
+    if let Some(Ok(format)) = matches.value_of("format").map(str::parse::<OutputFormat>) {
+        options.format = format;
     }
     
-    // 跟踪当前策略下的最佳结果
-    let mut best_size = frames_size;
-    let mut best_file = Some(temp_frames_opt);
-    
-    // 批量尝试不同的lossy值
-    // 创建临时文件和对应的lossy级别
-    let lossy_levels = [30, 60, 90, 120, 150, 180, 210, 240];
-    
-    // 每次处理两个lossy级别，平衡进程创建开销和并行效率
-    let chunk_size = 2;
-    
-    for chunk in lossy_levels.chunks(chunk_size) {
-        // 先检查是否有线程已经找到结果
-        if shared_state.is_target_found() {
-            log("已有其他线程找到满足条件的结果，提前退出");
-            return StrategyResult {
-                size: best_size,
-                file: best_file,
-                success: true,
-            };
-        }
-        
-        let mut temp_files = Vec::with_capacity(chunk.len());
-        let mut results = Vec::with_capacity(chunk.len());
+    Ok(())
+}
+
+/// 确保目标路径的目录存在
+fn create_parent_dir(output: &str) -> Result<(), GifError> {
+    if let Some(parent) = Path::new(output).parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.exists()
+    {
+        fs::create_dir_all(parent).with_path(parent)?;
+    }
+    Ok(())
+}
+
+/// `--confirm`: 先压缩到输出目录中的临时文件，显示大小和画质评分后由用户决定接受、调整参数重试还是放弃
+///
+/// 只有接受时才写出输出文件，放弃时返回 `GifError::Cancelled`。
+fn compress_confirmed<F>(
+    output_path: &Path,
+    original: Option<&Path>,
+    mut options: CompressionOptions,
+    reporter: &dyn Reporter,
+    compress: F,
+) -> Result<CompressionReport, GifError>
+where
+    F: Fn(&Path, &CompressionOptions) -> Result<CompressionReport, GifError>,
+{
+    let dir = output_path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    // 保留扩展名，输出PNG和WebM时的行为与直接写出相同
+    let suffix = output_path.extension().map_or(String::new(), |ext| format!(".{}", ext.to_string_lossy()));
+    let original = original.map(|path| fs::read(path).with_path(path)).transpose()?;
+    
+    loop {
+        let candidate = tempfile::Builder::new().prefix(".gif_compressor").suffix(&suffix).tempfile_in(dir)?;
+        let mut report = compress(candidate.path(), &options)?;
         
-        // 创建这一批次的临时文件
-        for &level in chunk {
-            match NamedTempFile::new() {
-                Ok(file) => {
-                    // 修改 TempFile::new 调用，处理 Result
-                    match TempFile::new(file) {
-                        Ok(tf) => temp_files.push((level, tf)),
-                        Err(e) => log(&format!("  创建lossy={}临时文件(keep)失败: {}", level, e)),
-                    }
-                },
-                Err(_) => {
-                    log(&format!("  创建lossy={} NamedTempFile 失败", level));
-                }
+        let data = fs::read(candidate.path()).with_path(candidate.path())?;
+        reporter.log(&format!("\n结果: {:.2} KB (目标 {} KB)", report.size_kb, options.target_size_kb));
+        if let Some(original) = &original {
+            match quality::compare(original, &data) {
+                Ok(quality) => reporter.log(&format!("画质评分: {:.0}/100 (PSNR {:.1} dB)，保留 {}/{} 帧",
+                    quality.score, quality.psnr, quality.frames, quality.original_frames)),
+                Err(e) => reporter.log(&format!("无法计算画质评分: {}", e)),
             }
         }
         
-        let current_best_path = match &best_file {
-            Some(file) => file.path_str(),
-            None => break,
-        };
-        
-        // 处理这一批次的lossy级别
-        for (level, temp_file) in &temp_files {
-            let temp_path = temp_file.path_str();
-            
-            // 创建lossy参数
-            let lossy_arg = format!("--lossy={}", level);
-            
-            // 优化的gifsicle命令参数
-            let args = vec![
-                "-O3", 
-                "--no-warnings",
-                "--no-conserve-memory", 
-                "--no-comments", 
-                "--no-names",
-                &lossy_arg,
-                &current_best_path, 
-                "-o", 
-                &temp_path
-            ];
-            
-            let _output = match Command::new("gifsicle")
-                .args(&args)
-                .output() {
-                Ok(output) if output.status.success() => {
-                    match get_file_size_kb(&temp_path) {
-                        Ok(size) => {
-                            log(&format!("  抽帧 + lossy={} 后大小: {:.2} KB", level, size));
-                            results.push((*level, size));
-                        },
-                        Err(_) => {
-                            log(&format!("  无法读取lossy={}压缩后大小", level));
-                        }
-                    }
-                },
-                _ => {
-                    log(&format!("  lossy={}压缩失败", level));
+        loop {
+            let answer = prompt("接受结果? [y]接受 / [r]调整参数重试 / [p]预览 / [n]放弃: ")?;
+            match answer.map(|answer| answer.to_lowercase()).as_deref() {
+                Some("y") | Some("yes") => {
+                    fs::write(output_path, &data).with_path(output_path)?;
+                    report.output = output_path.to_path_buf();
+                    return Ok(report);
                 }
-            };
-        }
-        
-        // 处理这一批次的结果
-        for (_result_idx, (level, size)) in results.iter().enumerate() {
-            if *size <= target_size_kb {
-                log(&format!("  lossy={} 已达到目标大小!", level));
-                
-                // 找到对应的临时文件
-                if let Some((_, temp_file)) = temp_files.iter().find(|(l, _)| *l == *level) {
-                    // 如果当前结果比之前的好，替换并清理旧文件
-                    if best_size > *size {
-                        if let Some(old_file) = best_file.take() {
-                            let _ = old_file.cleanup(); // 清理旧文件
-                        }
-                        best_size = *size;
-                        best_file = Some(temp_file.clone());
+                Some("p") => {
+                    if let Err(e) = preview(original.as_deref(), &data, candidate.path()) {
+                        reporter.log(&format!("无法打开预览: {}", e));
                     }
                 }
-                
-                // 设置标志通知其他线程已找到满足条件的结果
-                shared_state.set_found_target();
-                break;
-            } else if *size < best_size {
-                // 找到对应的临时文件
-                if let Some((_, temp_file)) = temp_files.iter().find(|(l, _)| *l == *level) {
-                    // 替换旧文件并清理
-                    if let Some(old_file) = best_file.take() {
-                        let _ = old_file.cleanup(); // 清理旧文件
+                Some("r") => {
+                    if adjust_options(&mut options, reporter)? {
+                        break;
                     }
-                    best_size = *size;
-                    best_file = Some(temp_file.clone());
                 }
+                Some("n") | Some("no") | None => {
+                    reporter.log("已放弃，未写出输出文件");
+                    return Err(GifError::Cancelled);
+                }
+                Some(_) => {}
             }
         }
-        
-        // 如果已找到目标，不再处理更多批次
-        if shared_state.is_target_found() {
-            break;
+    }
+}
+
+/// 读取用户输入的调整参数（与命令行参数相同，例如 `--target 800 --max-drop 30%`）并应用到选项上
+///
+/// 输入为空时返回false，继续使用当前结果。
+fn adjust_options(options: &mut CompressionOptions, reporter: &dyn Reporter) -> Result<bool, GifError> {
+    loop {
+        let Some(line) = prompt("调整的参数，例如 --target 800 --max-drop 30% (直接回车取消): ")? else {
+            return Ok(false);
+        };
+        if line.trim().is_empty() {
+            return Ok(false);
         }
-        
-        // 清理这批次中未被选中的临时文件
-        for (_level, temp_file) in &temp_files {
-            if let Some(best) = &best_file {
-                if best.path != temp_file.path {
-                    let _ = temp_file.cleanup();
-                }
-            } else {
-                let _ = temp_file.cleanup();
+        let matches = App::new("gif_compressor")
+            .setting(AppSettings::NoBinaryName)
+            .args(&option_args())
+            .get_matches_from_safe(line.split_whitespace());
+        match matches {
+            Ok(matches) => {
+                apply_args(&matches, &Config::load()?, options, reporter)?;
+                return Ok(true);
             }
+            Err(e) => eprintln!("{}", e.message),
         }
     }
-    
-    // Prepare the result to be returned
-    let final_best_file_for_return = best_file.clone(); // Clone the Option<TempFile>
+}
 
-    // If we have a best file locally, prevent its Drop implementation from running
-    // because we are transferring responsibility via the clone.
-    if let Some(local_best) = best_file {
-         std::mem::forget(local_best);
-    }
+/// 在标准错误输出提示并读取一行，输入结束时返回None
+fn prompt(message: &str) -> Result<Option<String>, GifError> {
+    eprint!("{}", message);
+    io::stderr().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
 
-    // Return the result containing the cloned Option<TempFile>
-    StrategyResult {
-        size: best_size,
-        file: final_best_file_for_return,
-        success: true, // Assuming we found at least one valid result
+/// 并排播放原文件和压缩结果；没有原文件（`compose`）时用系统默认的程序打开压缩结果
+#[cfg(feature = "preview")]
+fn preview(original: Option<&[u8]>, compressed: &[u8], path: &Path) -> Result<(), GifError> {
+    match original {
+        Some(original) => gif_compressor::preview::play(original, compressed),
+        None => Ok(open_preview(path)?),
     }
 }
 
-/// 优化GIF到目标大小 (并行版本)
-fn optimize_gif<P: AsRef<Path>, Q: AsRef<Path>>(
-    input_path: P,
-    output_path: Q,
-    target_size_kb: f64,
-    min_frame_percent: u32,
-    threads: usize,
+/// 未启用 `preview` 特性时用系统默认的程序打开压缩结果
+#[cfg(not(feature = "preview"))]
+fn preview(_original: Option<&[u8]>, _compressed: &[u8], path: &Path) -> Result<(), GifError> {
+    Ok(open_preview(path)?)
+}
+
+/// 用系统默认的程序打开文件
+fn open_preview(path: &Path) -> io::Result<()> {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(path).spawn().map(|_| ())
+}
+
+/// 写出报告、打印说明和耗时
+fn finish(
+    matches: &ArgMatches,
+    result: Result<CompressionReport, GifError>,
+    recorder: &CandidateRecorder,
+    reporter: &dyn Reporter,
+    start_time: Instant,
 ) -> Result<(), GifError> {
-    // 获取初始文件大小
-    let original_size = get_file_size_kb(&input_path)?;
-    println!("原始大小: {:.2} KB", original_size);
-    
-    // 如果已经小于目标大小，直接复制
-    if original_size <= target_size_kb {
-        println!("文件已经小于目标大小，无需压缩");
-        fs::copy(&input_path, &output_path)?;
-        return Ok(());
-    }
-    
-    // 获取初始帧数
-    let original_frame_count = get_frame_count(&input_path)?;
-    println!("原始帧数: {}", original_frame_count);
-    
-    // 检查gifsicle是否存在
-    match Command::new("gifsicle").arg("--version").output() {
-        Ok(_) => {}, // 命令存在，继续执行
-        Err(_) => return Err(GifError::GifsicleNotFound),
-    }
-    
-    // 基础优化 - 使用gifsicle的最高优化级别和更多高级选项
-    let temp_file = NamedTempFile::new()?;
-    let temp_file_opt = TempFile::new(temp_file)?;
-    let temp_file_opt_path = temp_file_opt.path_str();
-    
-    // 保存基础优化文件路径的副本，以便后续可能需要作为备选
-    let temp_file_opt_path_copy = PathBuf::from(&temp_file_opt_path);
-    
-    // 使用String而不是&str，避免生命周期问题
-    let input_path_str = input_path.as_ref().to_string_lossy().to_string();
-    
-    // 构建优化的参数列表
-    let args = vec![
-        "-O3",                            // 最高级别优化
-        "--no-warnings",                  // 不显示警告
-        "--no-conserve-memory",           // 使用更多内存以提高速度
-        "--no-comments",                  // 删除注释以减小文件大小
-        "--no-names",                     // 删除图像和对象名称
-        "--careful",                      // 更慎重的优化，避免损坏文件
-        &input_path_str,                  // 输入文件
-        "-o",                             // 输出选项
-        &temp_file_opt_path               // 输出文件
-    ];
-    
-    let _output = Command::new("gifsicle")
-        .args(&args)
-        .output()?;
+    let report = match result {
+        Ok(report) => report,
+        Err(e) => {
+            // 失败时报告文件中只包含错误码和错误信息
+            if let Some(path) = matches.value_of("report") {
+                fs::write(path, serde_json::to_string_pretty(&serde_json::json!({ "error": &e }))?).with_path(path)?;
+            }
+            return Err(e);
+        }
+    };
     
-    if !_output.status.success() {
-        let stderr = String::from_utf8_lossy(&_output.stderr).to_string();
-        return Err(GifError::GifsicleExecFailed(stderr));
+    if let Some(path) = matches.value_of("report") {
+        let json = serde_json::to_string_pretty(&report)?;
+        fs::write(path, json).with_path(path)?;
+        reporter.log(&format!("压缩报告已写入 {}", path));
     }
     
-    let opt_size = get_file_size_kb(&temp_file_opt_path)?;
-    println!("基础优化后大小: {:.2} KB", opt_size);
-    
-    // 如果已经达到目标大小，直接复制
-    if opt_size <= target_size_kb {
-        fs::copy(&temp_file_opt_path, &output_path)?;
-        return Ok(());
+    if matches.is_present("explain") {
+        for line in explain::explain(&report, &recorder.candidates(), recorder.selected()) {
+            reporter.log(&line);
+        }
     }
     
-    // 计算最小保留帧数
-    let min_frames = std::cmp::max(3, (original_frame_count as f64 * min_frame_percent as f64 / 100.0) as usize);
+    // 计算并输出处理时间
+    let elapsed = start_time.elapsed();
+    reporter.log(&format!("处理了 {} 毫秒", elapsed.as_millis()));
     
-    // 构建抽帧策略
-    let mut strategies = Vec::new();
+    Ok(())
+}
+
+/// 分析输入文件并打印几组参数的估计大小，不写出任何文件
+fn dry_run(input: &str, options: &CompressionOptions, reporter: &dyn Reporter) -> Result<(), GifError> {
+    let analysis = analysis::analyze(&fs::read(input).with_path(input)?)?;
     
-    // 从2抽1开始，最多抽到保留最小帧数
-    let max_skip = std::cmp::max(2, std::cmp::min(10, 
-        ((original_frame_count as f64) / (min_frames as f64)).ceil() as usize));
+    reporter.log(&format!("分析 '{}' (不会写出任何文件)", input));
+    reporter.log(&format!("大小: {:.2} KB, 尺寸: {}x{}, 帧数: {}, 平均帧率: {:.1} fps",
+             analysis.size_kb, analysis.width, analysis.height, analysis.frame_count, analysis.fps));
+    reporter.log(&format!("颜色数: {}, 颜色熵: {:.2} bit/像素",
+             analysis.unique_colors, analysis.palette_entropy));
     
-    for skip in 2..=max_skip {
-        strategies.push(Strategy {
-            skip,
-            delay: ((100.0 * skip as f64) / original_frame_count as f64) as u16 + 10,
-        });
+    if let Some(color) = analysis.background {
+        reporter.log(&format!("纯色背景: {}，可以使用 --key-background 转为透明", HexColor(color)));
     }
     
-    // 如果帧数很多，尝试更激进的抽帧策略
-    if original_frame_count > 30 {
-        let aggressive_skips = [max_skip + 5, max_skip + 10];
-        for &skip in &aggressive_skips {
-            if original_frame_count / skip >= min_frames {
-                strategies.push(Strategy {
-                    skip,
-                    delay: ((100.0 * skip as f64) / original_frame_count as f64) as u16 + 10,
-                });
-            }
-        }
+    let frame_colors: Vec<usize> = analysis.frames.iter().map(|f| f.unique_colors).collect();
+    reporter.log("\n颜色使用:");
+    reporter.log(&format!("  所有帧共 {} 种颜色，每帧 {}-{} 种，平均 {:.0} 种",
+             analysis.unique_colors,
+             frame_colors.iter().min().unwrap_or(&0),
+             analysis.max_frame_colors(),
+             frame_colors.iter().sum::<usize>() as f64 / frame_colors.len() as f64));
+    for colors in [128, 64, 32] {
+        let savings = analysis.color_savings(colors, options);
+        reporter.log(&format!("  --colors {:>3}: 约节省 {:>4.1}% ({:.2} KB)",
+                 colors, savings * 100.0, analysis.size_kb * savings));
     }
-    
-    // 限制线程数，不超过策略数量
-    let thread_count = std::cmp::min(threads, strategies.len());
-    println!("开始使用 {} 个线程并行处理 {} 个压缩策略...", thread_count, strategies.len());
-    
-    // 创建通道以接收处理结果
-    let (tx, rx): (Sender<StrategyResult>, Receiver<StrategyResult>) = mpsc::channel();
-    
-    // 创建线程池
-    let input_path_arc = Arc::new(input_path_str);
-    let mut handles = Vec::new();
-    
-    // 创建共享状态
-    let shared_state = Arc::new(SharedState::new());
-    
-    // 设置初始最佳大小为基础优化后的大小
-    shared_state.update_best_size(opt_size);
-    
-    for (i, chunk) in strategies.into_iter().enumerate() {
-        let tx_clone = tx.clone();
-        let input_path_clone = Arc::clone(&input_path_arc);
-        let shared_state_clone = Arc::clone(&shared_state);
-        
-        // 创建线程处理这个策略
-        let handle = thread::spawn(move || {
-            let result = process_strategy(
-                &input_path_clone,
-                chunk,
-                target_size_kb,
-                i + 1,
-                &shared_state_clone
-            );
-            
-            // 如果这是一个好的结果，更新共享状态中的最佳大小
-            if result.success && result.size < shared_state_clone.get_best_size() {
-                let is_better = shared_state_clone.update_best_size(result.size);
-                
-                // 如果我们的结果被接受为更好的结果，并且达到了目标大小，设置found_target标志
-                if is_better && result.size <= target_size_kb {
-                    shared_state_clone.set_found_target();
-                }
-            }
-            
-            // 发送结果到主线程
-            let _ = tx_clone.send(result);
-        });
-        
-        handles.push(handle);
+    if analysis.max_frame_colors() <= 128 {
+        reporter.log(&format!("  每帧最多只用 {} 种颜色，降到这个数以上的 --colors 不会减小文件", analysis.max_frame_colors()));
     }
     
-    // 丢弃发送者以允许接收者知道何时所有发送者都已完成
-    drop(tx);
-    
-    // 等待并收集所有策略的结果
-    let mut best_size = opt_size;
-    let mut best_file: Option<TempFile> = Some(temp_file_opt);
-    let mut found_solution = false;
-    let mut files_to_cleanup: Vec<TempFile> = Vec::new(); // <--- 新增：待清理文件列表
-    
-    // 从通道接收结果
-    for result in rx.iter() {
-        if !result.success {
-            // 如果结果的文件存在，也要加入清理列表
-            if let Some(file) = result.file {
-                files_to_cleanup.push(file);
-            }
-            continue;
-        }
-        
-        // 确保 result.file 是 Some
-        let result_file = match result.file {
-            Some(file) => file,
-            None => continue, // 没有文件，无法比较或使用
-        };
-
-        if result.size <= target_size_kb {
-            // 清理之前的最佳文件（如果有的话），将其加入待清理列表
-            if let Some(old_file) = best_file.take() {
-                // let _ = old_file.cleanup(); // <--- 移除：不再立即清理
-                files_to_cleanup.push(old_file); // <--- 修改：加入待清理列表
-            }
-            
-            best_size = result.size;
-            best_file = Some(result_file); // 使用 result_file
-            found_solution = true;
-            println!("找到达到目标大小的策略! 大小: {:.2} KB", best_size);
-            // 设置标志，以便其他线程可以提前退出
-            shared_state.set_found_target();
-            break; // 提前退出循环，不再处理其他结果
-        } else if result.size < best_size {
-            // 清理之前的最佳文件（如果有的话），将其加入待清理列表
-            if let Some(old_file) = best_file.take() {
-                // let _ = old_file.cleanup(); // <--- 移除：不再立即清理
-                files_to_cleanup.push(old_file); // <--- 修改：加入待清理列表
-            }
-            
-            best_size = result.size;
-            best_file = Some(result_file); // 使用 result_file
-        } else {
-            // 该结果不比当前最佳结果好，将其文件加入待清理列表
-            // if let Some(file) = result.file { // <--- 移除
-            //     let _ = file.cleanup(); // <--- 移除
-            // } // <--- 移除
-            files_to_cleanup.push(result_file); // <--- 修改：加入待清理列表
-        }
+    reporter.log("\n帧复杂度:");
+    for (i, frame) in analysis.frames.iter().enumerate() {
+        reporter.log(&format!("  帧 {:>4}: 变化 {:>5.1}%, 熵 {:.2} bit/像素",
+                 i + 1, frame.changed_percent, frame.entropy));
     }
-    
-    // 我们不再等待所有线程完成
-    // 如果已经找到满足条件的结果，其他线程会自动退出
-    // 如果我们想要优雅地等待，可以设置一个超时
-    if found_solution {
-        println!("已找到满足条件的结果，不再等待其他线程");
-    } else {
-        println!("尚未找到满足目标大小的结果，等待所有线程完成...");
-        // 等待所有线程完成
-        for handle in handles {
-            let _ = handle.join();
-        }
+    let mean_change = analysis.mean_changed_percent();
+    let mean_entropy = analysis.mean_frame_entropy();
+    reporter.log(&format!("平均每帧变化 {:.1}%, 平均熵 {:.2} bit/像素, 可压缩性评分 {:.0}/100",
+             mean_change, mean_entropy, analysis.compressibility_score()));
+    if mean_change > 50.0 {
+        reporter.log("  画面大部分像素每帧都在变化，帧间透明优化几乎无效，主要只能靠抽帧、缩小尺寸和lossy");
     }
-    
-    // 使用找到的最佳文件
-    if let Some(best) = best_file {
-        println!("\n复制最佳结果到输出文件...");
-        
-        // 添加文件存在性验证
-        println!("检查文件存在性: {}", &best.path.display());
-        if !best.path.exists() {
-            println!("⚠️ 警告：文件不存在，尝试使用备份");
-            
-            // 如果基础优化文件还存在（备份），尝试直接使用它
-            if temp_file_opt_path_copy.exists() {
-                println!("使用基础优化文件作为备选: {}", &temp_file_opt_path_copy.display());
-                fs::copy(&temp_file_opt_path_copy, &output_path)?;
-            } else {
-                println!("❌ 错误：基础优化文件也不存在");
-                return Err(GifError::Other("无法找到有效的临时文件进行复制".to_string()));
-            }
-        } else {
-            // 文件存在，执行正常复制
-            fs::copy(&best.path, &output_path)?;
-        }
-        
-        let final_size = get_file_size_kb(&output_path)?;
-        println!("完成! 最终大小: {:.2} KB", final_size);
-
-        // 清理临时文件
-        println!("清理临时文件...");
-        let _ = best.cleanup(); // 手动清理最佳文件
-        for file_to_clean in files_to_cleanup {
-            let _ = file_to_clean.cleanup(); // 手动清理其他文件
-        }
-
-    } else {
-        // 如果 best_file 是 None (例如基础优化后就满足条件，但逻辑上应该总有 best_file)
-        // 确保清理所有可能产生的临时文件
-        println!("清理临时文件...");
-        for file_to_clean in files_to_cleanup {
-            let _ = file_to_clean.cleanup();
-        }
-        return Err(GifError::NoValidResults);
+    if mean_entropy > 6.0 {
+        reporter.log("  每帧颜色分布复杂（照片、渐变或噪点），减少颜色数或lossy会比较明显");
     }
+    reporter.log(&format!("\n估计大小 (经验模型，仅供参考，目标 {} KB):", options.target_size_kb));
     
-    // 如果还是没达到目标大小，给出提示
-    if best_size > target_size_kb {
-        println!("\n无法达到目标大小 {} KB。", target_size_kb);
-        println!("最接近的大小是 {:.2} KB，已保存到输出文件。", best_size);
-        println!("建议尝试允许减少尺寸或颜色数量以达到更小的文件大小。");
+    for estimate in analysis.estimates(options) {
+        let lossy = estimate.lossy.map_or("无".to_string(), |level| level.to_string());
+        let colors = estimate.colors.map_or("原始".to_string(), |colors| colors.to_string());
+        let mark = if estimate.size_kb <= options.target_size_kb { "✓" } else { " " };
+        reporter.log(&format!("  {} 每 {} 帧取1帧, lossy={}, 颜色数={}: 约 {:.2} KB",
+                 mark, estimate.skip, lossy, colors, estimate.size_kb));
     }
     
     Ok(())
 }
 
-fn main() -> Result<(), GifError> {
-    // 记录开始时间
-    let start_time = std::time::Instant::now();
-    
-    // 解析命令行参数
-    let matches = App::new("GIF压缩工具")
-        .version("1.0")
-        .author("Rust GIF Compressor")
-        .about("压缩GIF到目标大小，保持颜色和尺寸")
-        .arg(Arg::with_name("input")
-            .help("输入GIF文件路径")
-            .required(true)
-            .index(1))
-        .arg(Arg::with_name("output")
-            .help("输出GIF文件路径")
-            .required(true)
-            .index(2))
-        .arg(Arg::with_name("target")
+/// 参数值的校验器：能解析为 `T` 且满足 `valid` 时通过，否则报告 `expected`
+fn check<T: FromStr>(expected: &'static str, valid: fn(&T) -> bool) -> impl Fn(String) -> Result<(), String> {
+    move |value| match value.trim().parse::<T>() {
+        Ok(parsed) if valid(&parsed) => Ok(()),
+        _ => Err(format!("{}: {}", expected, value)),
+    }
+}
+
+/// 压缩命令和 `compose` 子命令共用的选项
+fn option_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("target")
             .long("target")
             .help("目标文件大小(KB)，默认500KB")
             .takes_value(true)
-            .default_value("500"))
-        .arg(Arg::with_name("min-frames")
+            .validator(check::<f64>("应为大于0的数", |v| v.is_finite() && *v > 0.0)),
+        Arg::with_name("min-frames")
             .long("min-frames")
             .help("保留的最小帧数百分比，默认为原始帧数的10%")
             .takes_value(true)
-            .default_value("10"))
-        .arg(Arg::with_name("threads")
+            .validator(check::<u32>("应为0-100的整数", |v| *v <= 100)),
+        Arg::with_name("threads")
             .long("threads")
             .help("并行处理线程数，默认为系统CPU核心数")
             .takes_value(true)
-            .default_value("0"))
-        .get_matches();
-    
-    let input = matches.value_of("input").unwrap();
-    let output = matches.value_of("output").unwrap();
-    let target = matches.value_of("target")
-        .unwrap()
-        .parse::<f64>()
-        .unwrap_or(500.0);
-    let min_frames = matches.value_of("min-frames")
-        .unwrap()
-        .parse::<u32>()
-        .unwrap_or(10);
-    let threads = matches.value_of("threads")
-        .unwrap()
-        .parse::<usize>()
-        .unwrap_or(0);
-    
-    // 如果线程数为0，使用系统CPU核心数
-    let thread_count = if threads == 0 {
-        num_cpus::get()
-    } else {
-        threads
-    };
-    
-    // 检查输入文件是否存在
-    if !Path::new(input).exists() {
-        return Err(GifError::InputFileNotFound(input.to_string()));
-    }
-    
-    // 确保目标路径的目录存在
-    if let Some(parent) = Path::new(output).parent() {
-        if !parent.as_os_str().is_empty() && !parent.exists() {
-            fs::create_dir_all(parent)?;
-        }
-    }
-    
-    println!("开始压缩 '{}' 到 '{}' (目标: {} KB, 线程数: {})", 
-             input, output, target, thread_count);
-    optimize_gif(input, output, target, min_frames, thread_count)?;
-    
-    // 计算并输出处理时间
-    let elapsed = start_time.elapsed();
-    println!("处理了 {} 毫秒", elapsed.as_millis());
-    
-    Ok(())
-}
\ No newline at end of file
+            .validator(check::<usize>("应为非负整数", |_| true)),
+        Arg::with_name("backend")
+            .long("backend")
+            .help("压缩后端，默认为gifsicle；native为纯Rust实现，不依赖gifsicle")
+            .takes_value(true)
+            .possible_values(&["gifsicle", "native"]),
+        Arg::with_name("preset")
+            .long("preset")
+            .help("使用预设：内置的discord-emoji, discord-sticker, slack, telegram-sticker, github-readme, email，或配置文件中定义的预设")
+            .takes_value(true),
+        Arg::with_name("profile")
+            .long("profile")
+            .help("按内容类型调整参数：screen 适合终端和界面录屏，可以与 --preset 同时使用")
+            .takes_value(true)
+            .possible_values(&["screen"]),
+        Arg::with_name("max-width")
+            .long("max-width")
+            .help("最大宽度，超出时等比缩小")
+            .takes_value(true)
+            .validator(check::<u32>("应为正整数", |v| *v >= 1)),
+        Arg::with_name("max-height")
+            .long("max-height")
+            .help("最大高度，超出时等比缩小")
+            .takes_value(true)
+            .validator(check::<u32>("应为正整数", |v| *v >= 1)),
+        Arg::with_name("sharpen")
+            .long("sharpen")
+            .help("缩小尺寸后的锐化强度(0-100)，让缩小后的界面文字保持清晰，默认0不锐化")
+            .takes_value(true)
+            .validator(|v| v.parse::<Percent>().map(|_| ())),
+        Arg::with_name("max-fps")
+            .long("max-fps")
+            .help("最大帧率")
+            .takes_value(true)
+            .validator(check::<f64>("应为大于0的数", |v| v.is_finite() && *v > 0.0)),
+        Arg::with_name("colors")
+            .long("colors")
+            .help("最大颜色数(2-256)")
+            .takes_value(true)
+            .validator(check::<u16>("应为2-256的整数", |v| (2..=256).contains(v))),
+        Arg::with_name("quantizer")
+            .long("quantizer")
+            .help("纯Rust后端的调色板生成算法：median-cut（默认）快速且结果确定，neuquant适合照片类内容，kmeans生成所有帧共用的调色板，品牌色较多时偏色更少")
+            .takes_value(true)
+            .possible_values(&["median-cut", "neuquant", "kmeans"]),
+        Arg::with_name("sample-fac")
+            .long("sample-fac")
+            .help("NeuQuant采样因子(1-30)，1最慢质量最好，30最快，默认10")
+            .takes_value(true)
+            .validator(check::<u32>("应为1-30的整数", |v| SAMPLE_FAC_RANGE.contains(v))),
+        Arg::with_name("color-space")
+            .long("color-space")
+            .help("纯Rust后端量化时计算颜色距离的颜色空间：srgb（默认）、linear线性光、oklab感知均匀空间，后两者渐变和暗部的色带更少")
+            .takes_value(true)
+            .possible_values(&["srgb", "linear", "oklab"]),
+        Arg::with_name("deband")
+            .long("deband")
+            .help("对天空、界面光晕等平滑渐变做有序抖动，减少量化后的色带，文件会略微变大"),
+        Arg::with_name("format")
+            .long("format")
+            .help("输出格式，默认为gif；webm需要启用webm特性并安装ffmpeg")
+            .takes_value(true)
+            .possible_values(&["gif", "webm"]),
+        Arg::with_name("loop-count")
+            .long("loop-count")
+            .help("循环次数，0表示只播放一次，默认无限循环")
+            .takes_value(true)
+            .validator(check::<u16>("应为0-65535的整数", |_| true)),
+        Arg::with_name("strict")
+            .long("strict")
+            .help("严格模式：无法满足目标大小或尺寸限制时报错，不写出输出文件"),
+        Arg::with_name("collapse-static")
+            .long("collapse-static")
+            .help("所有帧几乎相同时输出单帧GIF，输出路径以.png结尾时输出PNG"),
+        Arg::with_name("lossless")
+            .long("lossless")
+            .help("无损模式：只删除元数据、合并完全相同的帧并做帧间优化，不抽帧、不使用有损压缩，达不到目标大小时如实报告"),
+        Arg::with_name("no-lossy")
+            .long("no-lossy")
+            .help("只通过抽帧和基础优化达到目标大小，不使用有损压缩，纯Rust后端也不减少颜色数"),
+        Arg::with_name("keep-all-frames")
+            .long("keep-all-frames")
+            .help("保留所有帧，只调整有损压缩、颜色数和缩放比例，适合每一步都必须可见的教程GIF"),
+        Arg::with_name("prefer")
+            .long("prefer")
+            .help("优先保留的方面，其余方面先被降级：frames 帧数, quality 画质, resolution 尺寸")
+            .takes_value(true)
+            .possible_values(&["frames", "quality", "resolution"]),
+        Arg::with_name("max-drop")
+            .long("max-drop")
+            .value_name("百分比")
+            .help("最多抽掉的帧数比例，例如 50%，无论目标大小多紧都不会超过")
+            .takes_value(true)
+            .validator(|v| v.parse::<Percent>().map(|_| ())),
+        Arg::with_name("optimizer")
+            .long("optimizer")
+            .help("搜索压缩参数的方式：grid 固定网格(默认), anneal 在时间预算内用模拟退火搜索抽帧、lossy和颜色数的组合, bayes 用大小模型预测下一个组合，尽量少调用gifsicle")
+            .takes_value(true)
+            .possible_values(&["grid", "anneal", "bayes"]),
+        Arg::with_name("optimizer-budget")
+            .long("optimizer-budget")
+            .value_name("秒")
+            .help("anneal 和 bayes 优化器的时间预算，默认30秒")
+            .takes_value(true)
+            .validator(|v| v.parse::<u64>().map(|_| ()).map_err(|e| e.to_string())),
+        Arg::with_name("autocrop")
+            .long("autocrop")
+            .help("裁掉所有帧都相同的纯色边框，例如录屏时多录进来的桌面或窗口边距"),
+        Arg::with_name("denoise")
+            .long("denoise")
+            .help("压缩前降噪（时间+空间），去除摄像头噪点和JPEG伪影")
+            .takes_value(true)
+            .possible_values(&["light", "medium", "strong"]),
+        Arg::with_name("key-background")
+            .long("key-background")
+            .help("检测所有帧共同的纯色背景并转为透明，网页上显示效果更好，文件也小得多"),
+        Arg::with_name("transparent-color")
+            .long("transparent-color")
+            .value_name("#rrggbb")
+            .help("把指定颜色（例如绿幕 #00ff00）转为透明")
+            .takes_value(true)
+            .validator(|v| v.parse::<HexColor>().map(|_| ())),
+        Arg::with_name("tolerance")
+            .long("tolerance")
+            .help("--transparent-color 和 --key-background 每个通道允许的颜色差异(0-255)，默认24")
+            .takes_value(true)
+            .validator(check::<u8>("应为0-255的整数", |_| true)),
+        Arg::with_name("freeze-static")
+            .long("freeze-static")
+            .help("冻结几乎不变的像素，消除屏幕录制中的噪点，让帧间优化生效"),
+        Arg::with_name("dedupe-frames")
+            .long("dedupe-frames")
+            .help("按感知哈希聚类近似重复的帧（包括不相邻的，例如循环的加载动画），重复帧使用同一画面，相邻的合并为一帧"),
+        Arg::with_name("protect-region")
+            .long("protect-region")
+            .value_name("x,y,w,h")
+            .help("保护区域（原图坐标），区域内保持清晰：纯Rust后端为其分配独立的调色板，gifsicle后端不做有损压缩")
+            .takes_value(true)
+            .validator(|v| v.parse::<Region>().map(|_| ())),
+        Arg::with_name("detect-text")
+            .long("detect-text")
+            .help("自动检测文字密集的区域并作为保护区域，避免小号文字被有损压缩破坏"),
+        Arg::with_name("drop-mode")
+            .long("drop-mode")
+            .help("抽帧时被丢弃帧的处理方式：discard直接丢弃，blend与保留的帧混合，interpolate在保留帧之间用运动补偿合成中间帧")
+            .takes_value(true)
+            .possible_values(&["discard", "blend", "interpolate"]),
+        Arg::with_name("confirm")
+            .long("confirm")
+            .help("压缩完成后显示大小和画质评分，确认接受后才写出输出文件，也可以预览或调整参数重试"),
+        Arg::with_name("explain")
+            .long("explain")
+            .help("压缩完成后说明选中了哪个结果、其他候选为什么被放弃以及可以调整的参数"),
+        Arg::with_name("reporter")
+            .long("reporter")
+            .help("输出方式，默认为console；json每行输出一个JSON对象，silent不输出")
+            .takes_value(true)
+            .possible_values(&["console", "json", "silent"]),
+        Arg::with_name("report")
+            .long("report")
+            .help("将压缩报告以JSON格式写入文件，其中的options字段可以直接作为配置文件使用")
+            .takes_value(true),
+    ]
+}
@@ -0,0 +1,77 @@
+//! 运动补偿插帧
+//!
+//! 大量抽帧后在相邻的保留帧之间合成一帧中间画面，用简单的块匹配估计运动，
+//! 让极少的帧数也能保持流畅感，适合体育和游戏片段。
+
+use image::RgbaImage;
+
+/// 块匹配的块边长
+const BLOCK_SIZE: u32 = 8;
+
+/// 运动搜索半径（像素），搜索步长为2，保证中间位置是整数
+const SEARCH_RADIUS: i32 = 8;
+
+/// 一个块在两帧间的像素差异之和
+fn block_cost(a: &RgbaImage, b: &RgbaImage, x: u32, y: u32, dx: i32, dy: i32) -> u64 {
+    let mut cost = 0;
+    for j in 0..BLOCK_SIZE.min(a.height() - y) {
+        for i in 0..BLOCK_SIZE.min(a.width() - x) {
+            let p = a.get_pixel(x + i, y + j);
+            let q = b.get_pixel(((x + i) as i32 + dx) as u32, ((y + j) as i32 + dy) as u32);
+            cost += p.0.iter().zip(q.0.iter()).map(|(u, v)| u.abs_diff(*v) as u64).sum::<u64>();
+        }
+    }
+    cost
+}
+
+/// 在搜索范围内找出块从 `a` 到 `b` 的最佳位移
+fn motion_vector(a: &RgbaImage, b: &RgbaImage, x: u32, y: u32) -> (i32, i32) {
+    let (width, height) = (a.width() as i32, a.height() as i32);
+    let block_w = BLOCK_SIZE.min(a.width() - x) as i32;
+    let block_h = BLOCK_SIZE.min(a.height() - y) as i32;
+
+    let mut best = ((0, 0), block_cost(a, b, x, y, 0, 0));
+    for dy in (-SEARCH_RADIUS..=SEARCH_RADIUS).step_by(2) {
+        for dx in (-SEARCH_RADIUS..=SEARCH_RADIUS).step_by(2) {
+            let (tx, ty) = (x as i32 + dx, y as i32 + dy);
+            if (dx, dy) == (0, 0) || tx < 0 || ty < 0 || tx + block_w > width || ty + block_h > height {
+                continue;
+            }
+            let cost = block_cost(a, b, x, y, dx, dy);
+            if cost < best.1 {
+                best = ((dx, dy), cost);
+            }
+        }
+    }
+    best.0
+}
+
+/// 合成两帧之间的中间帧
+///
+/// 先用50/50混合填满画面，再把每个块按运动向量的一半平移后覆盖上去，
+/// 块的内容取两帧对应位置的平均值。
+pub(crate) fn midpoint(a: &RgbaImage, b: &RgbaImage) -> RgbaImage {
+    let mut output = crate::filters::blend([a, b]);
+    for y in (0..a.height()).step_by(BLOCK_SIZE as usize) {
+        for x in (0..a.width()).step_by(BLOCK_SIZE as usize) {
+            let (dx, dy) = motion_vector(a, b, x, y);
+            if (dx, dy) == (0, 0) {
+                continue;
+            }
+            for j in 0..BLOCK_SIZE.min(a.height() - y) {
+                for i in 0..BLOCK_SIZE.min(a.width() - x) {
+                    let (sx, sy) = (x + i, y + j);
+                    let p = a.get_pixel(sx, sy);
+                    let q = b.get_pixel((sx as i32 + dx) as u32, (sy as i32 + dy) as u32);
+                    let (mx, my) = (sx as i32 + dx / 2, sy as i32 + dy / 2);
+                    let mut pixel = *p;
+                    for ((value, u), v) in pixel.0.iter_mut().zip(p.0).zip(q.0) {
+                        *value = (u as u16 + v as u16).div_ceil(2) as u8;
+                    }
+                    output.put_pixel(mx as u32, my as u32, pixel);
+                }
+            }
+        }
+    }
+    output
+}
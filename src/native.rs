@@ -0,0 +1,508 @@
+//! 纯Rust压缩后端
+//!
+//! 不启动外部进程也不访问文件系统，全部在内存中完成，可以编译到wasm32。
+
+use crate::{filters, motion, optimizer, text, GifError, Strategy};
+use crate::options::{CompressionOptions, DropMode, Optimizer, Prefer, Quantizer, Region};
+use crate::quantize::{self, ColorMap};
+use crate::progress::{ProgressEvent, Stage};
+use crate::reporter::Reporter;
+use gif::{DisposalMethod, Encoder, Frame, Repeat};
+use image::codecs::gif::GifDecoder;
+use image::imageops::{self, FilterType};
+use image::{AnimationDecoder, RgbaImage};
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::io::Cursor;
+
+/// 依次尝试的颜色数
+pub(crate) const COLOR_LEVELS: [u16; 5] = [256, 128, 64, 32, 16];
+
+/// 保留所有帧时依次尝试的缩放比例
+pub(crate) const SCALE_LEVELS: [f64; 3] = [1.0, 0.75, 0.5];
+
+/// 保护区域独占的调色板颜色数，不随颜色数级别减少
+const PROTECTED_COLORS: usize = 128;
+
+/// 与第一帧不同的像素比例不超过此值时视为同一画面
+const STATIC_CHANGE_RATIO: f64 = 0.001;
+
+/// 判断像素相同时每个通道允许的差异
+const STATIC_TOLERANCE: u8 = 8;
+
+/// 解码后的完整帧
+#[derive(Clone)]
+pub(crate) struct DecodedFrame {
+    pub(crate) image: RgbaImage,
+    /// 帧延迟(1/100秒)
+    pub(crate) delay: u16,
+}
+
+/// 解码GIF的所有帧
+pub(crate) fn decode_frames(data: &[u8]) -> Result<Vec<DecodedFrame>, GifError> {
+    let decoder = GifDecoder::new(Cursor::new(data))?;
+    let frames = decoder
+        .into_frames()
+        .collect_frames()?
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let delay = (numer as f64 / denom as f64 / 10.0).round() as u16;
+            DecodedFrame { image: frame.into_buffer(), delay }
+        })
+        .collect::<Vec<_>>();
+
+    if frames.is_empty() {
+        return Err(GifError::NoFrames);
+    }
+    Ok(frames)
+}
+
+/// 按最大尺寸等比缩小所有帧，缩小后按选项锐化
+pub(crate) fn resize_frames(frames: Vec<DecodedFrame>, options: &CompressionOptions) -> Vec<DecodedFrame> {
+    let (width, height) = frames[0].image.dimensions();
+    let scale_w = options.max_width.map_or(1.0, |max| max as f64 / width as f64);
+    let scale_h = options.max_height.map_or(1.0, |max| max as f64 / height as f64);
+    let scale = scale_w.min(scale_h);
+    if scale >= 1.0 {
+        return frames;
+    }
+    scale_frames(&frames, scale, options)
+}
+
+/// 按比例缩小所有帧，缩小后按选项锐化
+fn scale_frames(frames: &[DecodedFrame], scale: f64, options: &CompressionOptions) -> Vec<DecodedFrame> {
+    let (width, height) = frames[0].image.dimensions();
+    let new_width = ((width as f64 * scale).round() as u32).max(1);
+    let new_height = ((height as f64 * scale).round() as u32).max(1);
+    frames
+        .iter()
+        .map(|frame| {
+            let image = imageops::resize(&frame.image, new_width, new_height, FilterType::CatmullRom);
+            DecodedFrame { image: filters::sharpen(image, options.sharpen), delay: frame.delay }
+        })
+        .collect()
+}
+
+/// 源动画的平均帧率
+fn average_fps(frames: &[DecodedFrame]) -> f64 {
+    // 浏览器会把小于2的帧延迟按10处理
+    let total: f64 = frames
+        .iter()
+        .map(|frame| if frame.delay < 2 { 10.0 } else { frame.delay as f64 })
+        .sum();
+    frames.len() as f64 * 100.0 / total
+}
+
+/// 保护区域换算到缩放后的坐标
+fn scaled_region(options: &CompressionOptions, original_width: u32, width: u32) -> Option<Region> {
+    options.protect_region.map(|region| region.scaled(width as f64 / original_width as f64))
+}
+
+/// 所有帧共用的调色板，只有k-means生成全局调色板，其他算法逐帧训练
+fn global_color_map(frames: &[(Cow<'_, RgbaImage>, u32)], colors: u16, options: &CompressionOptions) -> Option<ColorMap> {
+    if options.quantizer != Quantizer::KMeans {
+        return None;
+    }
+    // 为透明色和保护区域预留调色板空间
+    let reserved = 1 + if options.protect_region.is_some() || options.detect_text { PROTECTED_COLORS } else { 0 };
+    let size = (colors as usize).min(256 - reserved).max(2);
+    let pixels: Vec<u8> = frames.iter().flat_map(|(image, _)| image.as_raw().iter().copied()).collect();
+    Some(ColorMap::train(options, size, &pixels))
+}
+
+/// 将一帧量化为调色板图像，半透明以下的像素映射为透明色
+///
+/// `global` 为None时按选项中的算法为这一帧单独训练调色板。有保护区域时区域内的像素
+/// 使用单独训练的调色板，颜色数固定为 [`PROTECTED_COLORS`]。
+fn quantize(
+    image: &RgbaImage,
+    colors: u16,
+    protect: Option<Region>,
+    options: &CompressionOptions,
+    global: Option<&ColorMap>,
+) -> Frame<'static> {
+    let (width, height) = image.dimensions();
+    let pixels = image.as_raw();
+    let has_transparency = pixels.chunks_exact(4).any(|p| p[3] < 128);
+
+    let region_pixels: Vec<u8> = match protect {
+        Some(region) => image
+            .enumerate_pixels()
+            .filter(|(x, y, _)| region.contains(*x, *y))
+            .flat_map(|(_, _, p)| p.0)
+            .collect(),
+        None => Vec::new(),
+    };
+
+    // 透明色占用调色板中的一项
+    let available = if has_transparency { 255 } else { 256 };
+    let region_size = if region_pixels.is_empty() { 0 } else { PROTECTED_COLORS.min(available / 2) };
+    let palette_size = (colors as usize).min(available - region_size).max(2);
+    let local;
+    let color_map = match global {
+        Some(color_map) => color_map,
+        None => {
+            local = ColorMap::train(options, palette_size, pixels);
+            &local
+        }
+    };
+    let mut palette = color_map.color_map_rgb();
+    // 调色板的颜色可能少于请求的数量，区域颜色紧接其后
+    let region_offset = palette.len() / 3;
+
+    let region_map = (region_size > 0).then(|| ColorMap::train(options, region_size, &region_pixels));
+    if let Some(region_map) = &region_map {
+        palette.extend(region_map.color_map_rgb());
+    }
+
+    // 去色带：平滑渐变中的像素先做有序抖动再映射
+    let smooth = options.deband.then(|| quantize::smooth_mask(image));
+
+    let transparent = if has_transparency {
+        let index = palette.len() / 3;
+        palette.extend_from_slice(&[0, 0, 0]);
+        Some(index as u8)
+    } else {
+        None
+    };
+
+    let indices: Vec<u8> = pixels
+        .chunks_exact(4)
+        .enumerate()
+        .map(|(i, p)| {
+            let (x, y) = (i as u32 % width, i as u32 / width);
+            match (transparent, &region_map, protect) {
+                (Some(index), _, _) if p[3] < 128 => index,
+                (_, Some(region_map), Some(region)) if region.contains(x, y) => {
+                    (region_offset + region_map.index_of(p)) as u8
+                }
+                _ if smooth.as_ref().is_some_and(|mask| mask[i]) => {
+                    color_map.index_of(&quantize::dither(p, x, y, region_offset)) as u8
+                }
+                _ => color_map.index_of(p) as u8,
+            }
+        })
+        .collect();
+
+    let mut frame = Frame::from_palette_pixels(width as u16, height as u16, indices, palette, transparent);
+    if has_transparency {
+        // 每帧都是完整画面，透明区域不能透出上一帧
+        frame.dispose = DisposalMethod::Background;
+    }
+    frame
+}
+
+/// 两帧是否几乎相同
+pub(crate) fn is_near_identical(a: &RgbaImage, b: &RgbaImage) -> bool {
+    let changed = a
+        .pixels()
+        .zip(b.pixels())
+        .filter(|(p, q)| p.0.iter().zip(q.0.iter()).any(|(x, y)| x.abs_diff(*y) > STATIC_TOLERANCE))
+        .count();
+    changed as f64 <= a.len() as f64 / 4.0 * STATIC_CHANGE_RATIO
+}
+
+/// 所有帧几乎相同时返回缩放后的第一帧，单帧GIF返回None
+pub(crate) fn static_frame(data: &[u8], options: &CompressionOptions) -> Result<Option<RgbaImage>, GifError> {
+    let mut frames = decode_frames(data)?;
+    if frames.len() < 2 || !frames[1..].iter().all(|frame| is_near_identical(&frames[0].image, &frame.image)) {
+        return Ok(None);
+    }
+    frames.truncate(1);
+    Ok(resize_frames(frames, options).pop().map(|frame| frame.image))
+}
+
+/// 编码单帧GIF
+pub(crate) fn encode_still(image: &RgbaImage, options: &CompressionOptions) -> Result<Vec<u8>, GifError> {
+    let (width, height) = image.dimensions();
+    let mut output = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut output, width as u16, height as u16, &[])?;
+        let colors = options.colors.unwrap_or(256).clamp(2, 256);
+        encoder.write_frame(&quantize(image, colors, None, options, None))?;
+    }
+    Ok(output)
+}
+
+/// 不量化地编码所有帧，每帧颜色不超过256种时保留原始颜色
+pub(crate) fn encode_exact(frames: &[DecodedFrame], options: &CompressionOptions) -> Result<Vec<u8>, GifError> {
+    let (width, height) = frames[0].image.dimensions();
+    let mut output = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut output, width as u16, height as u16, &[])?;
+        match options.loop_count {
+            None => encoder.set_repeat(Repeat::Infinite)?,
+            Some(0) => {}
+            Some(count) => encoder.set_repeat(Repeat::Finite(count))?,
+        }
+
+        for decoded in frames {
+            let mut pixels = decoded.image.as_raw().clone();
+            let mut frame = Frame::from_rgba_speed(width as u16, height as u16, &mut pixels, 10);
+            frame.delay = decoded.delay;
+            if frame.transparent.is_some() {
+                frame.dispose = DisposalMethod::Background;
+            }
+            encoder.write_frame(&frame)?;
+        }
+    }
+    Ok(output)
+}
+
+/// 按抽帧间隔合并帧，返回每个输出帧的画面和延迟(1/100秒)
+///
+/// 保留帧的延迟为被合并帧的延迟之和，保持播放速度不变；插帧模式下
+/// 延迟由保留帧和其后合成的中间帧平分。
+fn reduce_frames<'a>(
+    frames: &'a [DecodedFrame],
+    skip: usize,
+    options: &CompressionOptions,
+) -> Vec<(Cow<'a, RgbaImage>, u32)> {
+    let groups: Vec<(Cow<'a, RgbaImage>, u32)> = frames
+        .chunks(skip)
+        .map(|group| {
+            let image = match options.drop_mode {
+                DropMode::Blend if group.len() > 1 => Cow::Owned(filters::blend(group.iter().map(|frame| &frame.image))),
+                _ => Cow::Borrowed(&group[0].image),
+            };
+            (image, group.iter().map(|frame| frame.delay as u32).sum())
+        })
+        .collect();
+
+    if options.drop_mode != DropMode::Interpolate || skip < 2 {
+        return groups;
+    }
+    let mut output = Vec::with_capacity(groups.len() * 2);
+    for i in 0..groups.len() {
+        let delay = groups[i].1;
+        match groups.get(i + 1) {
+            Some((next, _)) => {
+                let middle = motion::midpoint(&groups[i].0, next);
+                output.push((groups[i].0.clone(), delay / 2));
+                output.push((Cow::Owned(middle), delay - delay / 2));
+            }
+            None => output.push((groups[i].0.clone(), delay)),
+        }
+    }
+    output
+}
+
+/// 按颜色数编码合并后的帧
+fn encode(
+    frames: &[(Cow<'_, RgbaImage>, u32)],
+    colors: u16,
+    protect: Option<Region>,
+    options: &CompressionOptions,
+) -> Result<Vec<u8>, GifError> {
+    let (width, height) = frames[0].0.dimensions();
+    let mut output = Vec::new();
+    {
+        let mut encoder = Encoder::new(&mut output, width as u16, height as u16, &[])?;
+        match options.loop_count {
+            None => encoder.set_repeat(Repeat::Infinite)?,
+            Some(0) => {} // 没有循环扩展时只播放一次
+            Some(count) => encoder.set_repeat(Repeat::Finite(count))?,
+        }
+
+        let global = global_color_map(frames, colors, options);
+        for (image, delay) in frames {
+            let mut frame = quantize(image, colors, protect, options, global.as_ref());
+            frame.delay = ((*delay).min(u16::MAX as u32) as u16).max(options.min_delay());
+            encoder.write_frame(&frame)?;
+        }
+    }
+    Ok(output)
+}
+
+/// 在内存中压缩GIF
+///
+/// 依次增大抽帧间隔，每个间隔下逐步减少颜色数，返回第一个达到目标大小的结果，
+/// 都达不到时返回最小的结果。
+pub fn compress_bytes(
+    data: &[u8],
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<Vec<u8>, GifError> {
+    compress(data, options, reporter).map(|(output, _)| output)
+}
+
+/// 在内存中压缩GIF，同时返回选中的抽帧策略，None表示保留所有帧
+pub(crate) fn compress(
+    data: &[u8],
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<(Vec<u8>, Option<Strategy>), GifError> {
+    let original_size = data.len() as f64 / 1024.0;
+    reporter.log(&format!("原始大小: {:.2} KB", original_size));
+
+    let frames = decode_frames(data)?;
+    let total_frames = frames.len();
+    reporter.log(&format!("原始帧数: {}", total_frames));
+
+    let mut min_skip = options.min_skip(average_fps(&frames));
+    let max_drop_skip = options.max_drop_skip(total_frames);
+    if min_skip > max_drop_skip && !options.keep_all_frames {
+        reporter.log(&format!("帧率上限需要每 {} 帧取1帧，超出 --max-drop 允许的比例，改为每 {} 帧取1帧",
+            min_skip, max_drop_skip));
+        min_skip = max_drop_skip;
+    }
+    let original_width = frames[0].image.width();
+    let mut frames = resize_frames(frames, options);
+    filters::apply(&mut frames, options, reporter);
+    let mut protect = scaled_region(options, original_width, frames[0].image.width());
+    if protect.is_none() && options.detect_text {
+        protect = text::detect_text_region(&frames[0].image);
+        text::log_detected(protect, reporter);
+    }
+
+    // 与gifsicle后端相同的最小保留帧数规则
+    let min_frames = std::cmp::max(3, (total_frames as f64 * options.min_frame_percent as f64 / 100.0) as usize)
+        .min(total_frames);
+    let max_skip = std::cmp::max(min_skip, total_frames.div_ceil(min_frames)).min(max_drop_skip);
+    if let Some(max_drop) = options.max_drop {
+        reporter.log(&format!("最多抽掉 {} 的帧: 最多每 {} 帧取1帧", max_drop, max_drop_skip));
+    }
+
+    let max_colors = options.colors.unwrap_or(256).clamp(2, 256);
+    let mut color_levels = vec![max_colors];
+    if options.no_lossy {
+        reporter.log("不减少颜色数，只抽帧");
+    } else {
+        color_levels.extend(COLOR_LEVELS.iter().copied().filter(|&c| c < max_colors));
+    }
+
+    // 每组保留帧的延迟为被合并帧的延迟之和
+    let strategy = |skip| (skip > 1).then_some(Strategy { skip, delay: 0 });
+    
+    // 保留所有帧时不抽帧；指定了优先保留的方面或保留所有帧时才缩小尺寸
+    let skips: Vec<usize> = if options.keep_all_frames {
+        if min_skip > 1 {
+            reporter.log("保留所有帧: 忽略帧率上限");
+        }
+        vec![1]
+    } else {
+        (min_skip..=max_skip).collect()
+    };
+    if options.optimizer != Optimizer::Grid && !options.keep_all_frames {
+        if options.prefer.is_some() {
+            reporter.log("使用 --optimizer 时忽略 --prefer");
+        }
+        let space = optimizer::Space { skips, lossy: vec![0], colors: color_levels };
+        let result = optimizer::search(&space, options, reporter, |point| {
+            let reduced = reduce_frames(&frames, space.skips[point.skip], options);
+            let output = encode(&reduced, space.colors[point.colors], protect, options)?;
+            let size = output.len() as f64 / 1024.0;
+            Ok((output, size))
+        })?;
+        let (point, output, size) = result.ok_or_else(|| options.cancel.no_results())?;
+        let skip = space.skips[point.skip];
+        if size > options.target_size_kb {
+            if options.strict {
+                return Err(GifError::SizeLimitExceeded { size_kb: size, limit_kb: options.target_size_kb });
+            }
+            reporter.log(&format!("\n无法达到目标大小 {} KB。", options.target_size_kb));
+            reporter.log(&format!("最接近的大小是 {:.2} KB。", size));
+        } else {
+            reporter.log(&format!("选中: 每 {} 帧取1帧, {} 色, 大小: {:.2} KB", skip, space.colors[point.colors], size));
+        }
+        return Ok((output, strategy(skip)));
+    }
+
+    let scales = &SCALE_LEVELS;
+    let scale_indices: Vec<usize> = if options.keep_all_frames || options.prefer.is_some() {
+        (0..scales.len()).collect()
+    } else {
+        vec![0]
+    };
+    // 默认顺序与优先保留帧数相同：先减少颜色数再抽帧
+    let mut candidates = options
+        .prefer
+        .unwrap_or(Prefer::Frames)
+        .groups(&skips, &color_levels, &scale_indices);
+    // 默认顺序下原尺寸都达不到目标时，以最大的抽帧间隔继续缩小尺寸，直到最小的缩放比例
+    if scale_indices.len() == 1 && let Some(&skip) = skips.last() {
+        candidates.extend((1..scales.len()).map(|index| color_levels.iter().map(|&colors| (skip, colors, index)).collect()));
+    }
+
+    let scaled: Vec<OnceCell<Vec<DecodedFrame>>> = scales.iter().map(|_| OnceCell::new()).collect();
+    // 只在抽帧间隔或缩放比例变化时重新合并帧
+    let mut reduced_key = None;
+    let mut reduced = Vec::new();
+    let mut best: Option<(Vec<u8>, usize, u16, f64)> = None;
+    for (skip, colors, index) in candidates.into_iter().flatten() {
+        // 时间用完时使用目前最好的结果，至少压缩出一个结果
+        if options.cancel.is_expired() && best.is_some() {
+            break;
+        }
+        let scale = scales[index];
+        let (source, protect, label) = if scale < 1.0 {
+            let source = scaled[index].get_or_init(|| scale_frames(&frames, scale, options));
+            (source.as_slice(), protect.map(|region| region.scaled(scale)), format!("缩放到 {:.0}%, ", scale * 100.0))
+        } else {
+            (frames.as_slice(), protect, String::new())
+        };
+        if reduced_key != Some((skip, index)) {
+            reduced = reduce_frames(source, skip, options);
+            reduced_key = Some((skip, index));
+        }
+
+        options.cancel.check()?;
+        let output = encode(&reduced, colors, protect, options)?;
+        let size = output.len() as f64 / 1024.0;
+        reporter.log(&format!("{}每 {} 帧取1帧, {} 色后大小: {:.2} KB", label, skip, colors, size));
+        reporter.event(&ProgressEvent::Candidate { stage: Stage::Native, skip, lossy: 0, colors, scale, size_kb: size });
+
+        if size <= options.target_size_kb {
+            reporter.log("已达到目标大小!");
+            // 第二轮: 颜色数取与上一级之间的值。少抽一帧的组合在第一轮中已经尝试过
+            if colors < max_colors && !options.cancel.is_expired() {
+                options.cancel.check()?;
+                let fine_colors = (colors + (colors * 2).min(max_colors)) / 2;
+                let fine_output = encode(&reduced, fine_colors, protect, options)?;
+                let fine_size = fine_output.len() as f64 / 1024.0;
+                reporter.log(&format!("细搜索: {}每 {} 帧取1帧, {} 色后大小: {:.2} KB",
+                    label, skip, fine_colors, fine_size));
+                reporter.event(&ProgressEvent::Candidate {
+                    stage: Stage::Fine,
+                    skip,
+                    lossy: 0,
+                    colors: fine_colors,
+                    scale,
+                    size_kb: fine_size,
+                });
+                if fine_size <= options.target_size_kb {
+                    reporter.log("细搜索找到画质更好的结果");
+                    reporter.event(&ProgressEvent::Selected {
+                        stage: Stage::Fine,
+                        skip,
+                        lossy: 0,
+                        colors: fine_colors,
+                        scale,
+                        size_kb: fine_size,
+                    });
+                    return Ok((fine_output, strategy(skip)));
+                }
+            }
+            reporter.event(&ProgressEvent::Selected { stage: Stage::Native, skip, lossy: 0, colors, scale, size_kb: size });
+            return Ok((output, strategy(skip)));
+        }
+        if best.as_ref().is_none_or(|(b, ..)| output.len() < b.len()) {
+            best = Some((output, skip, colors, scale));
+        }
+    }
+
+    let (best, skip, colors, scale) = best.ok_or(GifError::NoValidResults)?;
+    let best_size = best.len() as f64 / 1024.0;
+    if options.strict {
+        return Err(GifError::SizeLimitExceeded { size_kb: best_size, limit_kb: options.target_size_kb });
+    }
+    reporter.event(&ProgressEvent::Selected { stage: Stage::Native, skip, lossy: 0, colors, scale, size_kb: best_size });
+    reporter.log(&format!("\n无法达到目标大小 {} KB。", options.target_size_kb));
+    reporter.log(&format!("最接近的大小是 {:.2} KB。", best_size));
+    if scale_indices.len() == 1 && !options.cancel.is_expired() {
+        reporter.log(&format!("已降到画质下限（{} 色、缩小到 {:.0}%）。",
+            COLOR_LEVELS[COLOR_LEVELS.len() - 1], SCALE_LEVELS[SCALE_LEVELS.len() - 1] * 100.0));
+    }
+    Ok((best, strategy(skip)))
+}
@@ -0,0 +1,631 @@
+//! 在抽帧间隔、lossy级别和颜色数的联合空间中搜索
+//!
+//! 网格搜索按固定顺序逐级降级，遇到难压缩的GIF时常常在相邻两级之间错过更好的组合。
+//! 这里的搜索把达到目标大小的组合按画质损失计分，超出目标的组合按超出的比例计分且总是差于
+//! 达到目标的组合，最后选中计分最低的组合：
+//!
+//! - 模拟退火随机游走，温度随时间预算的消耗逐渐降低，前期可以接受更差的组合跳出局部最优。
+//! - 模型搜索用已有结果拟合对数大小的贝叶斯线性模型，按画质损失从小到大选下一个预测能达到
+//!   目标的组合；大小随每个维度的降级单调减小，一个组合超出目标时所有各维度都不差于它的组合
+//!   也一定超出，不再尝试，效果类似在画质损失上二分。适合每次压缩都很慢的大文件。
+
+use crate::options::{CompressionOptions, Optimizer};
+use crate::progress::{ProgressEvent, Stage, StopReason};
+use crate::reporter::Reporter;
+use crate::GifError;
+use std::collections::HashSet;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+/// 初始温度，与画质损失的计分同一单位
+const INITIAL_TEMPERATURE: f64 = 50.0;
+
+/// 预算用完时的温度
+const FINAL_TEMPERATURE: f64 = 0.5;
+
+/// 超出目标大小的组合的基础计分，大于任何达到目标的组合
+const OVER_TARGET_PENALTY: f64 = 1000.0;
+
+/// 伪随机数种子，固定种子使相同输入的搜索过程可以复现
+const SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// 大小模型各系数的先验(均值, 标准差)，依次为常数项、ln(抽帧间隔)、lossy/100、log2(颜色数)。
+/// 大小大致与保留的帧数成正比，颜色数每减半约小10%
+const PRIOR: [(f64, f64); FEATURES] = [(0.0, 10.0), (-1.0, 0.5), (-0.5, 0.5), (0.15, 0.2)];
+
+/// 模型的特征数
+const FEATURES: usize = 4;
+
+/// 对数大小的观测噪声标准差
+const MODEL_NOISE: f64 = 0.1;
+
+/// 预测大小减去此倍数的标准差后不超过目标的组合视为可能达到目标
+const OPTIMISM: f64 = 0.5;
+
+/// 搜索空间，每个维度按画质从高到低排列
+pub(crate) struct Space {
+    pub(crate) skips: Vec<usize>,
+    pub(crate) lossy: Vec<u32>,
+    pub(crate) colors: Vec<u16>,
+}
+
+/// 搜索空间中的一个组合，保存各维度的序号
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Point {
+    pub(crate) skip: usize,
+    pub(crate) lossy: usize,
+    pub(crate) colors: usize,
+}
+
+impl Space {
+    fn len(&self) -> usize {
+        self.skips.len() * self.lossy.len() * self.colors.len()
+    }
+
+    fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        (0..self.skips.len()).flat_map(move |skip| {
+            (0..self.lossy.len()).flat_map(move |lossy| {
+                (0..self.colors.len()).map(move |colors| Point { skip, lossy, colors })
+            })
+        })
+    }
+
+    /// 起点: 画质最好、画质最差和中间的组合
+    fn starts(&self) -> Vec<Point> {
+        let corner = |f: fn(usize) -> usize| Point {
+            skip: f(self.skips.len()),
+            lossy: f(self.lossy.len()),
+            colors: f(self.colors.len()),
+        };
+        let mut starts = Vec::new();
+        for point in [corner(|_| 0), corner(|len| len - 1), corner(|len| (len - 1) / 2)] {
+            if !starts.contains(&point) {
+                starts.push(point);
+            }
+        }
+        starts
+    }
+
+    /// 只改变一个维度一级的相邻组合
+    fn neighbors(&self, point: Point) -> Vec<Point> {
+        let step = |value: usize, len: usize| {
+            [value.checked_sub(1), (value + 1 < len).then_some(value + 1)].into_iter().flatten()
+        };
+        let mut neighbors = Vec::new();
+        neighbors.extend(step(point.skip, self.skips.len()).map(|skip| Point { skip, ..point }));
+        neighbors.extend(step(point.lossy, self.lossy.len()).map(|lossy| Point { lossy, ..point }));
+        neighbors.extend(step(point.colors, self.colors.len()).map(|colors| Point { colors, ..point }));
+        neighbors
+    }
+
+    /// 画质损失: 抽掉的帧的百分比、lossy级别的一半、颜色数每减半12.5
+    fn degradation(&self, point: Point) -> f64 {
+        let skip = self.skips[point.skip] as f64;
+        let colors = self.colors[point.colors] as f64;
+        (1.0 - 1.0 / skip) * 100.0
+            + self.lossy[point.lossy] as f64 / 2.0
+            + (self.colors[0] as f64 / colors).log2() * 12.5
+    }
+
+    /// 模型的特征
+    fn features(&self, point: Point) -> [f64; FEATURES] {
+        [
+            1.0,
+            (self.skips[point.skip] as f64).ln(),
+            self.lossy[point.lossy] as f64 / 100.0,
+            (self.colors[point.colors] as f64).log2(),
+        ]
+    }
+
+    fn cost(&self, point: Point, size: f64, target_size_kb: f64) -> f64 {
+        if size <= target_size_kb {
+            self.degradation(point)
+        } else {
+            OVER_TARGET_PENALTY * size / target_size_kb
+        }
+    }
+}
+
+/// xorshift64伪随机数
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// 时间预算
+///
+/// wasm32上没有计时器，只按已尝试的组合占整个空间的比例计算进度。
+struct Budget {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: Instant,
+    seconds: u64,
+    total: usize,
+}
+
+impl Budget {
+    fn new(seconds: u64, total: usize) -> Self {
+        Budget {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: Instant::now(),
+            seconds,
+            total,
+        }
+    }
+
+    /// 0-1的进度，取时间和尝试次数中消耗较多的一个
+    fn progress(&self, evaluated: usize) -> f64 {
+        (evaluated as f64 / self.total.max(1) as f64).max(self.time_used()).min(1.0)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn time_used(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() / self.seconds.max(1) as f64
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn time_used(&self) -> f64 {
+        let _ = self.seconds;
+        0.0
+    }
+}
+
+/// 按 `--optimizer` 选择的方式搜索，见 [`anneal`] 和 [`model_search`]
+pub(crate) fn search<T, F>(
+    space: &Space,
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+    evaluate: F,
+) -> Result<Option<(Point, T, f64)>, GifError>
+where
+    T: Send,
+    F: Fn(Point) -> Result<(T, f64), GifError> + Sync,
+{
+    match options.optimizer {
+        Optimizer::Bayes => model_search(space, options, reporter, evaluate),
+        Optimizer::Grid | Optimizer::Anneal => anneal(space, options, reporter, evaluate),
+    }
+}
+
+/// 在时间预算内用模拟退火搜索
+///
+/// `evaluate` 压缩一个组合并返回结果和大小(KB)，每轮并行尝试当前组合的最多 `threads` 个
+/// 未尝试过的相邻组合，相邻组合都尝试过时随机跳到一个未尝试的组合。返回计分最低的组合，
+/// 没有任何组合压缩成功时返回None。
+fn anneal<T, F>(
+    space: &Space,
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+    evaluate: F,
+) -> Result<Option<(Point, T, f64)>, GifError>
+where
+    T: Send,
+    F: Fn(Point) -> Result<(T, f64), GifError> + Sync,
+{
+    let target_size_kb = options.target_size_kb;
+    let budget = Budget::new(options.optimizer_budget, space.len());
+    reporter.log(&format!("\n模拟退火: 在 {} 秒内搜索 {} 个抽帧间隔、{} 个lossy级别、{} 个颜色数的组合...",
+        options.optimizer_budget, space.skips.len(), space.lossy.len(), space.colors.len()));
+
+    let mut rng = Rng(SEED);
+    let mut visited: HashSet<Point> = HashSet::new();
+    let mut current: Option<(Point, f64)> = None;
+    let mut best: Option<(Point, T, f64, f64)> = None;
+    let mut batch = space.starts();
+    while !batch.is_empty() && !options.cancel.is_expired() {
+        options.cancel.check()?;
+        let temperature = INITIAL_TEMPERATURE * (1.0 - budget.progress(visited.len())) + FINAL_TEMPERATURE;
+        let results = evaluate_batch(&batch, options.threads, &evaluate);
+        for (point, result) in batch.iter().copied().zip(results) {
+            visited.insert(point);
+            let (skip, lossy, colors) = (space.skips[point.skip], space.lossy[point.lossy], space.colors[point.colors]);
+            let (payload, size) = match result {
+                Ok(result) => result,
+                Err(GifError::Cancelled) => return Err(GifError::Cancelled),
+                // 时间用完时被终止的组合不计入结果，这一轮结束后停止搜索
+                Err(GifError::TimedOut) => {
+                    reporter.event(&ProgressEvent::CandidateStopped {
+                        stage: Stage::Optimizer,
+                        skip,
+                        lossy,
+                        colors,
+                        scale: 1.0,
+                        reason: StopReason::TimedOut,
+                    });
+                    continue;
+                }
+                Err(e) => {
+                    reporter.log(&format!("  每 {} 帧取1帧, lossy={}, {} 色压缩失败: {}", skip, lossy, colors, e));
+                    continue;
+                }
+            };
+            reporter.log(&format!("退火: 每 {} 帧取1帧, lossy={}, {} 色后大小: {:.2} KB", skip, lossy, colors, size));
+            reporter.event(&ProgressEvent::Candidate { stage: Stage::Optimizer, skip, lossy, colors, scale: 1.0, size_kb: size });
+
+            let cost = space.cost(point, size, target_size_kb);
+            if best.as_ref().is_none_or(|&(_, _, _, best_cost)| cost < best_cost) {
+                best = Some((point, payload, size, cost));
+            }
+            if current.is_none_or(|(_, current_cost)| accepts(cost, current_cost, temperature, &mut rng)) {
+                current = Some((point, cost));
+            }
+        }
+
+        if budget.progress(visited.len()) >= 1.0 {
+            break;
+        }
+        batch = next_batch(space, current.map(|(point, _)| point), &visited, options.threads.max(1), &mut rng);
+    }
+
+    reporter.log(&format!("模拟退火共尝试 {} 个组合", visited.len()));
+    Ok(best.map(|(point, payload, size, _)| (point, payload, size)))
+}
+
+/// Metropolis准则: 更好的组合总是接受，更差的按温度以一定概率接受
+fn accepts(cost: f64, current_cost: f64, temperature: f64, rng: &mut Rng) -> bool {
+    cost <= current_cost || rng.unit() < (-(cost - current_cost) / temperature).exp()
+}
+
+/// 用大小模型选择下一个组合，在时间预算内搜索
+///
+/// 先尝试画质最好、画质最差和中间的组合，之后每轮从没有被排除、画质损失小于当前最佳的
+/// 组合中选出最多 `threads` 个预测能达到目标且画质损失最小的组合。没有可选的组合时结束；
+/// 返回值与 [`anneal`] 相同。
+fn model_search<T, F>(
+    space: &Space,
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+    evaluate: F,
+) -> Result<Option<(Point, T, f64)>, GifError>
+where
+    T: Send,
+    F: Fn(Point) -> Result<(T, f64), GifError> + Sync,
+{
+    let target_size_kb = options.target_size_kb;
+    let budget = Budget::new(options.optimizer_budget, space.len());
+    reporter.log(&format!("\n模型搜索: 在 {} 秒内搜索 {} 个抽帧间隔、{} 个lossy级别、{} 个颜色数的组合...",
+        options.optimizer_budget, space.skips.len(), space.lossy.len(), space.colors.len()));
+
+    let mut visited: HashSet<Point> = HashSet::new();
+    let mut samples: Vec<(Point, f64)> = Vec::new();
+    let mut over_target: Vec<Point> = Vec::new();
+    let mut best: Option<(Point, T, f64, f64)> = None;
+    let mut batch: Vec<(Point, Option<f64>)> = space.starts().into_iter().map(|point| (point, None)).collect();
+    while !batch.is_empty() && !options.cancel.is_expired() {
+        options.cancel.check()?;
+        let points: Vec<Point> = batch.iter().map(|&(point, _)| point).collect();
+        let results = evaluate_batch(&points, options.threads, &evaluate);
+        for ((point, predicted), result) in batch.iter().copied().zip(results) {
+            visited.insert(point);
+            let (skip, lossy, colors) = (space.skips[point.skip], space.lossy[point.lossy], space.colors[point.colors]);
+            let (payload, size) = match result {
+                Ok(result) => result,
+                Err(GifError::Cancelled) => return Err(GifError::Cancelled),
+                // 时间用完时被终止的组合不计入结果，这一轮结束后停止搜索
+                Err(GifError::TimedOut) => {
+                    reporter.event(&ProgressEvent::CandidateStopped {
+                        stage: Stage::Optimizer,
+                        skip,
+                        lossy,
+                        colors,
+                        scale: 1.0,
+                        reason: StopReason::TimedOut,
+                    });
+                    continue;
+                }
+                Err(e) => {
+                    reporter.log(&format!("  每 {} 帧取1帧, lossy={}, {} 色压缩失败: {}", skip, lossy, colors, e));
+                    continue;
+                }
+            };
+            let predicted = predicted.map_or(String::new(), |predicted| format!(" (预测 {:.2} KB)", predicted));
+            reporter.log(&format!("模型搜索: 每 {} 帧取1帧, lossy={}, {} 色后大小: {:.2} KB{}",
+                skip, lossy, colors, size, predicted));
+            reporter.event(&ProgressEvent::Candidate { stage: Stage::Optimizer, skip, lossy, colors, scale: 1.0, size_kb: size });
+
+            samples.push((point, size));
+            if size > target_size_kb {
+                over_target.push(point);
+            }
+            let cost = space.cost(point, size, target_size_kb);
+            if best.as_ref().is_none_or(|&(_, _, _, best_cost)| cost < best_cost) {
+                best = Some((point, payload, size, cost));
+            }
+        }
+
+        if budget.progress(visited.len()) >= 1.0 {
+            break;
+        }
+        // 已经达到目标时只需要尝试画质损失更小的组合
+        let limit = best
+            .as_ref()
+            .filter(|&&(_, _, size, _)| size <= target_size_kb)
+            .map_or(f64::INFINITY, |&(point, _, _, _)| space.degradation(point));
+        batch = next_model_batch(space, &samples, &over_target, &visited, limit, target_size_kb, options.threads.max(1));
+    }
+
+    reporter.log(&format!("模型搜索共尝试 {} 个组合（搜索空间共 {} 个）", visited.len(), space.len()));
+    Ok(best.map(|(point, payload, size, _)| (point, payload, size)))
+}
+
+/// 模型搜索下一轮要尝试的组合和预测的大小
+fn next_model_batch(
+    space: &Space,
+    samples: &[(Point, f64)],
+    over_target: &[Point],
+    visited: &HashSet<Point>,
+    limit: f64,
+    target_size_kb: f64,
+    count: usize,
+) -> Vec<(Point, Option<f64>)> {
+    let model = SizeModel::fit(space, samples);
+    let log_target = target_size_kb.ln();
+    // (组合, 画质损失, 预测的对数大小, 标准差)
+    let mut candidates: Vec<(Point, f64, f64, f64)> = space
+        .points()
+        .filter(|point| !visited.contains(point))
+        .filter(|point| {
+            !over_target
+                .iter()
+                .any(|over| point.skip <= over.skip && point.lossy <= over.lossy && point.colors <= over.colors)
+        })
+        .map(|point| {
+            let (mean, deviation) = model.predict(&space.features(point));
+            (point, space.degradation(point), mean, deviation)
+        })
+        .filter(|&(_, degradation, _, _)| degradation < limit)
+        .collect();
+    candidates.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut batch: Vec<(Point, Option<f64>)> = candidates
+        .iter()
+        .filter(|&&(_, _, mean, deviation)| mean - OPTIMISM * deviation <= log_target)
+        .take(count)
+        .map(|&(point, _, mean, _)| (point, Some(mean.exp())))
+        .collect();
+    // 预测都达不到目标时，尝试最可能达到目标的组合
+    if batch.is_empty() {
+        let likely = |&(_, _, mean, deviation): &(Point, f64, f64, f64)| (log_target - mean) / deviation;
+        if let Some(candidate) = candidates.iter().max_by(|a, b| likely(a).total_cmp(&likely(b))) {
+            batch.push((candidate.0, Some(candidate.2.exp())));
+        }
+    }
+    batch
+}
+
+/// 对数大小的贝叶斯线性模型，系数的后验为正态分布
+struct SizeModel {
+    mean: [f64; FEATURES],
+    covariance: [[f64; FEATURES]; FEATURES],
+}
+
+impl SizeModel {
+    /// 由先验和已有结果计算后验；常数项的先验很宽，第一个结果就能确定大致的大小
+    fn fit(space: &Space, samples: &[(Point, f64)]) -> Self {
+        let noise = 1.0 / (MODEL_NOISE * MODEL_NOISE);
+        let mut precision = [[0.0; FEATURES]; FEATURES];
+        let mut weighted = [0.0; FEATURES];
+        for (i, &(mean, deviation)) in PRIOR.iter().enumerate() {
+            precision[i][i] = 1.0 / (deviation * deviation);
+            weighted[i] = mean * precision[i][i];
+        }
+        for &(point, size) in samples {
+            let x = space.features(point);
+            let y = size.max(f64::MIN_POSITIVE).ln();
+            for i in 0..FEATURES {
+                for j in 0..FEATURES {
+                    precision[i][j] += x[i] * x[j] * noise;
+                }
+                weighted[i] += x[i] * y * noise;
+            }
+        }
+
+        let covariance = invert(precision);
+        let mut mean = [0.0; FEATURES];
+        for (i, value) in mean.iter_mut().enumerate() {
+            *value = (0..FEATURES).map(|j| covariance[i][j] * weighted[j]).sum();
+        }
+        SizeModel { mean, covariance }
+    }
+
+    /// 预测的对数大小及其标准差
+    fn predict(&self, x: &[f64; FEATURES]) -> (f64, f64) {
+        let mean = (0..FEATURES).map(|i| self.mean[i] * x[i]).sum();
+        let variance: f64 = (0..FEATURES)
+            .flat_map(|i| (0..FEATURES).map(move |j| (i, j)))
+            .map(|(i, j)| x[i] * self.covariance[i][j] * x[j])
+            .sum();
+        (mean, (variance + MODEL_NOISE * MODEL_NOISE).sqrt())
+    }
+}
+
+/// Gauss-Jordan消元求逆，`matrix` 为正定矩阵
+fn invert(mut matrix: [[f64; FEATURES]; FEATURES]) -> [[f64; FEATURES]; FEATURES] {
+    let mut inverse = [[0.0; FEATURES]; FEATURES];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    for column in 0..FEATURES {
+        let pivot = (column..FEATURES)
+            .max_by(|&a, &b| matrix[a][column].abs().total_cmp(&matrix[b][column].abs()))
+            .unwrap_or(column);
+        matrix.swap(column, pivot);
+        inverse.swap(column, pivot);
+        let scale = matrix[column][column];
+        for j in 0..FEATURES {
+            matrix[column][j] /= scale;
+            inverse[column][j] /= scale;
+        }
+        for row in 0..FEATURES {
+            if row != column {
+                let factor = matrix[row][column];
+                for j in 0..FEATURES {
+                    matrix[row][j] -= factor * matrix[column][j];
+                    inverse[row][j] -= factor * inverse[column][j];
+                }
+            }
+        }
+    }
+    inverse
+}
+
+/// 下一轮要尝试的组合
+fn next_batch(space: &Space, current: Option<Point>, visited: &HashSet<Point>, count: usize, rng: &mut Rng) -> Vec<Point> {
+    let mut candidates: Vec<Point> = current
+        .map(|point| space.neighbors(point))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|point| !visited.contains(point))
+        .collect();
+    if candidates.is_empty() {
+        let unvisited: Vec<Point> = space.points().filter(|point| !visited.contains(point)).collect();
+        if unvisited.is_empty() {
+            return Vec::new();
+        }
+        candidates.push(unvisited[rng.below(unvisited.len())]);
+    }
+
+    let mut batch = Vec::with_capacity(count);
+    while batch.len() < count && !candidates.is_empty() {
+        batch.push(candidates.swap_remove(rng.below(candidates.len())));
+    }
+    batch
+}
+
+/// 并行压缩一轮的组合，结果与 `points` 一一对应
+fn evaluate_batch<T, F>(points: &[Point], threads: usize, evaluate: &F) -> Vec<Result<(T, f64), GifError>>
+where
+    T: Send,
+    F: Fn(Point) -> Result<(T, f64), GifError> + Sync,
+{
+    if cfg!(target_arch = "wasm32") || threads <= 1 || points.len() <= 1 {
+        return points.iter().map(|&point| evaluate(point)).collect();
+    }
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = points.iter().map(|&point| scope.spawn(move || evaluate(point))).collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or(Err(GifError::NoValidResults)))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 抽帧间隔的搜索空间，大小为 `480 / 抽帧间隔`
+    fn skip_space() -> Space {
+        Space { skips: vec![1, 2, 3, 4, 6, 8], lossy: vec![0], colors: vec![256] }
+    }
+
+    fn skip_point(space: &Space, skip: usize) -> Point {
+        Point { skip: space.skips.iter().position(|&s| s == skip).unwrap(), lossy: 0, colors: 0 }
+    }
+
+    fn skips(space: &Space, batch: &[(Point, Option<f64>)]) -> Vec<usize> {
+        batch.iter().map(|&(point, _)| space.skips[point.skip]).collect()
+    }
+
+    #[test]
+    fn accepts_better_and_equal_costs() {
+        let mut rng = Rng(SEED);
+        for _ in 0..100 {
+            assert!(accepts(10.0, 20.0, FINAL_TEMPERATURE, &mut rng));
+            assert!(accepts(20.0, 20.0, FINAL_TEMPERATURE, &mut rng));
+        }
+    }
+
+    #[test]
+    fn accepts_worse_costs_by_temperature() {
+        let rate = |delta: f64, temperature: f64| {
+            let mut rng = Rng(SEED);
+            (0..10_000).filter(|_| accepts(20.0 + delta, 20.0, temperature, &mut rng)).count() as f64 / 10_000.0
+        };
+        assert_eq!(rate(50.0, FINAL_TEMPERATURE), 0.0);
+        for (delta, temperature) in [(10.0_f64, 50.0), (50.0, 50.0), (100.0, 25.0)] {
+            let expected = (-delta / temperature).exp();
+            assert!((rate(delta, temperature) - expected).abs() < 0.02, "delta={} temperature={}", delta, temperature);
+        }
+    }
+
+    #[test]
+    fn fits_log_linear_sizes() {
+        let space = Space { skips: vec![1, 2, 4, 8], lossy: vec![0, 40, 80, 120], colors: vec![256, 128, 64, 32] };
+        let size = |point: Point| {
+            let [_, ln_skip, lossy, log_colors] = space.features(point);
+            (2.0 - 0.9 * ln_skip - 0.6 * lossy + 0.12 * log_colors).exp()
+        };
+        let samples: Vec<(Point, f64)> = space.points().map(|point| (point, size(point))).collect();
+        let model = SizeModel::fit(&space, &samples);
+
+        for (coefficient, expected) in model.mean[1..].iter().zip([-0.9, -0.6, 0.12]) {
+            assert!((coefficient - expected).abs() < 0.01, "{:?}", model.mean);
+        }
+        for point in space.points() {
+            let (mean, deviation) = model.predict(&space.features(point));
+            assert!((mean - size(point).ln()).abs() < 0.01);
+            assert!(deviation < MODEL_NOISE * 1.1);
+        }
+    }
+
+    #[test]
+    fn picks_least_degraded_points_predicted_to_fit() {
+        let space = skip_space();
+        let samples = [(skip_point(&space, 1), 480.0), (skip_point(&space, 8), 60.0)];
+        let visited: HashSet<Point> = samples.iter().map(|&(point, _)| point).collect();
+
+        let batch = next_model_batch(&space, &samples, &[], &visited, f64::INFINITY, 200.0, 2);
+        assert_eq!(skips(&space, &batch), [3, 4]);
+        let predicted = batch[0].1.unwrap();
+        assert!((predicted - 160.0).abs() < 16.0, "预测 {}", predicted);
+    }
+
+    #[test]
+    fn skips_points_dominated_by_over_target() {
+        let space = skip_space();
+        let samples = [(skip_point(&space, 1), 480.0), (skip_point(&space, 8), 60.0), (skip_point(&space, 3), 210.0)];
+        let visited: HashSet<Point> = samples.iter().map(|&(point, _)| point).collect();
+        let over_target = [skip_point(&space, 3)];
+
+        let batch = next_model_batch(&space, &samples, &over_target, &visited, f64::INFINITY, 200.0, 2);
+        assert_eq!(skips(&space, &batch), [4, 6]);
+    }
+
+    #[test]
+    fn stays_below_degradation_limit() {
+        let space = skip_space();
+        let samples = [(skip_point(&space, 1), 480.0), (skip_point(&space, 8), 60.0)];
+        let visited: HashSet<Point> = samples.iter().map(|&(point, _)| point).collect();
+        let limit = space.degradation(skip_point(&space, 4));
+
+        let batch = next_model_batch(&space, &samples, &[], &visited, limit, 200.0, 2);
+        assert_eq!(skips(&space, &batch), [3]);
+        let batch = next_model_batch(&space, &samples, &[], &visited, space.degradation(skip_point(&space, 2)), 200.0, 2);
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn falls_back_to_most_likely_point() {
+        let space = skip_space();
+        let samples = [(skip_point(&space, 1), 480.0), (skip_point(&space, 8), 60.0)];
+        let visited: HashSet<Point> = samples.iter().map(|&(point, _)| point).collect();
+
+        // 只剩预测都达不到目标的组合时尝试预测最小的
+        let batch = next_model_batch(&space, &samples, &[], &visited, f64::INFINITY, 30.0, 2);
+        assert_eq!(skips(&space, &batch), [6]);
+    }
+}
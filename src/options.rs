@@ -0,0 +1,693 @@
+use crate::cancel::CancellationToken;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// 默认的有损压缩级别
+pub const DEFAULT_LOSSY_LEVELS: [u32; 8] = [30, 60, 90, 120, 150, 180, 210, 240];
+
+/// NeuQuant采样因子的取值范围
+pub const SAMPLE_FAC_RANGE: RangeInclusive<u32> = 1..=30;
+
+/// 压缩后端
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// 调用外部gifsicle命令
+    Gifsicle,
+    /// 纯Rust实现，不依赖外部命令，可以编译到wasm32
+    Native,
+}
+
+/// 输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// GIF动画
+    Gif,
+    /// VP9编码的WebM视频，需要启用 `webm` 特性并安装ffmpeg
+    Webm,
+}
+
+/// 纯Rust后端的调色板生成算法
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Quantizer {
+    /// 中位切分，速度快且结果确定，逐帧生成调色板
+    MedianCut,
+    /// NeuQuant神经网络量化，逐帧生成调色板，适合照片类内容
+    NeuQuant,
+    /// k-means聚类生成所有帧共用的调色板，品牌色较多时偏色更少
+    KMeans,
+}
+
+impl FromStr for Quantizer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "median-cut" => Ok(Quantizer::MedianCut),
+            "neuquant" => Ok(Quantizer::NeuQuant),
+            "kmeans" => Ok(Quantizer::KMeans),
+            _ => Err(format!("未知的量化算法: {}", s)),
+        }
+    }
+}
+
+/// 降噪强度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Denoise {
+    /// 只做时间降噪
+    Light,
+    /// 时间降噪加一次空间降噪
+    Medium,
+    /// 更高的阈值，时间降噪加两次空间降噪
+    Strong,
+}
+
+impl FromStr for Denoise {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "light" => Ok(Denoise::Light),
+            "medium" => Ok(Denoise::Medium),
+            "strong" => Ok(Denoise::Strong),
+            _ => Err(format!("未知的降噪强度: {}", s)),
+        }
+    }
+}
+
+/// 颜色量化时计算颜色距离和平均色的颜色空间
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorSpace {
+    /// 直接使用sRGB字节值
+    Srgb,
+    /// 线性光，渐变和暗部的色带更少
+    Linear,
+    /// OKLab感知均匀颜色空间
+    Oklab,
+}
+
+impl FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srgb" => Ok(ColorSpace::Srgb),
+            "linear" => Ok(ColorSpace::Linear),
+            "oklab" => Ok(ColorSpace::Oklab),
+            _ => Err(format!("未知的颜色空间: {}", s)),
+        }
+    }
+}
+
+/// 抽帧时被丢弃帧的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DropMode {
+    /// 直接丢弃
+    Discard,
+    /// 与保留的帧平均混合，类似运动模糊，大量抽帧时动作更连贯
+    Blend,
+    /// 丢弃后在相邻的保留帧之间用运动补偿合成一帧中间画面
+    Interpolate,
+}
+
+impl FromStr for DropMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "discard" => Ok(DropMode::Discard),
+            "blend" => Ok(DropMode::Blend),
+            "interpolate" => Ok(DropMode::Interpolate),
+            _ => Err(format!("未知的抽帧方式: {}", s)),
+        }
+    }
+}
+
+/// 压缩时优先保留的方面，其余方面先被降级
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Prefer {
+    /// 保留帧数：先提高lossy级别（纯Rust后端为减少颜色数），再缩小尺寸，最后才抽帧
+    Frames,
+    /// 保留画质：先抽帧，再缩小尺寸，最后才使用有损压缩
+    Quality,
+    /// 保留尺寸：先提高lossy级别，再抽帧，最后才缩小尺寸
+    Resolution,
+}
+
+impl FromStr for Prefer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "frames" => Ok(Prefer::Frames),
+            "quality" => Ok(Prefer::Quality),
+            "resolution" => Ok(Prefer::Resolution),
+            _ => Err(format!("未知的优先保留方面: {}", s)),
+        }
+    }
+}
+
+impl Prefer {
+    /// 按降级顺序排列抽帧、画质和缩放级别的所有组合
+    ///
+    /// 每个参数列表都从最好的级别开始。返回的每一组只有最先降级的方面不同，
+    /// 可以并行尝试；组与组之间按顺序尝试。
+    pub(crate) fn groups<F: Copy, Q: Copy, S: Copy>(self, frames: &[F], quality: &[Q], scales: &[S]) -> Vec<Vec<(F, Q, S)>> {
+        let mut groups = Vec::new();
+        match self {
+            Prefer::Frames => {
+                for &f in frames {
+                    for &s in scales {
+                        groups.push(quality.iter().map(|&q| (f, q, s)).collect());
+                    }
+                }
+            }
+            Prefer::Quality => {
+                for &q in quality {
+                    for &s in scales {
+                        groups.push(frames.iter().map(|&f| (f, q, s)).collect());
+                    }
+                }
+            }
+            Prefer::Resolution => {
+                for &s in scales {
+                    for &f in frames {
+                        groups.push(quality.iter().map(|&q| (f, q, s)).collect());
+                    }
+                }
+            }
+        }
+        groups
+    }
+}
+
+/// 搜索压缩参数的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Optimizer {
+    /// 按固定的网格和顺序尝试抽帧间隔和lossy级别
+    Grid,
+    /// 模拟退火，在时间预算内随机搜索抽帧间隔、lossy级别和颜色数的联合空间
+    Anneal,
+    /// 用前几次压缩的结果拟合大小模型，预测下一个最可能以最小画质损失达到目标的组合，
+    /// 超出目标的组合排除所有画质更好的组合，尽量少调用gifsicle
+    Bayes,
+}
+
+impl FromStr for Optimizer {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "grid" => Ok(Optimizer::Grid),
+            "anneal" => Ok(Optimizer::Anneal),
+            "bayes" => Ok(Optimizer::Bayes),
+            _ => Err(format!("未知的优化器: {}", s)),
+        }
+    }
+}
+
+/// 针对内容类型调好的一组参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Profile {
+    /// 终端和界面录屏：大面积纯色、文字锐利、帧间变化集中在局部
+    Screen,
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "screen" => Ok(Profile::Screen),
+            _ => Err(format!("未知的内容类型: {}", s)),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gif" => Ok(OutputFormat::Gif),
+            "webm" => Ok(OutputFormat::Webm),
+            _ => Err(format!("未知的输出格式: {}", s)),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gifsicle" => Ok(Backend::Gifsicle),
+            "native" => Ok(Backend::Native),
+            _ => Err(format!("未知的压缩后端: {}", s)),
+        }
+    }
+}
+
+/// 图像中的矩形区域，文本格式为 `x,y,宽,高`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// 像素是否位于区域内
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && y >= self.y && x - self.x < self.width && y - self.y < self.height
+    }
+    
+    /// 按缩放比例换算区域，边界向外取整
+    pub(crate) fn scaled(&self, scale: f64) -> Region {
+        let x = (self.x as f64 * scale).floor() as u32;
+        let y = (self.y as f64 * scale).floor() as u32;
+        let right = (self.x.saturating_add(self.width) as f64 * scale).ceil() as u32;
+        let bottom = (self.y.saturating_add(self.height) as f64 * scale).ceil() as u32;
+        Region { x, y, width: right - x, height: bottom - y }
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("无效的区域: {}", s))?;
+        match values[..] {
+            [x, y, width, height] if width > 0 && height > 0 => Ok(Region { x, y, width, height }),
+            _ => Err(format!("区域格式应为 x,y,宽,高: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Region {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+impl From<Region> for String {
+    fn from(region: Region) -> Self {
+        region.to_string()
+    }
+}
+
+/// `#rrggbb` 格式的RGB颜色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HexColor(pub [u8; 3]);
+
+impl FromStr for HexColor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(format!("颜色格式应为 #rrggbb: {}", s));
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| format!("无效的颜色: {}", s));
+        Ok(HexColor([channel(0)?, channel(2)?, channel(4)?]))
+    }
+}
+
+impl TryFrom<String> for HexColor {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for HexColor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0[0], self.0[1], self.0[2])
+    }
+}
+
+impl From<HexColor> for String {
+    fn from(color: HexColor) -> Self {
+        color.to_string()
+    }
+}
+
+/// 0-100的百分比，可以写成 `50%` 或 `50`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Percent(pub u32);
+
+impl FromStr for Percent {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().trim_end_matches('%').trim().parse::<u32>() {
+            Ok(value) if value <= 100 => Ok(Percent(value)),
+            _ => Err(format!("百分比应为0-100: {}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for Percent {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Percent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}%", self.0)
+    }
+}
+
+impl From<Percent> for String {
+    fn from(percent: Percent) -> Self {
+        percent.to_string()
+    }
+}
+
+/// 压缩选项
+///
+/// 序列化格式与配置文件相同，缺省的字段使用默认值。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case", default)]
+pub struct CompressionOptions {
+    /// 目标文件大小(KB)
+    #[serde(rename = "target")]
+    pub target_size_kb: f64,
+    /// 保留的最小帧数百分比
+    #[serde(rename = "min-frames")]
+    pub min_frame_percent: u32,
+    /// 并行处理线程数，0表示使用系统CPU核心数
+    pub threads: usize,
+    /// 依次尝试的lossy级别
+    pub lossy_levels: Vec<u32>,
+    /// 压缩后端
+    pub backend: Backend,
+    /// 临时文件目录，None表示使用系统临时目录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temp_dir: Option<PathBuf>,
+    /// 最大宽度，超出时等比缩小
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_width: Option<u32>,
+    /// 最大高度，超出时等比缩小
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_height: Option<u32>,
+    /// 缩小后的锐化强度(0-100)，0表示不锐化
+    pub sharpen: u8,
+    /// 最大帧率
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_fps: Option<f64>,
+    /// 最大颜色数(2-256)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colors: Option<u16>,
+    /// 严格模式：无法满足目标大小或尺寸限制时报错
+    pub strict: bool,
+    /// 输出格式
+    pub format: OutputFormat,
+    /// 循环次数，None表示无限循环，Some(0)表示只播放一次
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub loop_count: Option<u16>,
+    /// 所有帧几乎相同时输出单帧图像
+    pub collapse_static: bool,
+    /// 无损模式，只删除元数据、合并相同的帧并做帧间优化
+    pub lossless: bool,
+    /// 只抽帧和做基础优化，不使用lossy，纯Rust后端也不减少颜色数
+    pub no_lossy: bool,
+    /// 保留所有帧，只调整lossy、颜色数和缩放比例
+    pub keep_all_frames: bool,
+    /// 优先保留的方面，None时使用默认的搜索顺序
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefer: Option<Prefer>,
+    /// 最多抽掉的帧数比例，无论目标大小多紧都不会超过
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_drop: Option<Percent>,
+    /// 搜索压缩参数的方式
+    pub optimizer: Optimizer,
+    /// `anneal` 和 `bayes` 优化器的时间预算(秒)
+    pub optimizer_budget: u64,
+    /// 裁掉所有帧都相同的纯色边框
+    pub autocrop: bool,
+    /// 压缩前降噪，None表示不降噪
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denoise: Option<Denoise>,
+    /// 检测纯色背景并转为透明
+    pub key_background: bool,
+    /// 转为透明的颜色
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transparent_color: Option<HexColor>,
+    /// 转为透明时每个通道允许的差异
+    pub tolerance: u8,
+    /// 冻结几乎不变的像素，让帧间透明优化生效
+    pub freeze_static: bool,
+    /// 聚类近似重复的帧，重复帧使用同一画面，相邻的合并为一帧
+    pub dedupe_frames: bool,
+    /// 保护区域，区域内不做有损压缩并使用独立的调色板
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protect_region: Option<Region>,
+    /// 未设置保护区域时自动检测文字区域并作为保护区域
+    pub detect_text: bool,
+    /// 抽帧时被丢弃帧的处理方式
+    pub drop_mode: DropMode,
+    /// 纯Rust后端的调色板生成算法
+    pub quantizer: Quantizer,
+    /// NeuQuant采样因子(1-30)，1最慢质量最好，30最快
+    pub sample_fac: u32,
+    /// 颜色量化使用的颜色空间
+    pub color_space: ColorSpace,
+    /// 对平滑渐变做有序抖动，减少量化后的色带
+    pub deband: bool,
+    /// 取消令牌
+    #[serde(skip)]
+    pub cancel: CancellationToken,
+}
+
+impl Default for CompressionOptions {
+    fn default() -> Self {
+        Self {
+            target_size_kb: 500.0,
+            min_frame_percent: 10,
+            threads: 0,
+            lossy_levels: DEFAULT_LOSSY_LEVELS.to_vec(),
+            backend: Backend::Gifsicle,
+            temp_dir: None,
+            max_width: None,
+            max_height: None,
+            sharpen: 0,
+            max_fps: None,
+            colors: None,
+            strict: false,
+            format: OutputFormat::Gif,
+            loop_count: None,
+            collapse_static: false,
+            lossless: false,
+            no_lossy: false,
+            keep_all_frames: false,
+            prefer: None,
+            max_drop: None,
+            optimizer: Optimizer::Grid,
+            optimizer_budget: 30,
+            autocrop: false,
+            denoise: None,
+            key_background: false,
+            transparent_color: None,
+            tolerance: 24,
+            freeze_static: false,
+            dedupe_frames: false,
+            protect_region: None,
+            detect_text: false,
+            drop_mode: DropMode::Discard,
+            quantizer: Quantizer::MedianCut,
+            sample_fac: 10,
+            color_space: ColorSpace::Srgb,
+            deband: false,
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+
+impl CompressionOptions {
+    /// 获取临时文件目录
+    pub fn temp_dir(&self) -> Option<&Path> {
+        self.temp_dir.as_deref()
+    }
+    
+    /// 是否需要改变尺寸、颜色数或循环次数
+    pub fn has_transforms(&self) -> bool {
+        self.max_width.is_some()
+            || self.max_height.is_some()
+            || self.colors.is_some()
+            || self.loop_count.is_some()
+    }
+    
+    /// 是否可以使用gifsicle的有损压缩
+    ///
+    /// gifsicle无法只对部分区域做有损压缩，有保护区域时不使用lossy。
+    pub fn lossy_allowed(&self) -> bool {
+        !self.no_lossy && self.protect_region.is_none()
+    }
+    
+    /// 满足帧率上限所需的最小抽帧间隔
+    pub fn min_skip(&self, source_fps: f64) -> usize {
+        match self.max_fps {
+            Some(max_fps) if max_fps > 0.0 && source_fps > max_fps => {
+                (source_fps / max_fps).ceil() as usize
+            }
+            _ => 1,
+        }
+    }
+    
+    /// `max_drop` 允许的最大抽帧间隔，未设置时不限制
+    pub fn max_drop_skip(&self, frame_count: usize) -> usize {
+        let Some(Percent(percent)) = self.max_drop else {
+            return usize::MAX;
+        };
+        // 每skip帧取1帧时保留 ceil(帧数/skip) 帧
+        let allowed = frame_count * percent as usize / 100;
+        let mut skip = 1;
+        while skip < frame_count && frame_count - frame_count.div_ceil(skip + 1) <= allowed {
+            skip += 1;
+        }
+        skip
+    }
+    
+    /// 满足帧率上限的最小帧延迟(1/100秒)
+    pub fn min_delay(&self) -> u16 {
+        match self.max_fps {
+            Some(max_fps) if max_fps > 0.0 => (100.0 / max_fps).ceil() as u16,
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_region() {
+        assert_eq!("10,20,30,40".parse(), Ok(Region { x: 10, y: 20, width: 30, height: 40 }));
+        assert_eq!(" 0, 0, 1, 1 ".parse(), Ok(Region { x: 0, y: 0, width: 1, height: 1 }));
+    }
+
+    #[test]
+    fn rejects_bad_region() {
+        assert!("10;20;30;40".parse::<Region>().is_err());
+        assert!("10 20 30 40".parse::<Region>().is_err());
+        assert!("10,20,30".parse::<Region>().is_err());
+        assert!("10,20,30,40,50".parse::<Region>().is_err());
+        assert!("10,20,0,40".parse::<Region>().is_err());
+        assert!("-1,20,30,40".parse::<Region>().is_err());
+        assert!("".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn scales_region_outward() {
+        let region = Region { x: 3, y: 5, width: 7, height: 9 };
+        // 起点向下取整，终点向上取整
+        assert_eq!(region.scaled(0.5), Region { x: 1, y: 2, width: 4, height: 5 });
+        assert_eq!(region.scaled(1.0), region);
+        // 缩小很多时也至少保留一个像素
+        assert_eq!(Region { x: 1, y: 1, width: 1, height: 1 }.scaled(0.1), Region { x: 0, y: 0, width: 1, height: 1 });
+    }
+
+    #[test]
+    fn scaled_region_at_image_edge_covers_last_pixel() {
+        for (original, scaled) in [(100u32, 37u32), (25, 7), (41, 23), (101, 51)] {
+            let scale = scaled as f64 / original as f64;
+            let region = Region { x: original - 10, y: 0, width: 10, height: original }.scaled(scale);
+            assert!(region.contains(scaled - 1, scaled - 1), "{} -> {}: {:?}", original, scaled, region);
+            assert!(region.contains(region.x, 0));
+            // 浮点误差最多让区域超出图像一个像素
+            assert!((scaled..=scaled + 1).contains(&(region.x + region.width)), "{} -> {}: {:?}", original, scaled, region);
+        }
+    }
+
+    #[test]
+    fn parses_hex_color() {
+        assert_eq!("#ff8000".parse(), Ok(HexColor([0xff, 0x80, 0x00])));
+        assert_eq!("00FF7f".parse(), Ok(HexColor([0x00, 0xff, 0x7f])));
+        assert_eq!(HexColor([1, 2, 255]).to_string(), "#0102ff");
+    }
+
+    #[test]
+    fn rejects_bad_hex_color() {
+        assert!("#fff".parse::<HexColor>().is_err());
+        assert!("#ff80000".parse::<HexColor>().is_err());
+        assert!("#gg0000".parse::<HexColor>().is_err());
+        assert!("#ff 800".parse::<HexColor>().is_err());
+        assert!("#ffé00".parse::<HexColor>().is_err());
+    }
+
+    #[test]
+    fn parses_percent() {
+        assert_eq!("50%".parse(), Ok(Percent(50)));
+        assert_eq!("50".parse(), Ok(Percent(50)));
+        assert_eq!(" 0 % ".parse(), Ok(Percent(0)));
+        assert_eq!("100%".parse(), Ok(Percent(100)));
+    }
+
+    #[test]
+    fn rejects_bad_percent() {
+        assert!("101%".parse::<Percent>().is_err());
+        assert!("-5%".parse::<Percent>().is_err());
+        assert!("50%%x".parse::<Percent>().is_err());
+        assert!("".parse::<Percent>().is_err());
+    }
+
+    fn max_drop_skip(max_drop: Option<u32>, frame_count: usize) -> usize {
+        CompressionOptions { max_drop: max_drop.map(Percent), ..Default::default() }.max_drop_skip(frame_count)
+    }
+
+    #[test]
+    fn max_drop_skip_is_unlimited_without_max_drop() {
+        assert_eq!(max_drop_skip(None, 10), usize::MAX);
+    }
+
+    #[test]
+    fn max_drop_skip_keeps_dropped_frames_within_percent() {
+        assert_eq!(max_drop_skip(Some(0), 10), 1);
+        // 每2帧取1帧抽掉5帧，每3帧取1帧抽掉6帧
+        assert_eq!(max_drop_skip(Some(50), 10), 2);
+        assert_eq!(max_drop_skip(Some(49), 10), 1);
+        // 每7帧取1帧保留2帧，每8帧取1帧只保留1帧
+        assert_eq!(max_drop_skip(Some(75), 8), 7);
+        assert_eq!(max_drop_skip(Some(100), 10), 10);
+    }
+
+    #[test]
+    fn max_drop_skip_handles_few_frames() {
+        assert_eq!(max_drop_skip(Some(100), 1), 1);
+        assert_eq!(max_drop_skip(Some(100), 0), 1);
+        assert_eq!(max_drop_skip(Some(50), 2), 2);
+    }
+}
@@ -0,0 +1,30 @@
+use directories::ProjectDirs;
+use std::fs;
+use std::path::PathBuf;
+
+/// 获取本程序在当前平台上的标准目录
+///
+/// Linux遵循XDG规范（`~/.config/gif_compressor`、`~/.cache/gif_compressor`），
+/// macOS和Windows使用各自系统约定的位置。
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "gif_compressor")
+}
+
+/// 用户配置文件路径
+pub fn user_config_file() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.config_dir().join("config.toml"))
+}
+
+/// 缓存目录
+pub fn cache_dir() -> Option<PathBuf> {
+    project_dirs().map(|dirs| dirs.cache_dir().to_path_buf())
+}
+
+/// 压缩过程中的临时工作目录，位于缓存目录下，不存在时自动创建
+///
+/// 无法确定或创建时返回None，调用方应退回到系统临时目录。
+pub fn work_dir() -> Option<PathBuf> {
+    let dir = cache_dir()?.join("work");
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
@@ -0,0 +1,161 @@
+use crate::options::{CompressionOptions, OutputFormat, Profile, DEFAULT_LOSSY_LEVELS};
+
+/// 针对常见平台的预设参数
+#[derive(Debug, Clone, Copy)]
+pub struct Preset {
+    /// 预设名称，用于 `--preset`
+    pub name: &'static str,
+    /// 简短说明
+    pub description: &'static str,
+    /// 目标文件大小(KB)
+    pub target_size_kb: f64,
+    /// 最大宽度
+    pub max_width: Option<u32>,
+    /// 最大高度
+    pub max_height: Option<u32>,
+    /// 最大帧率
+    pub max_fps: Option<f64>,
+    /// 最大颜色数
+    pub colors: Option<u16>,
+    /// 是否为硬性限制，无法满足时报错
+    pub strict: bool,
+    /// 启用 `webm` 特性时是否输出WebM
+    pub prefer_webm: bool,
+    /// 循环次数，None表示无限循环，Some(0)表示只播放一次
+    pub loop_count: Option<u16>,
+    /// 有损压缩级别，None表示使用默认级别
+    pub lossy_levels: Option<&'static [u32]>,
+}
+
+/// 内置预设
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "discord-emoji",
+        description: "Discord表情: 256KB以内, 128x128",
+        target_size_kb: 256.0,
+        max_width: Some(128),
+        max_height: Some(128),
+        max_fps: Some(30.0),
+        colors: None,
+        strict: true,
+        prefer_webm: false,
+        loop_count: None,
+        lossy_levels: None,
+    },
+    Preset {
+        name: "discord-sticker",
+        description: "Discord贴纸: 256KB以内, 320x320",
+        target_size_kb: 256.0,
+        max_width: Some(320),
+        max_height: Some(320),
+        max_fps: Some(30.0),
+        colors: None,
+        strict: true,
+        prefer_webm: false,
+        loop_count: None,
+        lossy_levels: None,
+    },
+    Preset {
+        name: "slack",
+        description: "Slack自定义表情: 128KB以内, 128x128",
+        target_size_kb: 128.0,
+        max_width: Some(128),
+        max_height: Some(128),
+        max_fps: Some(20.0),
+        colors: None,
+        strict: false,
+        prefer_webm: false,
+        loop_count: None,
+        lossy_levels: None,
+    },
+    Preset {
+        name: "telegram-sticker",
+        description: "Telegram贴纸: 256KB以内, 512x512, 启用webm特性时输出WebM视频贴纸",
+        target_size_kb: 256.0,
+        max_width: Some(512),
+        max_height: Some(512),
+        max_fps: Some(30.0),
+        colors: None,
+        strict: false,
+        prefer_webm: true,
+        loop_count: None,
+        lossy_levels: None,
+    },
+    Preset {
+        name: "github-readme",
+        description: "GitHub README演示: 5MB以内, 宽度不超过1280",
+        target_size_kb: 5000.0,
+        max_width: Some(1280),
+        max_height: None,
+        max_fps: Some(20.0),
+        colors: None,
+        strict: false,
+        prefer_webm: false,
+        loop_count: None,
+        lossy_levels: None,
+    },
+    Preset {
+        name: "email",
+        description: "邮件嵌入: 1MB以内, 宽度不超过600, 只播放一次, 保守的有损压缩",
+        target_size_kb: 1000.0,
+        max_width: Some(600),
+        max_height: None,
+        max_fps: Some(15.0),
+        colors: Some(128),
+        strict: false,
+        prefer_webm: false,
+        loop_count: Some(0),
+        lossy_levels: Some(&[20, 40, 60]),
+    },
+];
+
+/// 录屏的有损压缩级别，过高的级别会在文字边缘和纯色区域产生明显的噪点
+const SCREEN_LOSSY_LEVELS: [u32; 3] = [10, 20, 40];
+
+/// 按名称查找内置预设
+pub fn find(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name == name)
+}
+
+impl Preset {
+    /// 将预设应用到压缩选项上
+    pub fn apply(&self, options: &mut CompressionOptions) {
+        options.target_size_kb = self.target_size_kb;
+        options.max_width = self.max_width;
+        options.max_height = self.max_height;
+        options.max_fps = self.max_fps;
+        options.colors = self.colors;
+        options.strict = self.strict;
+        options.loop_count = self.loop_count;
+        options.lossy_levels = self.lossy_levels.unwrap_or(&DEFAULT_LOSSY_LEVELS).to_vec();
+        options.format = if self.prefer_webm && cfg!(feature = "webm") {
+            OutputFormat::Webm
+        } else {
+            OutputFormat::Gif
+        };
+    }
+}
+
+impl Profile {
+    /// 简短说明
+    pub fn description(self) -> &'static str {
+        match self {
+            Profile::Screen => "录屏: 裁掉纯色边框, 合并重复帧, 保留全部颜色且不抖动, 保守的有损压缩",
+        }
+    }
+
+    /// 将内容类型对应的参数应用到压缩选项上
+    ///
+    /// 只修改与内容相关的参数，目标大小和尺寸限制等平台参数保持不变。
+    pub fn apply(self, options: &mut CompressionOptions) {
+        match self {
+            Profile::Screen => {
+                options.autocrop = true;
+                options.dedupe_frames = true;
+                options.colors = None;
+                options.deband = false;
+                options.lossy_levels = SCREEN_LOSSY_LEVELS.to_vec();
+            }
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! 预览窗口
+//!
+//! 在同一个窗口中按原文件的时间轴并排循环播放原文件（左）和压缩结果（右），
+//! 只看文件大小无法判断抖动、色带和抽帧后的卡顿。压缩结果缩放到原尺寸，
+//! 使用最近邻插值，不会掩盖压缩带来的瑕疵。
+
+use crate::native::{self, DecodedFrame};
+use crate::quality::frame_index;
+use crate::GifError;
+use image::imageops::{self, FilterType};
+use minifb::{Key, Scale, ScaleMode, Window, WindowOptions};
+use std::time::Instant;
+
+/// 两个画面之间的间隔(像素)
+const GAP: usize = 8;
+
+/// 透明像素和间隔使用的背景色
+const BACKGROUND: [u8; 3] = [0x80, 0x80, 0x80];
+
+/// 打开预览窗口并播放，关闭窗口或按Esc后返回
+pub fn play(original: &[u8], compressed: &[u8]) -> Result<(), GifError> {
+    let original = native::decode_frames(original)?;
+    let mut compressed = native::decode_frames(compressed)?;
+    let (width, height) = original[0].image.dimensions();
+    for frame in &mut compressed {
+        if frame.image.dimensions() != (width, height) {
+            frame.image = imageops::resize(&frame.image, width, height, FilterType::Nearest);
+        }
+    }
+
+    let (width, height) = (width as usize, height as usize);
+    let buffer_width = width * 2 + GAP;
+    let mut buffer = vec![pack(BACKGROUND); buffer_width * height];
+    let mut window = Window::new(
+        "原文件 | 压缩结果 (Esc关闭)",
+        buffer_width,
+        height,
+        WindowOptions {
+            resize: true,
+            scale: Scale::FitScreen,
+            scale_mode: ScaleMode::AspectRatioStretch,
+            ..WindowOptions::default()
+        },
+    )
+    .map_err(preview_error)?;
+    window.set_target_fps(100);
+
+    let duration = original.iter().map(|frame| frame.delay as u64).sum::<u64>().max(1);
+    let start = Instant::now();
+    let mut shown = None;
+    while window.is_open() && !window.is_key_down(Key::Escape) {
+        let time = (start.elapsed().as_millis() / 10) as u64 % duration;
+        let current = (frame_index(&original, time), frame_index(&compressed, time));
+        if shown != Some(current) {
+            draw(&mut buffer, buffer_width, 0, &original[current.0]);
+            draw(&mut buffer, buffer_width, width + GAP, &compressed[current.1]);
+            shown = Some(current);
+        }
+        window.update_with_buffer(&buffer, buffer_width, height).map_err(preview_error)?;
+    }
+    Ok(())
+}
+
+/// 把一帧画到缓冲区中横坐标 `left` 开始的位置，透明像素与背景色混合
+fn draw(buffer: &mut [u32], buffer_width: usize, left: usize, frame: &DecodedFrame) {
+    let width = frame.image.width() as usize;
+    for (i, pixel) in frame.image.pixels().enumerate() {
+        let alpha = pixel[3] as u32;
+        let blend = |channel: usize| {
+            ((pixel[channel] as u32 * alpha + BACKGROUND[channel] as u32 * (255 - alpha)) / 255) as u8
+        };
+        buffer[(i / width) * buffer_width + left + i % width] = pack([blend(0), blend(1), blend(2)]);
+    }
+}
+
+/// minifb的像素格式 0RGB
+fn pack([r, g, b]: [u8; 3]) -> u32 {
+    ((r as u32) << 16) | ((g as u32) << 8) | b as u32
+}
+
+fn preview_error(e: minifb::Error) -> GifError {
+    GifError::Preview { message: e.to_string() }
+}
@@ -0,0 +1,71 @@
+//! 压缩进度事件
+
+use serde::Serialize;
+
+/// 压缩过程中产生的进度事件
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case", rename_all_fields = "kebab-case")]
+pub enum ProgressEvent {
+    /// 开始压缩
+    Started { original_size_kb: f64 },
+    /// 基础优化完成
+    BaseOptimized { size_kb: f64 },
+    /// 生成了一个候选结果，lossy为0表示未使用有损压缩，scale为1表示原尺寸
+    Candidate { stage: Stage, skip: usize, lossy: u32, colors: u16, scale: f64, size_kb: f64 },
+    /// 候选在压缩完成前被终止
+    CandidateStopped { stage: Stage, skip: usize, lossy: u32, colors: u16, scale: f64, reason: StopReason },
+    /// 候选结果被写入输出文件，之后的阶段找到更好的结果时会再次替换
+    Selected { stage: Stage, skip: usize, lossy: u32, colors: u16, scale: f64, size_kb: f64 },
+    /// 压缩完成
+    Finished { size_kb: f64 },
+}
+
+/// 产生候选结果的搜索阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    /// 保留所有帧的基础优化
+    Base,
+    /// 按抽帧策略并行搜索lossy级别
+    Grid,
+    /// 在选中结果附近的细搜索
+    Fine,
+    /// 仍超出目标时减少颜色数、提高lossy级别、缩小尺寸
+    Refine,
+    /// 按 `--prefer` 指定的顺序搜索
+    Prefer,
+    /// 保留所有帧时的搜索
+    KeepAll,
+    /// 按 `--optimizer` 在联合空间中搜索
+    Optimizer,
+    /// 纯Rust后端的搜索
+    Native,
+}
+
+impl Stage {
+    /// 阶段名称
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Base => "基础优化",
+            Stage::Grid => "网格搜索",
+            Stage::Fine => "细搜索",
+            Stage::Refine => "降级搜索",
+            Stage::Prefer => "按优先级的搜索",
+            Stage::KeepAll => "保留所有帧的搜索",
+            Stage::Optimizer => "联合搜索",
+            Stage::Native => "纯Rust后端的搜索",
+        }
+    }
+}
+
+/// 候选被提前终止的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StopReason {
+    /// 输出已经超过当时可能被选中的大小
+    TooLarge,
+    /// 其他候选已经达到目标，不再需要这个结果
+    Cancelled,
+    /// 超出时间限制
+    TimedOut,
+}
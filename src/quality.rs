@@ -0,0 +1,81 @@
+//! 压缩结果的画质评估
+//!
+//! 把压缩结果的每一帧与原文件中同一时刻显示的帧比较，计算整体的PSNR。
+//! 降低颜色数、有损压缩和缩小尺寸会降低评分；抽帧的影响单独用保留的帧数表示，
+//! 否则运动较多的内容抽帧后评分总是接近0。适合在接受结果之前做粗略判断。
+
+use crate::native::{self, DecodedFrame};
+use crate::GifError;
+use image::imageops::{self, FilterType};
+use serde::Serialize;
+use std::borrow::Cow;
+
+/// 评分为0时的PSNR(dB)
+const MIN_PSNR: f64 = 15.0;
+
+/// 评分为100时的PSNR(dB)，更高的差异肉眼基本看不出
+const MAX_PSNR: f64 = 40.0;
+
+/// 压缩结果相对原文件的画质
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Quality {
+    /// 峰值信噪比(dB)，两者完全相同时为无穷大
+    pub psnr: f64,
+    /// 0-100的画质评分，越高越接近原文件
+    pub score: f64,
+    /// 压缩结果的帧数
+    pub frames: usize,
+    /// 原文件的帧数
+    pub original_frames: usize,
+}
+
+/// 比较原文件和压缩结果
+///
+/// 尺寸不同时先把压缩结果缩放到原尺寸；透明像素按黑色背景计算。
+pub fn compare(original: &[u8], compressed: &[u8]) -> Result<Quality, GifError> {
+    let original = native::decode_frames(original)?;
+    let compressed = native::decode_frames(compressed)?;
+    let (width, height) = original[0].image.dimensions();
+
+    let mut total_error = 0.0;
+    let mut samples = 0u64;
+    let mut time = 0u64;
+    for frame in &compressed {
+        let image = if frame.image.dimensions() == (width, height) {
+            Cow::Borrowed(&frame.image)
+        } else {
+            Cow::Owned(imageops::resize(&frame.image, width, height, FilterType::Triangle))
+        };
+        let shown = &original[frame_index(&original, time)].image;
+        for (a, b) in shown.pixels().zip(image.pixels()) {
+            for channel in 0..3 {
+                let diff = premultiplied(a.0, channel) - premultiplied(b.0, channel);
+                total_error += diff * diff;
+            }
+        }
+        samples += width as u64 * height as u64 * 3;
+        time += frame.delay as u64;
+    }
+
+    let mse = total_error / samples.max(1) as f64;
+    let psnr = if mse == 0.0 { f64::INFINITY } else { 10.0 * (255.0 * 255.0 / mse).log10() };
+    let score = ((psnr - MIN_PSNR) / (MAX_PSNR - MIN_PSNR) * 100.0).clamp(0.0, 100.0);
+    Ok(Quality { psnr, score, frames: compressed.len(), original_frames: original.len() })
+}
+
+/// 在时刻 `time`(1/100秒) 显示的帧的序号，超出总时长时为最后一帧
+pub(crate) fn frame_index(frames: &[DecodedFrame], time: u64) -> usize {
+    let mut end = 0u64;
+    for (i, frame) in frames.iter().enumerate() {
+        end += frame.delay as u64;
+        if end > time {
+            return i;
+        }
+    }
+    frames.len().saturating_sub(1)
+}
+
+fn premultiplied(pixel: [u8; 4], channel: usize) -> f64 {
+    pixel[channel] as f64 * pixel[3] as f64 / 255.0
+}
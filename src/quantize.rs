@@ -0,0 +1,407 @@
+//! 颜色量化
+//!
+//! 纯Rust后端的调色板生成算法，通过 `--quantizer` 选择。中位切分和k-means的
+//! 聚类、求平均以及最近颜色查找都在 `--color-space` 指定的颜色空间中进行。
+
+use crate::options::{ColorSpace, CompressionOptions, Quantizer, SAMPLE_FAC_RANGE};
+use color_quant::NeuQuant;
+use image::RgbaImage;
+use std::cell::RefCell;
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
+
+/// 中位切分和k-means训练时最多使用的采样像素数
+const MAX_SAMPLES: usize = 65536;
+
+/// k-means最多迭代次数
+const KMEANS_ITERATIONS: usize = 8;
+
+/// 4x4 Bayer有序抖动矩阵
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// 去色带时视为平滑渐变的邻域通道差异上限
+const SMOOTH_RANGE: u8 = 16;
+
+/// 工作颜色空间中的颜色
+type Color = [f32; 3];
+
+/// 训练好的调色板
+pub(crate) enum ColorMap {
+    NeuQuant(NeuQuant),
+    /// 固定的调色板，在工作颜色空间中按最近颜色映射，结果按颜色缓存
+    Palette {
+        space: ColorSpace,
+        rgb: Vec<[u8; 3]>,
+        colors: Vec<Color>,
+        cache: RefCell<HashMap<[u8; 3], usize>>,
+    },
+}
+
+impl ColorMap {
+    /// 按选项中的算法用RGBA像素训练最多 `size` 种颜色的调色板
+    pub(crate) fn train(options: &CompressionOptions, size: usize, pixels: &[u8]) -> ColorMap {
+        let space = options.color_space;
+        match options.quantizer {
+            Quantizer::NeuQuant => {
+                let sample_fac = options.sample_fac.clamp(*SAMPLE_FAC_RANGE.start(), *SAMPLE_FAC_RANGE.end()) as i32;
+                let quantizer = NeuQuant::new(sample_fac, size, pixels);
+                if space == ColorSpace::Srgb {
+                    return ColorMap::NeuQuant(quantizer);
+                }
+                // NeuQuant只能在sRGB中训练，映射改为在工作颜色空间中查找
+                let rgb = quantizer.color_map_rgb().chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect();
+                ColorMap::from_rgb(space, rgb)
+            }
+            Quantizer::MedianCut => ColorMap::from_colors(space, median_cut(size, histogram(pixels, space))),
+            Quantizer::KMeans => ColorMap::from_colors(space, kmeans(size, histogram(pixels, space))),
+        }
+    }
+
+    fn from_rgb(space: ColorSpace, rgb: Vec<[u8; 3]>) -> ColorMap {
+        let colors = rgb.iter().map(|&c| to_space(space, c)).collect();
+        ColorMap::Palette { space, rgb, colors, cache: RefCell::new(HashMap::new()) }
+    }
+
+    fn from_colors(space: ColorSpace, colors: Vec<Color>) -> ColorMap {
+        ColorMap::from_rgb(space, colors.into_iter().map(|c| from_space(space, c)).collect())
+    }
+
+    /// RGB调色板
+    pub(crate) fn color_map_rgb(&self) -> Vec<u8> {
+        match self {
+            ColorMap::NeuQuant(quantizer) => quantizer.color_map_rgb(),
+            ColorMap::Palette { rgb, .. } => rgb.concat(),
+        }
+    }
+
+    /// RGBA像素对应的调色板序号
+    pub(crate) fn index_of(&self, pixel: &[u8]) -> usize {
+        match self {
+            ColorMap::NeuQuant(quantizer) => quantizer.index_of(pixel),
+            ColorMap::Palette { space, colors, cache, .. } => {
+                let rgb = [pixel[0], pixel[1], pixel[2]];
+                *cache.borrow_mut().entry(rgb).or_insert_with(|| nearest(colors, to_space(*space, rgb)))
+            }
+        }
+    }
+}
+
+fn srgb_to_linear(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+fn linear_to_srgb(v: f32) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let v = if v <= 0.003_130_8 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+    (v * 255.0).round() as u8
+}
+
+/// sRGB转换到工作颜色空间，各空间的数值范围相近，距离可以直接比较
+fn to_space(space: ColorSpace, rgb: [u8; 3]) -> Color {
+    match space {
+        ColorSpace::Srgb => rgb.map(|v| v as f32),
+        ColorSpace::Linear => rgb.map(|v| srgb_to_linear(v) * 255.0),
+        ColorSpace::Oklab => {
+            let [r, g, b] = rgb.map(srgb_to_linear);
+            let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+            let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+            let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+            [
+                (0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s) * 255.0,
+                (1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s) * 255.0,
+                (0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s) * 255.0,
+            ]
+        }
+    }
+}
+
+/// 工作颜色空间转换回sRGB
+fn from_space(space: ColorSpace, color: Color) -> [u8; 3] {
+    match space {
+        ColorSpace::Srgb => color.map(|v| v.round().clamp(0.0, 255.0) as u8),
+        ColorSpace::Linear => color.map(|v| linear_to_srgb(v / 255.0)),
+        ColorSpace::Oklab => {
+            let [lightness, a, b] = color.map(|v| v / 255.0);
+            let l = (lightness + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+            let m = (lightness - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+            let s = (lightness - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+            [
+                4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+                -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+                -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+            ]
+            .map(linear_to_srgb)
+        }
+    }
+}
+
+fn distance(a: Color, b: Color) -> f32 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+fn nearest(colors: &[Color], color: Color) -> usize {
+    (0..colors.len())
+        .min_by(|&i, &j| distance(colors[i], color).total_cmp(&distance(colors[j], color)))
+        .unwrap_or(0)
+}
+
+/// 不透明像素的颜色直方图，像素过多时等间隔采样
+fn histogram(pixels: &[u8], space: ColorSpace) -> Vec<(Color, u32)> {
+    let count = pixels.len() / 4;
+    let step = count.div_ceil(MAX_SAMPLES).max(1);
+    let mut counts: HashMap<[u8; 3], u32> = HashMap::new();
+    for p in pixels.chunks_exact(4).step_by(step).filter(|p| p[3] >= 128) {
+        *counts.entry([p[0], p[1], p[2]]).or_insert(0) += 1;
+    }
+    let mut histogram: Vec<_> = counts.into_iter().collect();
+    // 按出现次数和颜色排序，保证结果与哈希表顺序无关
+    histogram.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    histogram.into_iter().map(|(rgb, count)| (to_space(space, rgb), count)).collect()
+}
+
+/// 逐通道比较颜色，用于确定的排序
+fn compare(a: &Color, b: &Color) -> Ordering {
+    a.iter().zip(b).fold(Ordering::Equal, |order, (x, y)| order.then(x.total_cmp(y)))
+}
+
+/// 按出现次数加权的平均色
+fn mean(colors: &[(Color, u32)]) -> Color {
+    let total: f64 = colors.iter().map(|(_, count)| *count as f64).sum();
+    [0, 1, 2].map(|c| (colors.iter().map(|(color, count)| color[c] as f64 * *count as f64).sum::<f64>() / total) as f32)
+}
+
+/// 颜色范围最大的通道及其范围
+fn widest_channel(colors: &[(Color, u32)]) -> (usize, f32) {
+    (0..3)
+        .map(|c| {
+            let min = colors.iter().map(|(color, _)| color[c]).fold(f32::INFINITY, f32::min);
+            let max = colors.iter().map(|(color, _)| color[c]).fold(f32::NEG_INFINITY, f32::max);
+            (c, max - min)
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)))
+        .unwrap_or((0, 0.0))
+}
+
+/// 中位切分生成调色板
+///
+/// 反复选出颜色范围最大的盒子，沿范围最大的通道在加权中位数处一分为二，
+/// 每个盒子取加权平均色。不依赖随机数，相同输入总是得到相同的调色板。
+fn median_cut(size: usize, histogram: Vec<(Color, u32)>) -> Vec<Color> {
+    if histogram.is_empty() {
+        return vec![[0.0; 3]];
+    }
+
+    let mut boxes = vec![histogram];
+    while boxes.len() < size {
+        let Some((index, channel)) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(i, colors)| (i, widest_channel(colors)))
+            .max_by(|a, b| a.1.1.total_cmp(&b.1.1).then(b.0.cmp(&a.0)))
+            .map(|(i, (channel, _))| (i, channel))
+        else {
+            break;
+        };
+
+        let mut colors = boxes.swap_remove(index);
+        colors.sort_unstable_by(|a, b| a.0[channel].total_cmp(&b.0[channel]).then_with(|| compare(&a.0, &b.0)));
+        let half = colors.iter().map(|(_, count)| *count as u64).sum::<u64>() / 2;
+        let mut seen = 0;
+        let split = colors
+            .iter()
+            .position(|(_, count)| {
+                seen += *count as u64;
+                seen > half
+            })
+            .unwrap_or(0)
+            .clamp(1, colors.len() - 1);
+        let upper = colors.split_off(split);
+        boxes.push(colors);
+        boxes.push(upper);
+    }
+
+    boxes.iter().map(|colors| mean(colors)).collect()
+}
+
+/// k-means聚类生成调色板
+///
+/// 颜色不超过 `size` 种时原样保留，品牌色不会偏色；否则从出现最多的颜色开始
+/// 依次选取离已选颜色最远的颜色作为初始中心，再按出现次数加权迭代。
+fn kmeans(size: usize, histogram: Vec<(Color, u32)>) -> Vec<Color> {
+    if histogram.len() <= size {
+        let mut colors: Vec<Color> = histogram.into_iter().map(|(color, _)| color).collect();
+        if colors.is_empty() {
+            colors.push([0.0; 3]);
+        }
+        return colors;
+    }
+
+    let mut centers = vec![histogram[0].0];
+    let mut nearest_distance: Vec<f32> = histogram.iter().map(|(color, _)| distance(*color, centers[0])).collect();
+    while centers.len() < size {
+        let farthest = (0..nearest_distance.len())
+            .max_by(|&i, &j| nearest_distance[i].total_cmp(&nearest_distance[j]).then(Reverse(i).cmp(&Reverse(j))))
+            .unwrap_or(0);
+        let center = histogram[farthest].0;
+        centers.push(center);
+        for (d, (color, _)) in nearest_distance.iter_mut().zip(&histogram) {
+            *d = d.min(distance(*color, center));
+        }
+    }
+
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut clusters: Vec<Vec<(Color, u32)>> = vec![Vec::new(); centers.len()];
+        for &(color, count) in &histogram {
+            clusters[nearest(&centers, color)].push((color, count));
+        }
+
+        let mut changed = false;
+        for (center, cluster) in centers.iter_mut().zip(&clusters) {
+            if cluster.is_empty() {
+                continue;
+            }
+            let updated = mean(cluster);
+            changed |= updated != *center;
+            *center = updated;
+        }
+        if !changed {
+            break;
+        }
+    }
+    centers
+}
+
+/// 标记平滑渐变中的像素：相距2像素的邻居与它的差异不为0且不超过 [`SMOOTH_RANGE`]
+///
+/// 完全平坦的区域不抖动，避免无谓地增加文件大小。
+pub(crate) fn smooth_mask(image: &RgbaImage) -> Vec<bool> {
+    let (width, height) = image.dimensions();
+    image
+        .enumerate_pixels()
+        .map(|(x, y, pixel)| {
+            let neighbors = [
+                (x.saturating_sub(2), y),
+                ((x + 2).min(width - 1), y),
+                (x, y.saturating_sub(2)),
+                (x, (y + 2).min(height - 1)),
+            ];
+            let range = neighbors
+                .iter()
+                .flat_map(|&(nx, ny)| pixel.0.iter().zip(image.get_pixel(nx, ny).0).map(|(a, b)| a.abs_diff(b)))
+                .max()
+                .unwrap_or(0);
+            (1..=SMOOTH_RANGE).contains(&range)
+        })
+        .collect()
+}
+
+/// 按有序抖动矩阵偏移像素，偏移幅度与调色板的平均颜色间距相当
+pub(crate) fn dither(pixel: &[u8], x: u32, y: u32, colors: usize) -> [u8; 4] {
+    let step = 256.0 / (colors as f32).cbrt();
+    let offset = ((BAYER[(y % 4) as usize][(x % 4) as usize] as f32 + 0.5) / 16.0 - 0.5) * step;
+    [
+        (pixel[0] as f32 + offset).round().clamp(0.0, 255.0) as u8,
+        (pixel[1] as f32 + offset).round().clamp(0.0, 255.0) as u8,
+        (pixel[2] as f32 + offset).round().clamp(0.0, 255.0) as u8,
+        pixel[3],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(quantizer: Quantizer, color_space: ColorSpace) -> CompressionOptions {
+        CompressionOptions { quantizer, color_space, ..CompressionOptions::default() }
+    }
+
+    /// 每种颜色重复 `count` 次的不透明RGBA像素
+    fn pixels(colors: &[[u8; 3]], count: usize) -> Vec<u8> {
+        colors.iter().flat_map(|&[r, g, b]| [r, g, b, 255].repeat(count)).collect()
+    }
+
+    fn palette(map: &ColorMap) -> Vec<[u8; 3]> {
+        map.color_map_rgb().chunks_exact(3).map(|c| [c[0], c[1], c[2]]).collect()
+    }
+
+    const BRAND: [[u8; 3]; 5] = [[255, 0, 0], [0, 120, 215], [250, 250, 250], [30, 30, 30], [255, 184, 28]];
+
+    #[test]
+    fn kmeans_keeps_few_colors_exactly() {
+        let pixels = pixels(&BRAND, 10);
+        let map = ColorMap::train(&options(Quantizer::KMeans, ColorSpace::Srgb), 8, &pixels);
+        let palette = palette(&map);
+        let mut sorted = palette.clone();
+        sorted.sort_unstable();
+        let mut expected = BRAND.to_vec();
+        expected.sort_unstable();
+        assert_eq!(sorted, expected);
+        for color in BRAND {
+            assert_eq!(palette[map.index_of(&[color[0], color[1], color[2], 255])], color);
+        }
+    }
+
+    #[test]
+    fn kmeans_limits_palette_size() {
+        let colors: Vec<[u8; 3]> = (0..64).map(|i| [i * 4, 255 - i * 4, i * 2]).collect();
+        let map = ColorMap::train(&options(Quantizer::KMeans, ColorSpace::Srgb), 8, &pixels(&colors, 3));
+        assert_eq!(palette(&map).len(), 8);
+    }
+
+    #[test]
+    fn median_cut_is_deterministic() {
+        let colors: Vec<[u8; 3]> = (0..200u32).map(|i| [(i * 37 % 256) as u8, (i * 91 % 256) as u8, (i * 13 % 256) as u8]).collect();
+        let forward = pixels(&colors, 2);
+        let mut reversed = colors.clone();
+        reversed.reverse();
+        let options = options(Quantizer::MedianCut, ColorSpace::Srgb);
+
+        let first = palette(&ColorMap::train(&options, 16, &forward));
+        assert_eq!(first, palette(&ColorMap::train(&options, 16, &forward)));
+        // 像素顺序不同但直方图相同时调色板也相同
+        assert_eq!(first, palette(&ColorMap::train(&options, 16, &pixels(&reversed, 2))));
+    }
+
+    #[test]
+    fn median_cut_limits_palette_size() {
+        let colors: Vec<[u8; 3]> = (0..1000u32).map(|i| [(i % 256) as u8, (i / 4 % 256) as u8, (i * 7 % 256) as u8]).collect();
+        let many = pixels(&colors, 1);
+        let options = options(Quantizer::MedianCut, ColorSpace::Srgb);
+        for size in [1, 2, 16, 255, 256] {
+            assert_eq!(palette(&ColorMap::train(&options, size, &many)).len(), size);
+        }
+
+        // 颜色少于调色板大小时不会凭空增加颜色
+        let few = pixels(&BRAND, 4);
+        assert_eq!(palette(&ColorMap::train(&options, 256, &few)).len(), BRAND.len());
+        // 没有不透明像素时仍然有一种颜色
+        assert_eq!(palette(&ColorMap::train(&options, 256, &[0, 0, 0, 0])).len(), 1);
+    }
+
+    #[test]
+    fn color_spaces_round_trip() {
+        let levels: Vec<u8> = (0..=255).step_by(15).chain([1, 254]).collect();
+        for space in [ColorSpace::Srgb, ColorSpace::Linear, ColorSpace::Oklab] {
+            for &r in &levels {
+                for &g in &levels {
+                    for &b in &levels {
+                        assert_eq!(from_space(space, to_space(space, [r, g, b])), [r, g, b], "{:?}", space);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn oklab_of_gray_is_neutral() {
+        let [lightness, a, b] = to_space(ColorSpace::Oklab, [255, 255, 255]);
+        assert!((lightness - 255.0).abs() < 0.1);
+        assert!(a.abs() < 0.1 && b.abs() < 0.1);
+        let [_, a, b] = to_space(ColorSpace::Oklab, [128, 128, 128]);
+        assert!(a.abs() < 0.1 && b.abs() < 0.1);
+        assert_eq!(to_space(ColorSpace::Oklab, [0, 0, 0]), [0.0; 3]);
+        assert_eq!(to_space(ColorSpace::Linear, [255, 255, 255]), [255.0; 3]);
+    }
+}
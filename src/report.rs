@@ -0,0 +1,40 @@
+//! 压缩报告
+
+use crate::Strategy;
+use crate::options::CompressionOptions;
+use serde::{Deserialize, Serialize, Serializer};
+use std::path::{Path, PathBuf};
+
+/// 一次压缩的结果
+///
+/// `options` 的字段与配置文件相同，可以直接保存为参数文件复用。
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CompressionReport {
+    /// 输入文件
+    #[serde(serialize_with = "lossy_path")]
+    pub input: PathBuf,
+    /// 输出文件
+    #[serde(serialize_with = "lossy_path")]
+    pub output: PathBuf,
+    /// 原始大小(KB)
+    pub original_size_kb: f64,
+    /// 压缩后大小(KB)
+    pub size_kb: f64,
+    /// 是否达到目标大小
+    pub target_reached: bool,
+    /// 选中的抽帧策略，None表示保留所有帧
+    pub strategy: Option<Strategy>,
+    /// 选中的lossy级别，None表示未使用有损压缩
+    pub lossy: Option<u32>,
+    /// 是否因为超出时间限制提前结束搜索，此时输出的是目前为止最好的结果
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    /// 实际使用的压缩选项
+    pub options: CompressionOptions,
+}
+
+/// 把路径写成字符串，不是UTF-8的部分替换为U+FFFD，报告不会因为文件名而写不出来
+fn lossy_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
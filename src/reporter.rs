@@ -0,0 +1,89 @@
+//! 压缩过程的输出
+//!
+//! 压缩流程中的所有日志和进度事件都通过 [`Reporter`] 输出，
+//! 库的调用方可以选择内置实现或者自己实现该trait。
+
+use crate::progress::ProgressEvent;
+use serde_json::json;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// 接收日志和进度事件，可能在多个工作线程中同时调用
+pub trait Reporter: Send + Sync {
+    /// 一行日志
+    fn log(&self, message: &str);
+
+    /// 进度事件，默认忽略
+    fn event(&self, event: &ProgressEvent) {
+        let _ = event;
+    }
+}
+
+/// 在线程间共享的输出
+pub type SharedReporter = Arc<dyn Reporter>;
+
+/// 输出到标准输出的日志，忽略进度事件
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConsoleReporter;
+
+impl Reporter for ConsoleReporter {
+    fn log(&self, message: &str) {
+        println!("{}", message);
+    }
+}
+
+/// 每行输出一个JSON对象，日志为 `{"type": "log", "message": ...}`，
+/// 进度事件以 `type` 字段区分，例如 `{"type": "candidate", "skip": 2, ...}`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonReporter;
+
+impl Reporter for JsonReporter {
+    fn log(&self, message: &str) {
+        println!("{}", json!({ "type": "log", "message": message }));
+    }
+
+    fn event(&self, event: &ProgressEvent) {
+        if let Ok(line) = serde_json::to_string(event) {
+            println!("{}", line);
+        }
+    }
+}
+
+/// 不输出任何内容
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SilentReporter;
+
+impl Reporter for SilentReporter {
+    fn log(&self, _message: &str) {}
+}
+
+/// 内置的输出方式，用于命令行参数 `--reporter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReporterKind {
+    Console,
+    Json,
+    Silent,
+}
+
+impl ReporterKind {
+    pub fn build(self) -> SharedReporter {
+        match self {
+            ReporterKind::Console => Arc::new(ConsoleReporter),
+            ReporterKind::Json => Arc::new(JsonReporter),
+            ReporterKind::Silent => Arc::new(SilentReporter),
+        }
+    }
+}
+
+impl FromStr for ReporterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "console" => Ok(ReporterKind::Console),
+            "json" => Ok(ReporterKind::Json),
+            "silent" => Ok(ReporterKind::Silent),
+            _ => Err(format!("未知的输出方式: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! 文字区域检测
+//!
+//! 有损压缩最先破坏小号的界面文字。这里用简单的笔画特征找出文字密集的区域，
+//! 作为保护区域降低该区域的压缩强度。
+
+use crate::options::Region;
+use crate::reporter::Reporter;
+use image::RgbaImage;
+
+/// 检测时划分的方块边长
+const TILE_SIZE: u32 = 16;
+
+/// 相邻像素亮度差超过此值视为边缘
+const EDGE_THRESHOLD: u8 = 48;
+
+/// 文字方块的边缘像素比例范围，过低是平坦区域，过高是噪点或纹理
+const EDGE_DENSITY: (f64, f64) = (0.08, 0.5);
+
+/// 文字方块中接近最亮或最暗的像素比例下限，文字通常只有前景和背景两种颜色
+const BIMODAL_RATIO: f64 = 0.7;
+
+/// 至少检测到这么多文字方块才认为存在文字区域
+const MIN_TEXT_TILES: usize = 3;
+
+fn luma(p: &image::Rgba<u8>) -> u8 {
+    ((p[0] as u32 * 299 + p[1] as u32 * 587 + p[2] as u32 * 114) / 1000) as u8
+}
+
+/// 方块是否像文字：边缘密度适中且亮度集中在两端
+fn is_text_tile(image: &RgbaImage, x0: u32, y0: u32, w: u32, h: u32) -> bool {
+    let mut values = Vec::with_capacity((w * h) as usize);
+    let mut edges = 0;
+    for y in y0..y0 + h {
+        for x in x0..x0 + w {
+            let value = luma(image.get_pixel(x, y));
+            if x > x0 && value.abs_diff(luma(image.get_pixel(x - 1, y))) > EDGE_THRESHOLD {
+                edges += 1;
+            }
+            if y > y0 && value.abs_diff(luma(image.get_pixel(x, y - 1))) > EDGE_THRESHOLD {
+                edges += 1;
+            }
+            values.push(value);
+        }
+    }
+
+    let density = edges as f64 / values.len() as f64;
+    if density < EDGE_DENSITY.0 || density > EDGE_DENSITY.1 {
+        return false;
+    }
+
+    let min = *values.iter().min().unwrap_or(&0);
+    let max = *values.iter().max().unwrap_or(&0);
+    let band = (max - min) / 4;
+    let extreme = values
+        .iter()
+        .filter(|&&v| v - min <= band || max - v <= band)
+        .count();
+    extreme as f64 >= values.len() as f64 * BIMODAL_RATIO
+}
+
+/// 返回包含所有文字方块的最小矩形，没有文字时返回None
+pub(crate) fn detect_text_region(image: &RgbaImage) -> Option<Region> {
+    let (width, height) = image.dimensions();
+    let mut tiles = 0;
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+
+    for y in (0..height).step_by(TILE_SIZE as usize) {
+        for x in (0..width).step_by(TILE_SIZE as usize) {
+            let w = TILE_SIZE.min(width - x);
+            let h = TILE_SIZE.min(height - y);
+            if is_text_tile(image, x, y, w, h) {
+                tiles += 1;
+                left = left.min(x);
+                top = top.min(y);
+                right = right.max(x + w);
+                bottom = bottom.max(y + h);
+            }
+        }
+    }
+
+    (tiles >= MIN_TEXT_TILES).then_some(Region { x: left, y: top, width: right - left, height: bottom - top })
+}
+
+/// 输出检测结果
+pub(crate) fn log_detected(region: Option<Region>, reporter: &dyn Reporter) {
+    match region {
+        Some(region) => reporter.log(&format!("检测到文字区域 {}，降低该区域的压缩强度", region)),
+        None => reporter.log("未检测到文字区域"),
+    }
+}
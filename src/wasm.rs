@@ -0,0 +1,26 @@
+//! WebAssembly绑定
+//!
+//! 浏览器中无法启动子进程也没有文件系统，因此固定使用纯Rust后端输出GIF。
+
+use crate::compress_bytes_with_reporter;
+use crate::config::Config;
+use crate::options::{Backend, CompressionOptions, OutputFormat};
+use crate::reporter::SilentReporter;
+use std::sync::Arc;
+use wasm_bindgen::prelude::*;
+
+/// 压缩内存中的GIF，`options` 的字段与配置文件相同，可以为undefined
+#[wasm_bindgen]
+pub fn compress(data: &[u8], options: JsValue) -> Result<Vec<u8>, JsValue> {
+    let mut compression_options = CompressionOptions::default();
+    if !options.is_undefined() && !options.is_null() {
+        let config: Config = serde_wasm_bindgen::from_value(options)?;
+        config
+            .apply(&mut compression_options)
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    }
+
+    compression_options.backend = Backend::Native;
+    compression_options.format = OutputFormat::Gif;
+    compress_bytes_with_reporter(data, &compression_options, Arc::new(SilentReporter)).map_err(|e| JsValue::from_str(&e.to_string()))
+}
@@ -0,0 +1,99 @@
+use crate::{new_temp_file, GifError, WithPath};
+use crate::cancel::run_command;
+use crate::options::CompressionOptions;
+use crate::reporter::Reporter;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// 依次尝试的VP9质量参数，数值越大文件越小
+const CRF_LEVELS: [u32; 7] = [30, 36, 42, 48, 54, 58, 63];
+
+/// 构建ffmpeg的缩放参数
+fn scale_filter(options: &CompressionOptions) -> Option<String> {
+    match (options.max_width, options.max_height) {
+        (Some(w), Some(h)) => Some(format!("scale={}:{}:force_original_aspect_ratio=decrease", w, h)),
+        (Some(w), None) => Some(format!("scale='min({},iw)':-2", w)),
+        (None, Some(h)) => Some(format!("scale=-2:'min({},ih)'", h)),
+        (None, None) => None,
+    }
+}
+
+/// 使用ffmpeg将GIF转换为VP9编码的WebM，逐步提高压缩率直到达到目标大小
+///
+/// 每一遍先编码到工作目录中的临时文件，比目前最好的结果更小时才写入输出文件；
+/// 时间用完时保留目前最好的结果。
+pub fn optimize_webm<P: AsRef<Path>, Q: AsRef<Path>>(
+    input_path: P,
+    output_path: Q,
+    options: &CompressionOptions,
+    reporter: &dyn Reporter,
+) -> Result<(), GifError> {
+    // 检查ffmpeg是否存在
+    if Command::new("ffmpeg").arg("-version").output().is_err() {
+        return Err(GifError::FfmpegNotFound);
+    }
+    
+    let input_str = input_path.as_ref().to_string_lossy().to_string();
+    let output_path = output_path.as_ref();
+    
+    let mut best_size: Option<f64> = None;
+    for crf in CRF_LEVELS {
+        let encoded = new_temp_file(options.temp_dir())?;
+        let mut args = vec![
+            "-y".to_string(),
+            "-loglevel".to_string(), "error".to_string(),
+            "-i".to_string(), input_str.clone(),
+            "-an".to_string(),                        // 去掉音轨
+            "-c:v".to_string(), "libvpx-vp9".to_string(),
+            "-pix_fmt".to_string(), "yuva420p".to_string(), // 保留透明度
+            "-b:v".to_string(), "0".to_string(),
+            "-crf".to_string(), crf.to_string(),
+        ];
+        if let Some(filter) = scale_filter(options) {
+            args.push("-vf".to_string());
+            args.push(filter);
+        }
+        if let Some(max_fps) = options.max_fps {
+            args.push("-r".to_string());
+            args.push(max_fps.to_string());
+        }
+        // 临时文件没有扩展名，需要指定输出格式
+        args.extend(["-f".to_string(), "webm".to_string()]);
+        args.push(encoded.path().to_string_lossy().to_string());
+        
+        let output = match run_command(Command::new("ffmpeg").args(&args), &options.cancel) {
+            Err(GifError::TimedOut) if best_size.is_some() => break,
+            // 失败或取消时不保留输出文件
+            Err(e) => {
+                let _ = fs::remove_file(output_path);
+                return Err(e);
+            }
+            Ok(output) => output,
+        };
+        if !output.status.success() {
+            let _ = fs::remove_file(output_path);
+            return Err(GifError::command_failed("ffmpeg", &args, &output));
+        }
+        
+        let size = fs::metadata(encoded.path()).with_path(encoded.path())?.len() as f64 / 1024.0;
+        reporter.log(&format!("WebM crf={} 后大小: {:.2} KB", crf, size));
+        if best_size.is_none_or(|best| size < best) {
+            fs::copy(encoded.path(), output_path).with_path(output_path)?;
+            best_size = Some(size);
+        }
+        if size <= options.target_size_kb {
+            reporter.log(&format!("完成! 最终大小: {:.2} KB", size));
+            return Ok(());
+        }
+    }
+    
+    let size = best_size.ok_or_else(|| options.cancel.no_results())?;
+    if options.strict {
+        let _ = fs::remove_file(output_path);
+        return Err(GifError::SizeLimitExceeded { size_kb: size, limit_kb: options.target_size_kb });
+    }
+    reporter.log(&format!("\n无法达到目标大小 {} KB。", options.target_size_kb));
+    reporter.log(&format!("最接近的大小是 {:.2} KB，已保存到输出文件。", size));
+    Ok(())
+}